@@ -0,0 +1,35 @@
+use crate::CPU::CHIP8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum AccessKind {
+	Read,
+	Write
+}
+
+// The memory an instruction read or wrote as data. Fetching instructions doesn't count
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct MemoryAccess {
+	pub kind: AccessKind,
+	pub start: u16,
+	pub len: u16
+}
+
+impl MemoryAccess {
+	// Whether any of the accessed bytes are in start to end, both included
+	pub fn overlaps(&self, start: u16, end: u16) -> bool {
+		let last = self.start as u32 + self.len as u32; // one past the last byte
+		self.len > 0 && self.start <= end && last > start as u32
+	}
+}
+
+impl CHIP8 {
+	// The memory the last instruction read or wrote, if any. Sprites, FX33, FX55, FX65, XO-CHIP's ranges and
+	// audio patterns access memory
+	pub fn last_access(&self) -> Option<MemoryAccess> {
+		self.last_access
+	}
+
+	pub(crate) fn record_access(&mut self, kind: AccessKind, start: usize, len: usize) {
+		self.last_access = Some(MemoryAccess { kind, start: start as u16, len: len as u16 });
+	}
+}
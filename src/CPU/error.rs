@@ -0,0 +1,132 @@
+use std::{ error::Error, fmt };
+
+// What happened when an instruction was executed without problems
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StepOutcome {
+	Executed,
+	WaitingForKey, // FX0A is blocking until a key gets pressed, pc wasn't moved
+	Exited // the program ran 00FD, nothing else will be executed
+}
+
+// An instruction that couldn't be executed. The machine is left as it was before the instruction,
+// with pc still pointing at it
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ExecError {
+	UnknownOpcode { pc: u16, opcode: u16 },
+	StackOverflow { pc: u16, opcode: u16 }, // 2NNN with all 16 stack entries in use
+	StackUnderflow { pc: u16, opcode: u16 }, // 00EE with nothing on the stack
+	MemoryOutOfBounds { pc: u16, opcode: Option<u16>, address: usize } // the instruction would access memory that doesn't exist. No opcode if it couldn't be fetched
+}
+
+impl ExecError {
+	pub fn pc(&self) -> u16 {
+		match *self {
+			ExecError::UnknownOpcode { pc, .. } | ExecError::StackOverflow { pc, .. } |
+			ExecError::StackUnderflow { pc, .. } | ExecError::MemoryOutOfBounds { pc, .. } => pc
+		}
+	}
+
+	// The failing instruction's opcode. None when pc was past the end of memory, so there wasn't one
+	pub fn opcode(&self) -> Option<u16> {
+		match *self {
+			ExecError::UnknownOpcode { opcode, .. } | ExecError::StackOverflow { opcode, .. } |
+			ExecError::StackUnderflow { opcode, .. } => Some(opcode),
+			ExecError::MemoryOutOfBounds { opcode, .. } => opcode
+		}
+	}
+}
+
+impl fmt::Display for ExecError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match *self {
+			ExecError::UnknownOpcode { pc, opcode } => write!(f, "unknown opcode {:04X} at {:03X}", opcode, pc),
+			ExecError::StackOverflow { pc, opcode } => write!(f, "stack overflow by {:04X} at {:03X}", opcode, pc),
+			ExecError::StackUnderflow { pc, opcode } => write!(f, "stack underflow by {:04X} at {:03X}", opcode, pc),
+			ExecError::MemoryOutOfBounds { pc, opcode: Some(opcode), address } =>
+				write!(f, "{:04X} at {:03X} accessed memory out of bounds at {:X}", opcode, pc, address),
+			ExecError::MemoryOutOfBounds { pc, opcode: None, address } =>
+				write!(f, "---- at {:03X} accessed memory out of bounds at {:X}", pc, address)
+		}
+	}
+}
+
+impl Error for ExecError {}
+
+// What to do when an instruction fails
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ErrorAction {
+	Halt, // stop executing and report the error
+	Log, // print the error, skip the instruction and carry on
+	Ignore // skip the instruction and carry on
+}
+
+impl ErrorAction {
+	// halt, log or ignore
+	pub fn parse(name: &str) -> Option<ErrorAction> {
+		match name.trim().to_lowercase().as_str() {
+			"halt" => Some(ErrorAction::Halt),
+			"log" => Some(ErrorAction::Log),
+			"ignore" => Some(ErrorAction::Ignore),
+			_ => None
+		}
+	}
+}
+
+// The action to take for each kind of error
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ErrorPolicy {
+	pub unknown_opcode: ErrorAction,
+	pub stack_overflow: ErrorAction,
+	pub stack_underflow: ErrorAction,
+	pub memory_out_of_bounds: ErrorAction
+}
+
+impl ErrorPolicy {
+	// A comma separated list of actions, each for every kind of error or for one kind, changing the
+	// default policy: halt, or log,stack-underflow=ignore. The kinds are unknown-opcode, stack-overflow,
+	// stack-underflow and memory
+	pub fn parse(text: &str) -> Result<ErrorPolicy, String> {
+		let mut policy = ErrorPolicy::default();
+		for item in text.split(',') {
+			let (kind, action) = match item.split_once('=') {
+				Some((kind, action)) => (Some(kind.trim()), action),
+				None => (None, item)
+			};
+			let action = ErrorAction::parse(action).ok_or_else(|| format!("unknown action {}, try halt, log or ignore", action.trim()))?;
+
+			match kind {
+				None => policy = ErrorPolicy { unknown_opcode: action, stack_overflow: action, stack_underflow: action, memory_out_of_bounds: action },
+				Some("unknown-opcode") => policy.unknown_opcode = action,
+				Some("stack-overflow") => policy.stack_overflow = action,
+				Some("stack-underflow") => policy.stack_underflow = action,
+				Some("memory") => policy.memory_out_of_bounds = action,
+				Some(other) => return Err(format!("unknown kind of error {}, try unknown-opcode, stack-overflow, stack-underflow or memory", other))
+			}
+		}
+		Ok(policy)
+	}
+
+	pub fn action(&self, error: &ExecError) -> ErrorAction {
+		match error {
+			// there's no instruction to skip when pc is past the end of memory, skipping would go on forever
+			ExecError::MemoryOutOfBounds { opcode: None, .. } => ErrorAction::Halt,
+			ExecError::UnknownOpcode { .. } => self.unknown_opcode,
+			ExecError::StackOverflow { .. } => self.stack_overflow,
+			ExecError::StackUnderflow { .. } => self.stack_underflow,
+			ExecError::MemoryOutOfBounds { .. } => self.memory_out_of_bounds
+		}
+	}
+}
+
+// Unknown opcodes are often data a program never meant to run, or an extension it only uses on some
+// platforms, so they're skipped. Everything else means the program has gone off the rails
+impl Default for ErrorPolicy {
+	fn default() -> ErrorPolicy {
+		ErrorPolicy {
+			unknown_opcode: ErrorAction::Log,
+			stack_overflow: ErrorAction::Halt,
+			stack_underflow: ErrorAction::Halt,
+			memory_out_of_bounds: ErrorAction::Halt
+		}
+	}
+}
@@ -0,0 +1,160 @@
+// Every instruction of CHIP-8 and its SUPER-CHIP and XO-CHIP extensions, with its operands pulled out
+// of the opcode. x and y are register numbers, addr is a 12 bit address, byte and n are immediate values
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Instruction {
+	Clear, // 00E0
+	Return, // 00EE
+	ScrollDown { n: u8 }, // 00CN
+	ScrollUp { n: u8 }, // 00DN, XO-CHIP
+	ScrollRight, // 00FB
+	ScrollLeft, // 00FC
+	Exit, // 00FD
+	Lores, // 00FE
+	Hires, // 00FF
+	Jump { addr: u16 }, // 1NNN
+	Call { addr: u16 }, // 2NNN
+	SkipIfEqual { x: u8, byte: u8 }, // 3XNN
+	SkipIfNotEqual { x: u8, byte: u8 }, // 4XNN
+	SkipIfRegistersEqual { x: u8, y: u8 }, // 5XY0
+	SaveRange { x: u8, y: u8 }, // 5XY2, XO-CHIP
+	LoadRange { x: u8, y: u8 }, // 5XY3, XO-CHIP
+	SetImmediate { x: u8, byte: u8 }, // 6XNN
+	AddImmediate { x: u8, byte: u8 }, // 7XNN
+	Move { x: u8, y: u8 }, // 8XY0
+	Or { x: u8, y: u8 }, // 8XY1
+	And { x: u8, y: u8 }, // 8XY2
+	Xor { x: u8, y: u8 }, // 8XY3
+	Add { x: u8, y: u8 }, // 8XY4
+	Sub { x: u8, y: u8 }, // 8XY5
+	ShiftRight { x: u8, y: u8 }, // 8XY6
+	SubReversed { x: u8, y: u8 }, // 8XY7
+	ShiftLeft { x: u8, y: u8 }, // 8XYE
+	SkipIfRegistersNotEqual { x: u8, y: u8 }, // 9XY0
+	SetIndex { addr: u16 }, // ANNN
+	JumpOffset { addr: u16 }, // BNNN, the X of BXNN when the jump_vx quirk is on is the top digit of addr
+	Random { x: u8, byte: u8 }, // CXNN
+	Draw { x: u8, y: u8, n: u8 }, // DXYN
+	SkipIfKey { x: u8 }, // EX9E
+	SkipIfNotKey { x: u8 }, // EXA1
+	LongIndex, // F000 NNNN, XO-CHIP. The address is the word after the opcode
+	SelectPlanes { n: u8 }, // FN01, XO-CHIP
+	LoadAudio, // F002, XO-CHIP
+	GetDelay { x: u8 }, // FX07
+	WaitKey { x: u8 }, // FX0A
+	SetDelay { x: u8 }, // FX15
+	SetSound { x: u8 }, // FX18
+	AddIndex { x: u8 }, // FX1E
+	Font { x: u8 }, // FX29
+	BigFont { x: u8 }, // FX30
+	Bcd { x: u8 }, // FX33
+	SetPitch { x: u8 }, // FX3A, XO-CHIP
+	Save { x: u8 }, // FX55
+	Load { x: u8 }, // FX65
+	SaveFlags { x: u8 }, // FX75
+	LoadFlags { x: u8 }, // FX85
+	Unknown(u16)
+}
+
+// Splits an opcode into its instruction and operands. Doesn't depend on the platform, the extensions'
+// instructions are decoded even though other platforms can't run them
+pub fn decode(opcode: u16) -> Instruction {
+	use Instruction::*;
+
+	let x = ((opcode & 0x0F00) >> 8) as u8;
+	let y = ((opcode & 0x00F0) >> 4) as u8;
+	let n = (opcode & 0x000F) as u8;
+	let byte = (opcode & 0x00FF) as u8;
+	let addr = opcode & 0x0FFF;
+
+	match opcode & 0xF000 {
+		0x0000 => match opcode {
+			0x00E0 => Clear,
+			0x00EE => Return,
+			0x00C0 ..= 0x00CF => ScrollDown { n },
+			0x00D0 ..= 0x00DF => ScrollUp { n },
+			0x00FB => ScrollRight,
+			0x00FC => ScrollLeft,
+			0x00FD => Exit,
+			0x00FE => Lores,
+			0x00FF => Hires,
+			_ => Unknown(opcode)
+		},
+		0x1000 => Jump { addr },
+		0x2000 => Call { addr },
+		0x3000 => SkipIfEqual { x, byte },
+		0x4000 => SkipIfNotEqual { x, byte },
+		0x5000 => match n {
+			0x0 => SkipIfRegistersEqual { x, y },
+			0x2 => SaveRange { x, y },
+			0x3 => LoadRange { x, y },
+			_ => Unknown(opcode)
+		},
+		0x6000 => SetImmediate { x, byte },
+		0x7000 => AddImmediate { x, byte },
+		0x8000 => match n {
+			0x0 => Move { x, y },
+			0x1 => Or { x, y },
+			0x2 => And { x, y },
+			0x3 => Xor { x, y },
+			0x4 => Add { x, y },
+			0x5 => Sub { x, y },
+			0x6 => ShiftRight { x, y },
+			0x7 => SubReversed { x, y },
+			0xE => ShiftLeft { x, y },
+			_ => Unknown(opcode)
+		},
+		0x9000 if n == 0 => SkipIfRegistersNotEqual { x, y },
+		0xA000 => SetIndex { addr },
+		0xB000 => JumpOffset { addr },
+		0xC000 => Random { x, byte },
+		0xD000 => Draw { x, y, n },
+		0xE000 => match byte {
+			0x9E => SkipIfKey { x },
+			0xA1 => SkipIfNotKey { x },
+			_ => Unknown(opcode)
+		},
+		0xF000 => match byte {
+			0x00 if x == 0 => LongIndex,
+			0x01 => SelectPlanes { n: x },
+			0x02 if x == 0 => LoadAudio,
+			0x07 => GetDelay { x },
+			0x0A => WaitKey { x },
+			0x15 => SetDelay { x },
+			0x18 => SetSound { x },
+			0x1E => AddIndex { x },
+			0x29 => Font { x },
+			0x30 => BigFont { x },
+			0x33 => Bcd { x },
+			0x3A => SetPitch { x },
+			0x55 => Save { x },
+			0x65 => Load { x },
+			0x75 => SaveFlags { x },
+			0x85 => LoadFlags { x },
+			_ => Unknown(opcode)
+		},
+		_ => Unknown(opcode)
+	}
+}
+
+impl Instruction {
+	// How many bytes the instruction takes up in memory
+	pub fn size(&self) -> u16 {
+		match self {
+			Instruction::LongIndex => 4,
+			_ => 2
+		}
+	}
+
+	// Whether execution can carry on to the instruction after this one
+	pub fn falls_through(&self) -> bool {
+		!matches!(self, Instruction::Jump { .. } | Instruction::JumpOffset { .. } | Instruction::Return |
+						Instruction::Exit | Instruction::Unknown(_))
+	}
+
+	// Whether this is one of the instructions that skip the next one
+	pub fn is_skip(&self) -> bool {
+		matches!(self, Instruction::SkipIfEqual { .. } | Instruction::SkipIfNotEqual { .. } |
+						Instruction::SkipIfRegistersEqual { .. } | Instruction::SkipIfRegistersNotEqual { .. } |
+						Instruction::SkipIfKey { .. } | Instruction::SkipIfNotKey { .. })
+	}
+}
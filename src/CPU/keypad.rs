@@ -0,0 +1,42 @@
+use crate::CPU::CHIP8;
+
+// The 16 keys of the CHIP-8 hex keypad
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub enum Chip8Key {
+	K0, K1, K2, K3, K4, K5, K6, K7, K8, K9, KA, KB, KC, KD, KE, KF
+}
+
+const KEYS: [Chip8Key; 16] = [
+	Chip8Key::K0, Chip8Key::K1, Chip8Key::K2, Chip8Key::K3, Chip8Key::K4, Chip8Key::K5, Chip8Key::K6, Chip8Key::K7,
+	Chip8Key::K8, Chip8Key::K9, Chip8Key::KA, Chip8Key::KB, Chip8Key::KC, Chip8Key::KD, Chip8Key::KE, Chip8Key::KF
+];
+
+impl Chip8Key {
+	pub fn all() -> [Chip8Key; 16] {
+		KEYS
+	}
+
+	// The key with this value, as EX9E and FX0A see it
+	pub fn from_index(index: u8) -> Option<Chip8Key> {
+		KEYS.get(index as usize).copied()
+	}
+
+	// The key written as a single hex digit
+	pub fn from_digit(digit: char) -> Option<Chip8Key> {
+		digit.to_digit(16).and_then(|d| Chip8Key::from_index(d as u8))
+	}
+
+	pub fn index(self) -> u8 {
+		self as u8
+	}
+}
+
+impl CHIP8 {
+	pub fn set_key(&mut self, key: Chip8Key, pressed: bool) {
+		self.key[key.index() as usize] = pressed as u8;
+	}
+
+	pub fn is_key_pressed(&self, key: Chip8Key) -> bool {
+		self.key[key.index() as usize] != 0
+	}
+}
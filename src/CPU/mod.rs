@@ -1,87 +1,225 @@
 mod opcodes;
+pub mod quirks;
+pub mod platform;
+pub mod error;
+pub mod state;
+pub mod keypad;
+pub mod instruction;
+pub mod access;
 
-use crate::{ CHIP8, WINDOW_W, WINDOW_H, load_binary_file };
+use crate::{ CHIP8, decode, trace::Snapshot, database::sha1, Screen, SoundState, Quirks, Platform, ExecError, StepOutcome, ErrorPolicy, ErrorAction, WINDOW_W, WINDOW_H, HIRES_W, HIRES_H, BIG_FONT_START };
 
-use std::io;
+use std::{ fs, io, path::Path };
+
+// 600 instructions per second, about what most programs expect
+const DEFAULT_INSTRUCTIONS_PER_FRAME: u32 = 10;
 
 // Start-Up, program loading and screen updating
 impl CHIP8 {
-	pub fn new(args: Option<&[String]>) -> io::Result<CHIP8> {
-		let mut program_path = None;
-		match args {
-			Some(a) => {
-				if a.len() < 2 {
-					return Err(io::Error::new(io::ErrorKind::InvalidInput, "program path missing"));
-				} else {
-					program_path = Some(&a[1]);
-				}
-			},
-			None => ()
-		}
+	// A CHIP-8 with the program in rom loaded at 0x200. Fails if it doesn't fit in memory
+	pub fn new(rom: &[u8]) -> io::Result<CHIP8> {
+		CHIP8::with_platform(rom, Platform::Chip8)
+	}
 
+	// Same as new, but sizes the memory for the given platform before the program gets loaded
+	pub fn with_platform(rom: &[u8], platform: Platform) -> io::Result<CHIP8> {
 		let mut c = CHIP8 {
 			opcode: 0x0000, 
-			memory: [0x00u8; 4096],
+			memory: vec![0x00u8; platform.memory_size()],
 			V: [0x00; 16],
 			I: 0x0000,
 			pc: 0x0200, // Program counter starts at 512
-			gfx: [0; WINDOW_W as usize * WINDOW_H as usize],
+			gfx: [0; HIRES_W as usize * HIRES_H as usize],
+			hires: false,
+			exited: false,
+			plane: 0b01, // only the first plane, like on platforms without bitplanes
 			draw_flag: true, // Clear screen once
-			sound_flag: false,
 			delay_timer: 0x00,
 			sound_timer: 0x00,
+			pattern: [0x00; 16],
+			pitch: 64, // plays the pattern at 4000 samples per second
 			stack: [0x0000; 16],
 			sp: 0x0000,
-			key: [0x00; 16]
+			key: [0x00; 16],
+			rpl: [0x00; 16],
+			quirks: Quirks::default(),
+			platform,
+			instructions_per_frame: DEFAULT_INSTRUCTIONS_PER_FRAME,
+			frame_count: 0,
+			rom_hash: state::rom_hash(&[]),
+			rom_sha1: sha1(&[]),
+			decoded: vec![None; platform.memory_size()],
+			last_access: None,
+			tracer: None
 		};
 
 		// load fontset into memory
-		for i in 0 .. 80 {
-			c.memory[i] = crate::FONTSET[i];
-		}
+		c.memory[.. 80].copy_from_slice(&crate::FONTSET);
+		let big_font = BIG_FONT_START as usize;
+		c.memory[big_font .. big_font + 160].copy_from_slice(&crate::BIG_FONTSET);
 		
-		match program_path {
-			Some(p) => {
-				if let Err(e) = c.load_program(&p) {
-					return Err(e)
-				}
-			},
-			None => ()
-		}
-
+		c.load_program(rom)?;
 		Ok(c)
 	}
-	
-	fn load_program(&mut self, path: &str) -> io::Result<()> {
-		let file = load_binary_file(path)?;
-		
-		if 4096 - 512 < file.len() {
+
+	// Same as with_platform, with the program read from a file
+	pub fn open<P: AsRef<Path>>(path: P, platform: Platform) -> io::Result<CHIP8> {
+		CHIP8::with_platform(&fs::read(path)?, platform)
+	}
+
+	fn load_program(&mut self, file: &[u8]) -> io::Result<()> {
+		if self.memory.len() - 512 < file.len() {
 			return Err(io::Error::new(io::ErrorKind::WriteZero, "ROM too big for memory"));
 		}
 
-		for i in 0 .. file.len() {
-			self.memory[i + 512] = file[i]
-		}
+		self.memory[512 .. 512 + file.len()].copy_from_slice(file);
+		self.rom_hash = state::rom_hash(file);
+		self.rom_sha1 = sha1(file);
 
 		Ok(())
 	}
 
+	pub fn quirks(&self) -> Quirks {
+		self.quirks
+	}
+
+	// Changes how the ambiguous instructions are interpreted. Can be done at any point during execution
+	pub fn set_quirks(&mut self, quirks: Quirks) {
+		self.quirks = quirks;
+	}
+
+	pub fn platform(&self) -> Platform {
+		self.platform
+	}
+
+	// Switches to another platform, growing or shrinking the memory to its size. The program stays loaded
+	// as long as it fits
+	pub fn set_platform(&mut self, platform: Platform) {
+		self.platform = platform;
+		self.memory.resize(platform.memory_size(), 0);
+		self.decoded = vec![None; platform.memory_size()];
+	}
+
+	// The SHA-1 of the loaded program in lowercase hex, which the ROM database knows programs by
+	pub fn rom_sha1(&self) -> String {
+		self.rom_sha1.iter().map(|b| format!("{:02x}", b)).collect()
+	}
 }
 
 // Emulating
 impl CHIP8 {
 	// Emulates one cycle of the CPU
-	pub fn emulate_cycle(&mut self) {
+	pub fn emulate_cycle(&mut self) -> Result<StepOutcome, ExecError> {
+		if self.exited { return Ok(StepOutcome::Exited); }
+		self.last_access = None;
+
+		let before = self.tracer.as_ref().map(|_| Snapshot::of(self));
+
 		// Fetch opcode
+		if self.pc as usize + 1 >= self.memory.len() {
+			let error = ExecError::MemoryOutOfBounds { pc: self.pc, opcode: None, address: self.pc as usize + 1 };
+			if let Some(before) = before {
+				self.trace(&before, None, Some(&error));
+			}
+			return Err(error);
+		}
 		let opc1 = self.memory[self.pc as usize] as u16; // First byte 
 		let opc2 = self.memory[(self.pc + 1) as usize] as u16; // Second byte
 		// Merge the 2 bytes, by shifting the first by 8 and ORing the second.
 		self.opcode = opc1 << 8 | opc2;
 
-		// Decode opcode
-		self.handle_opcode();
-		
+		// Decode opcode, once for each address until the program overwrites it
+		let instruction = match self.decoded[self.pc as usize] {
+			Some(instruction) => instruction,
+			None => {
+				let instruction = decode(self.opcode);
+				self.decoded[self.pc as usize] = Some(instruction);
+				instruction
+			}
+		};
+
+		let result = self.execute(instruction);
+		if let Some(before) = before {
+			self.trace(&before, Some(instruction), result.as_ref().err());
+		}
+		result
+	}
+
+	// Forgets the decoded instructions that include any of the len bytes from start, after they're written.
+	// An instruction's opcode is 2 bytes long, so the one starting just before start is affected too
+	pub(crate) fn invalidate_decoded(&mut self, start: usize, len: usize) {
+		let first = start.saturating_sub(1);
+		let end = (start + len).min(self.decoded.len());
+		for entry in self.decoded[first .. end].iter_mut() {
+			*entry = None;
+		}
+	}
+
+	// Emulates one 60 Hz frame: runs the configured number of instructions, then ticks the timers once.
+	// Frontends call this 60 times per second of real time, headless runs can call it as fast as they like.
+	// Errors the policy says to halt on stop the frame straight away and are returned
+	pub fn run_frame(&mut self, policy: &ErrorPolicy) -> Result<(), ExecError> {
+		for _ in 0 .. self.instructions_per_frame {
+			if self.run_instruction(policy)? == StepOutcome::Exited {
+				break;
+			}
+		}
+
+		self.end_frame();
+		Ok(())
+	}
+
+	// Emulates one cycle, dealing with errors the way the policy says. Errors it skips over count as executed
+	pub(crate) fn run_instruction(&mut self, policy: &ErrorPolicy) -> Result<StepOutcome, ExecError> {
+		match self.emulate_cycle() {
+			Ok(outcome) => Ok(outcome),
+			Err(e) => {
+				let action = policy.action(&e);
+				self.dump_trace(&e, action == ErrorAction::Halt);
+				match action {
+					ErrorAction::Halt => Err(e),
+					ErrorAction::Log => {
+						eprintln!("{}, skipping it", e);
+						self.skip_instruction();
+						Ok(StepOutcome::Executed)
+					},
+					ErrorAction::Ignore => {
+						self.skip_instruction();
+						Ok(StepOutcome::Executed)
+					}
+				}
+			}
+		}
+	}
+
+	// Ticks the timers once all of a frame's instructions have been run
+	pub(crate) fn end_frame(&mut self) {
 		self.update_timers();
+		self.frame_count += 1;
+	}
+
+	// Moves pc to the next instruction without executing the current one, to carry on after an error
+	pub fn skip_instruction(&mut self) {
+		self.pc = self.pc.wrapping_add(2);
+	}
+
+	pub fn instructions_per_frame(&self) -> u32 {
+		self.instructions_per_frame
+	}
+
+	// Sets the CPU's speed. The timers always run at 60 Hz, so this is the instructions per second / 60
+	pub fn set_instructions_per_frame(&mut self, ipf: u32) {
+		self.instructions_per_frame = ipf;
+	}
+
+	// How many frames have been run since the machine was created
+	pub fn frame_count(&self) -> u64 {
+		self.frame_count
+	}
+
+	// Whether the program has stopped the interpreter with 00FD
+	pub fn has_exited(&self) -> bool {
+		self.exited
 	}
 
 	fn update_timers(&mut self) {
@@ -89,62 +227,70 @@ impl CHIP8 {
 			self.delay_timer -= 1;
 		}
 
-		if self.sound_timer > 0 { // does the same but also calls play_snd() when it gets to 1
-			if self.sound_timer == 1 {
-				self.play_snd();
-			}
+		if self.sound_timer > 0 { // the buzzer sounds for as long as the sound timer is above zero
 			self.sound_timer -= 1;
 		}
 	}
 }
 
-use crow::glutin::event::{ KeyboardInput, ElementState };
-use image::{ RgbaImage, Rgba };
-
 // Peripherals, input, display, sound
 impl CHIP8 {
-	pub fn handle_input(&mut self, key: KeyboardInput) {
-		match key.scancode {
-			0x2D => self.key[0x0] = (key.state == ElementState::Pressed) as u8, // X
-			0x02 => self.key[0x1] = (key.state == ElementState::Pressed) as u8, // 1
-			0x03 => self.key[0x2] = (key.state == ElementState::Pressed) as u8, // 2
-			0x04 => self.key[0x3] = (key.state == ElementState::Pressed) as u8, // 3
-			0x10 => self.key[0x4] = (key.state == ElementState::Pressed) as u8, // Q
-			0x11 => self.key[0x5] = (key.state == ElementState::Pressed) as u8, // W
-			0x12 => self.key[0x6] = (key.state == ElementState::Pressed) as u8, // E
-			0x1E => self.key[0x7] = (key.state == ElementState::Pressed) as u8, // A
-			0x1F => self.key[0x8] = (key.state == ElementState::Pressed) as u8, // S
-			0x20 => self.key[0x9] = (key.state == ElementState::Pressed) as u8, // D
-			0x2C => self.key[0xA] = (key.state == ElementState::Pressed) as u8, // Z
-			0x2E => self.key[0xB] = (key.state == ElementState::Pressed) as u8, // E
-			0x05 => self.key[0xC] = (key.state == ElementState::Pressed) as u8, // 4
-			0x13 => self.key[0xD] = (key.state == ElementState::Pressed) as u8, // R
-			0x21 => self.key[0xE] = (key.state == ElementState::Pressed) as u8, // F
-			0x2F => self.key[0xF] = (key.state == ElementState::Pressed) as u8, // V
-			_ => (),
+	// The width and height of the screen in the current mode
+	pub fn resolution(&self) -> (usize, usize) {
+		if self.hires {
+			(HIRES_W as usize, HIRES_H as usize)
+		} else {
+			(WINDOW_W as usize, WINDOW_H as usize)
 		}
 	}
 
-	// Create an image from the vram
-	pub fn create_screen_image(&mut self) -> RgbaImage {
-		let mut img = RgbaImage::from_pixel(WINDOW_W.into(), WINDOW_H.into(), 
-											Rgba([0, 0, 0, 255]));
-		
-		for y in 0 .. WINDOW_H {
-			for x in 0 .. WINDOW_W {
-				if self.gfx[(y as usize * WINDOW_W as usize) + x as usize] == 0 {
-					img.put_pixel(x.into(), y.into(), Rgba([0, 0, 0, 255])); // disabled
-				} else {
-					img.put_pixel(x.into(), y.into(), Rgba([255, 255, 255, 255])); // enabled
-				}
-			}
+	// Switches between the 64x32 and 128x64 modes, clearing the screen
+	fn set_hires(&mut self, hires: bool) {
+		self.hires = hires;
+		for pixel in self.gfx.iter_mut() {
+			*pixel = 0;
 		}
+		self.draw_flag = true;
+	}
 
+	// The vram in the current resolution
+	pub fn screen(&self) -> Screen<'_> {
+		let (w, h) = self.resolution();
+		Screen::new(w, h, &self.gfx)
+	}
+
+	// Create an image from the vram
+	#[cfg(feature = "image")]
+	pub fn create_screen_image(&mut self) -> image::RgbaImage {
 		self.draw_flag = false; // reset the draw flag
-		img
+		self.screen().to_image()
+	}
+
+	// Saves the screen as a PNG, with every pixel scaled up to a scale x scale square
+	#[cfg(feature = "image")]
+	pub fn save_screenshot<P: AsRef<Path>>(&self, path: P, scale: u32, palette: &crate::Palette) -> image::ImageResult<()> {
+		crate::screenshot::save_png(&self.screen(), path, scale, palette)
+	}
+
+	// Whether the buzzer should be sounding right now
+	pub fn is_sound_playing(&self) -> bool {
+		self.sound_timer > 0
+	}
+
+	pub fn sound_state(&self) -> SoundState {
+		SoundState {
+			playing: self.is_sound_playing(),
+			pattern: self.pattern,
+			rate: self.playback_rate()
+		}
+	}
+
+	pub fn audio_pattern(&self) -> [u8; 16] {
+		self.pattern
 	}
 
-	fn play_snd(&mut self) {
-		self.sound_flag = true;
+	// How many bits of the audio pattern are played per second, as set by FX3A
+	pub fn playback_rate(&self) -> f32 {
+		4000.0 * 2f32.powf((self.pitch as f32 - 64.0) / 48.0)
 	}
 }
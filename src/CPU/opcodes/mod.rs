@@ -1,5 +1,4 @@
-use crate::{ CPU::CHIP8, WINDOW_W, WINDOW_H };
-use std::convert::TryInto;
+use crate::{ CPU::CHIP8, Instruction, IndexIncrement, Platform, ExecError, StepOutcome, AccessKind, BIG_FONT_START };
 
 use rand::random;
 
@@ -7,301 +6,480 @@ use rand::random;
 mod tests;
 
 impl CHIP8 {
-	pub fn handle_opcode(&mut self) {
-		let vxi = ((self.opcode & 0x0F00) >> 8) as usize; // turn X (2nd digit of an opcode) into an index
-		let vyi = ((self.opcode & 0x00F0) >> 4) as usize; // same but for Y (3rd digit of an opcode)
-
-		match self.opcode & 0xF000 { // To match the opcodes we only care about the first 4 bits
-			0x0000 => { // There are multiple codes that start the first 4 bits as 0
-				match self.opcode & 0x000F { // Compare the last 4 bits
-					0x0000 => { // 00E0 - Display - Clears the screen
-						for i in 0 .. 2048 {
-							self.gfx[i] = 0x00 // a black pixel is represented as 0
-						}
-						self.draw_flag = true;
-						self.pc += 2;
-					},
+	// Executes a decoded instruction, the one at pc. If it fails nothing is changed and pc still points at it
+	pub fn execute(&mut self, instruction: Instruction) -> Result<StepOutcome, ExecError> {
+		use Instruction::*;
 
-					0x000E => { // 00EE - Flow - Returns from a subroutine
-						self.sp = self.sp.wrapping_sub(1); // decrease stack pointer
-						if self.sp as usize > self.stack.len() { 
-							panic!("Stack pointer {} is out of bounds!", self.sp);
-						}
-						self.pc = self.stack[self.sp as usize]; // puts the stored address into pc
-						self.pc += 2;
-					},
+		let xo_chip = self.platform == Platform::XoChip;
 
-					_ => { // Illegal opcode
-						println!("Unknown opcode [0x0000]: {:X}", self.opcode);
-					}
+		// instructions that move on to the next one can't be at the very end of memory, where pc would point
+		// past it, or for XO-CHIP's 64 KiB past what it can hold. checked first so nothing changes if they fail
+		if moves_on(instruction) {
+			self.pc_after(self.pc, if xo_chip && instruction == LongIndex { 4 } else { 2 })?;
+		}
+
+		match instruction {
+			Clear => { // 00E0 - Display - Clears the selected planes of the screen
+				for pixel in self.gfx.iter_mut() {
+					*pixel &= !self.plane // a black pixel is represented as 0
 				}
+				self.draw_flag = true;
+				self.pc += 2;
+			},
+
+			Return => { // 00EE - Flow - Returns from a subroutine
+				if self.sp == 0 { // there's nowhere to return to
+					return Err(ExecError::StackUnderflow { pc: self.pc, opcode: self.opcode });
+				}
+				let next = self.pc_after(self.stack[self.sp as usize - 1], 2)?; // the instruction after the call
+				self.sp -= 1; // decrease stack pointer
+				self.pc = next;
+			},
+
+			ScrollDown { n } => { // 00CN - Display - Scrolls the screen down by N lines
+				self.scroll(0, n as isize);
+				self.pc += 2;
+			},
+
+			ScrollUp { n } if xo_chip => { // 00DN - Display - Scrolls the screen up by N lines
+				self.scroll(0, -(n as isize));
+				self.pc += 2;
+			},
+
+			ScrollRight => { // 00FB - Display - Scrolls the screen right by 4 pixels
+				self.scroll(4, 0);
+				self.pc += 2;
+			},
+
+			ScrollLeft => { // 00FC - Display - Scrolls the screen left by 4 pixels
+				self.scroll(-4, 0);
+				self.pc += 2;
 			},
 
-			0x1000 => { // 1NNN - Flow - Jumps to address NNN
-				self.pc = self.opcode & 0x0FFF;
+			Exit => { // 00FD - Flow - Exits the interpreter
+				self.exited = true;
+				return Ok(StepOutcome::Exited);
 			},
 
-			0x2000 => { // 2NNN - Flow - Calls subroutine at NNN
+			Lores => { // 00FE - Display - Switches to the 64x32 low resolution mode
+				self.set_hires(false);
+				self.pc += 2;
+			},
+
+			Hires => { // 00FF - Display - Switches to the 128x64 high resolution mode
+				self.set_hires(true);
+				self.pc += 2;
+			},
+
+			Jump { addr } => { // 1NNN - Flow - Jumps to address NNN
+				self.pc = addr;
+			},
+
+			Call { addr } => { // 2NNN - Flow - Calls subroutine at NNN
+				if self.sp as usize >= self.stack.len() { // every level of the stack is in use
+					return Err(ExecError::StackOverflow { pc: self.pc, opcode: self.opcode });
+				}
 				self.stack[self.sp as usize] = self.pc; // store the pc's current address in the stack
 				self.sp += 1; // increase stack pointer
-				self.pc = self.opcode & 0x0FFF // gets rid of the first 4 bits and sets the pc to the address
+				self.pc = addr;
 			},
 
-			0x3000 => { // 3XNN - Cond - Skip the next instruction if Vx == NN
-				if self.V[vxi] as u16 == (self.opcode & 0x00FF) {
-					self.pc += 4;
-				} else {
-					self.pc += 2;
-				}
+			SkipIfEqual { x, byte } => { // 3XNN - Cond - Skip the next instruction if Vx == NN
+				self.skip_if(self.V[x as usize] == byte)?;
 			},
 
-			0x4000 => { // 4XNN - Cond - Skip the next instruction if Vx != NN
-				if self.V[vxi] as u16 != (self.opcode & 0x00FF) {
-					self.pc += 4;
-				} else {
-					self.pc += 2;
-				}
+			SkipIfNotEqual { x, byte } => { // 4XNN - Cond - Skip the next instruction if Vx != NN
+				self.skip_if(self.V[x as usize] != byte)?;
 			},
 
-			0x5000 => { // 5XY0 - Cond - Skip the next instruction if Vx == Vy
-				if self.V[vxi] == self.V[vyi] {
-					self.pc += 4;
-				} else {
-					self.pc += 2;
-				}
-			}
+			SkipIfRegistersEqual { x, y } => { // 5XY0 - Cond - Skip the next instruction if Vx == Vy
+				self.skip_if(self.V[x as usize] == self.V[y as usize])?;
+			},
 
-			0x6000 => { // 6XNN - Const - Sets Vx to NN
-				self.V[vxi] = (self.opcode & 0x00FF) as u8;
+			SaveRange { x, y } if xo_chip => { // 5XY2 - MEM - Stores Vx to Vy in memory, starts at I. I isn't changed
+				let (x, y) = (x as usize, y as usize);
+				let len = x.max(y) - x.min(y) + 1;
+				self.check_memory(self.I as usize, len)?;
+				for (offset, reg) in register_range(x, y).enumerate() {
+					self.memory[self.I as usize + offset] = self.V[reg];
+				}
+				self.invalidate_decoded(self.I as usize, len);
+				self.record_access(AccessKind::Write, self.I as usize, len);
 				self.pc += 2;
 			},
 
-			0x7000 => { // 7XNN - Const - Adds NN to Vx
-				let n: u8 = (self.opcode & 0x00FF).try_into().unwrap();
-				self.V[vxi] = self.V[vxi].wrapping_add(n);
+			LoadRange { x, y } if xo_chip => { // 5XY3 - MEM - Fills Vx to Vy with values from memory, starts at I. I isn't changed
+				let (x, y) = (x as usize, y as usize);
+				let len = x.max(y) - x.min(y) + 1;
+				self.check_memory(self.I as usize, len)?;
+				self.record_access(AccessKind::Read, self.I as usize, len);
+				for (offset, reg) in register_range(x, y).enumerate() {
+					self.V[reg] = self.memory[self.I as usize + offset];
+				}
 				self.pc += 2;
 			},
 
-			0x8000 => {
-				match self.opcode & 0x000F {
-					0x0000 => { // 8XY0 - Assign - Sets Vx to the value of Vy
-						self.V[vxi] = self.V[vyi];
-						self.pc += 2;
-					},
+			SetImmediate { x, byte } => { // 6XNN - Const - Sets Vx to NN
+				self.V[x as usize] = byte;
+				self.pc += 2;
+			},
 
-					0x0001 => { // 8XY1 - BitOp - Sets Vx to Vx or Vy
-						self.V[vxi] = self.V[vxi] | self.V[vyi];
-						self.pc += 2;
-					},
+			AddImmediate { x, byte } => { // 7XNN - Const - Adds NN to Vx
+				self.V[x as usize] = self.V[x as usize].wrapping_add(byte);
+				self.pc += 2;
+			},
 
-					0x0002 => { // 8XY2 - BitOp - Sets Vx to Vx and Vy
-						self.V[vxi] = self.V[vxi] & self.V[vyi];
-						self.pc += 2;
-					},
+			Move { x, y } => { // 8XY0 - Assign - Sets Vx to the value of Vy
+				self.V[x as usize] = self.V[y as usize];
+				self.pc += 2;
+			},
 
-					0x0003 => { // 8XY2 - BitOp - Sets Vx to Vx xor Vy
-						self.V[vxi] = self.V[vxi] ^ self.V[vyi];
-						self.pc += 2;
-					},
+			Or { x, y } => { // 8XY1 - BitOp - Sets Vx to Vx or Vy
+				self.V[x as usize] |= self.V[y as usize];
+				if self.quirks.vf_reset { self.V[0xF] = 0; }
+				self.pc += 2;
+			},
 
-					0x0004 => { // 8XY4 - Math - Adds Vy to Vx. sets Vf to 1 if theres a carry and 0 if there isnt
-						if self.V[vyi] > (255 - self.V[vxi]) { // there's overflow
-							self.V[0xF] = 1; // set the carry bit
-						} else {
-							self.V[0xF] = 0;
-						}
+			And { x, y } => { // 8XY2 - BitOp - Sets Vx to Vx and Vy
+				self.V[x as usize] &= self.V[y as usize];
+				if self.quirks.vf_reset { self.V[0xF] = 0; }
+				self.pc += 2;
+			},
 
-						self.V[vxi] = self.V[vxi].wrapping_add(self.V[vyi]);
-						self.pc += 2;
-					},
+			Xor { x, y } => { // 8XY3 - BitOp - Sets Vx to Vx xor Vy
+				self.V[x as usize] ^= self.V[y as usize];
+				if self.quirks.vf_reset { self.V[0xF] = 0; }
+				self.pc += 2;
+			},
 
-					0x0005 => { // 8XY5 - Math - Subs Vy from Vx. sets Vf to 0 if theres a borrow and 1 if there isnt
-						if self.V[vyi] > self.V[vxi] { // goes into negative
-							self.V[0xF] = 0; // There's a borrow.
-						} else {
-							self.V[0xF] = 1;
-						}
-						self.V[vxi] = self.V[vxi].wrapping_sub(self.V[vyi]);
-						self.pc += 2;
-					},
+			Add { x, y } => { // 8XY4 - Math - Adds Vy to Vx. sets Vf to 1 if theres a carry and 0 if there isnt
+				let (sum, carry) = self.V[x as usize].overflowing_add(self.V[y as usize]);
+				self.V[0xF] = carry as u8; // set the carry bit
+				self.V[x as usize] = sum;
+				self.pc += 2;
+			},
 
-					0x0006 => { // 8XY6 - BitOp - Stores the lsb of Vx in Vf. Shifts Vx to the right by 1
-						self.V[0xF] = self.V[vxi] & 0x1; // Get only the least significant bit
-						self.V[vxi] >>= 1; // shift by 1
-						self.pc += 2;
-					},
+			Sub { x, y } => { // 8XY5 - Math - Subs Vy from Vx. sets Vf to 0 if theres a borrow and 1 if there isnt
+				let (difference, borrow) = self.V[x as usize].overflowing_sub(self.V[y as usize]);
+				self.V[0xF] = !borrow as u8;
+				self.V[x as usize] = difference;
+				self.pc += 2;
+			},
 
-					0x0007 => { // 8XY7 - Math - Sets Vx to Vy-Vx. Vf is 0 when there's a borrow and 1 when there isn't.
-						if self.V[vxi] > self.V[vyi] { // there's a borrow
-							self.V[0xF] = 0;
-						} else {
-							self.V[0xF] = 1;
-						}
-						self.V[vxi] = self.V[vyi].wrapping_sub(self.V[vxi]);
-						self.pc += 2;
-					}
+			ShiftRight { x, y } => { // 8XY6 - BitOp - Stores the lsb of Vx in Vf. Shifts Vx to the right by 1
+				let value = if self.quirks.shift { self.V[x as usize] } else { self.V[y as usize] };
+				self.V[x as usize] = value >> 1; // shift by 1
+				self.V[0xF] = value & 0x1; // Get only the least significant bit
+				self.pc += 2;
+			},
 
-					0x000E => { // 8XYE - BitOp - Stores the msb of Vx in Vf. Shifts Vx to the left by 1
-						self.V[0xF] = self.V[vxi] >> 7; // Get only the most significant bit
-						self.V[vxi] <<= 1;
-						self.pc += 2;
-					},
+			SubReversed { x, y } => { // 8XY7 - Math - Sets Vx to Vy-Vx. Vf is 0 when there's a borrow and 1 when there isn't.
+				let (difference, borrow) = self.V[y as usize].overflowing_sub(self.V[x as usize]);
+				self.V[0xF] = !borrow as u8;
+				self.V[x as usize] = difference;
+				self.pc += 2;
+			},
 
-					_ => {
-						println!("Unknown opcode [0x8000]: {:X}", self.opcode);
-					}
-				}
+			ShiftLeft { x, y } => { // 8XYE - BitOp - Stores the msb of Vx in Vf. Shifts Vx to the left by 1
+				let value = if self.quirks.shift { self.V[x as usize] } else { self.V[y as usize] };
+				self.V[x as usize] = value << 1;
+				self.V[0xF] = value >> 7; // Get only the most significant bit
+				self.pc += 2;
 			},
 
-			0x9000 => { // 9XY0 - Cond - Skips the next instruction if Vx != Vy
-				if self.V[vxi] != self.V[vyi] {
-					self.pc += 4;
-				} else {
-					self.pc += 2;
-				}
-			}
+			SkipIfRegistersNotEqual { x, y } => { // 9XY0 - Cond - Skips the next instruction if Vx != Vy
+				self.skip_if(self.V[x as usize] != self.V[y as usize])?;
+			},
 
-			0xA000 => { // ANNN - MEM - Sets I to the address NNN
-				self.I = self.opcode & 0x0FFF; // gets rid of the first 4 bits and assigns the value to I
+			SetIndex { addr } => { // ANNN - MEM - Sets I to the address NNN
+				self.I = addr;
 				self.pc += 2; // Since every instruction is 2 bytes long we increment the program counter by 2
 			},
 
-			0xB000 => { // BNNN - Flow - Jumps to the address NNN + V0
-				self.pc = (self.opcode & 0xFFF) + self.V[0] as u16;
+			JumpOffset { addr } => { // BNNN - Flow - Jumps to the address NNN + V0
+				let offset = if self.quirks.jump_vx { self.V[(addr >> 8) as usize] } else { self.V[0] }; // BXNN jumps to XNN + Vx
+				self.pc = addr + offset as u16;
 			},
 
-			0xC000 => { // CXNN - Rand - Sets Vx to the result of rnd(0, 255) and NN
+			Random { x, byte } => { // CXNN - Rand - Sets Vx to the result of rnd(0, 255) and NN
 				let n = random::<u8>(); // will generate a number between 0 and 255 (ranges of an u8)
-				self.V[vxi] = n & (self.opcode & 0x00FF) as u8;
+				self.V[x as usize] = n & byte;
 				self.pc += 2;
-			}
+			},
 
-			0xD000 => { // DXYN - Disp -- Draw a sprite at (Vx,Vy) of width 8 and height N+1. Vf is set to 1 if any pixels get flipped and to 0 if not.
-				let x = self.V[vxi] as usize;
-				let y = self.V[vyi] as usize;
-				let height = (self.opcode & 0x000F) as usize;
-				self.V[0xF] = 0; // reset the Vf register
-
-				for yline in 0 .. height { // for each row
-					let sprite = self.memory[self.I as usize + yline]; // get the pixel data stored at I + yline
-
-					for xline in 0 .. 8 { // for each bit of a row
-						let cur_pixel = sprite & (0x80 >> xline); // scans through the sprite byte, 1 bit at a time.
-						if cur_pixel != 0 { // if the current pixel is set
-							// access the screen's 2D array of pixels with 1D indexing.
-							let screen_coords = (x + xline + ((y + yline) * 64)) %
-								(WINDOW_W as usize * WINDOW_H as usize);
-
-							// check if the pixel on display at that position is already set to 1, If it
-							// is set the Vf register accordingly.
-							if self.gfx[screen_coords] == 1 { self.V[0xF] = 1; }
-
-							// finally, update the screen's pixels with the new values using XOR
-							self.gfx[screen_coords] ^= 1;
-						}
-					}
+			Draw { x, y, n } => { // DXYN - Disp -- Draw a sprite at (Vx,Vy) of width 8 and height N+1. Vf is set to 1 if any pixels get flipped and to 0 if not.
+				if n == 0 { // DXY0 - Disp - Draws a 16x16 sprite instead
+					self.draw_sprite(self.V[x as usize], self.V[y as usize], 16, 16)?;
+				} else {
+					self.draw_sprite(self.V[x as usize], self.V[y as usize], 8, n as usize)?;
 				}
 
 				self.draw_flag = true; // the vram was changed so we must redraw
 				self.pc += 2;
 			},
 
-			0xE000 => {
-				match self.opcode & 0x00FF {
-					0x009E => { // EX9E - KeyOp - Skips the next instruction if the key stored in Vx is pressed.
-						if self.key[self.V[vxi] as usize] != 0 {
-							self.pc += 4;
-						} else {
-							self.pc += 2;
-						}
-					},
-					0x00A1 => { // EXA1 - KeyOp - Skips the next instruction if the key stored in Vx isn't pressed.
-						if self.key[self.V[vxi] as usize] == 0 {
-							self.pc += 4;
-						} else {
-							self.pc += 2;
-						}
+			SkipIfKey { x } => { // EX9E - KeyOp - Skips the next instruction if the key stored in Vx is pressed.
+				self.skip_if(self.key[self.V[x as usize] as usize & 0xF] != 0)?;
+			},
+
+			SkipIfNotKey { x } => { // EXA1 - KeyOp - Skips the next instruction if the key stored in Vx isn't pressed.
+				self.skip_if(self.key[self.V[x as usize] as usize & 0xF] == 0)?;
+			},
+
+			LongIndex if xo_chip => { // F000 NNNN - MEM - Sets I to the 16 bit address NNNN that follows
+				self.check_memory(self.pc as usize + 2, 2)?;
+				self.I = self.next_word();
+				self.pc += 4;
+			},
+
+			SelectPlanes { n } if xo_chip => { // FN01 - Display - Selects the planes N that drawing and clearing affect
+				self.plane = n;
+				self.pc += 2;
+			},
+
+			LoadAudio if xo_chip => { // F002 - Sound - Loads the 16 byte audio pattern stored at I
+				let start = self.I as usize;
+				self.check_memory(start, 16)?;
+				self.pattern.copy_from_slice(&self.memory[start .. start + 16]);
+				self.record_access(AccessKind::Read, start, 16);
+				self.pc += 2;
+			},
+
+			GetDelay { x } => { // FX07 - Timer - Sets Vx to the value of the delay timer
+				self.V[x as usize] = self.delay_timer;
+				self.pc += 2;
+			},
+
+			WaitKey { x } => { // FX0A - KeyOp - Waits for keypress, then stores it in Vx.
+				match (0 .. 16).rev().find(|&i| self.key[i] != 0) { // the highest key wins if several are down
+					Some(i) => {
+						let next = self.pc_after(self.pc, 2)?;
+						self.V[x as usize] = i as u8;
+						self.pc = next;
 					},
-					_ => {
-						println!("Unknown opcode [0xE000]: {:X}", self.opcode);
-					}
+					None => return Ok(StepOutcome::WaitingForKey) // pc stays put until a key is pressed
 				}
 			},
 
-			0xF000 => {
-				match self.opcode & 0x00FF {
-					0x0007 => { // FX07 - Timer - Sets Vx to the value of the delay timer
-						self.V[vxi] = self.delay_timer;
-						self.pc += 2;
-					},
+			SetDelay { x } => { // FX15 - Timer - Sets the delay timer to Vx
+				self.delay_timer = self.V[x as usize];
+				self.pc += 2;
+			},
 
-					0x000A => { // FX0A - KeyOp - Waits for keypress, then stores it in Vx.
-						let mut got_kp = false;
+			SetSound { x } => { // FX18 - Sound - Sets the sound timer to Vx
+				self.sound_timer = self.V[x as usize];
+				self.pc += 2;
+			},
 
-						for i in 0 .. 16 {
-							if self.key[i] != 0 {
-								self.V[vxi] = i as u8;
-								got_kp = true;
-							}
-						}
+			AddIndex { x } => { // FX1E - MEM - Adds Vx to I. Vf not affected
+				self.I = self.I.wrapping_add(self.V[x as usize] as u16);
+				self.pc += 2;
+			},
 
-						if got_kp { // will only incrase the pc if a key was pressed
-							self.pc += 2;
-						}
-					}
+			Font { x } => { // FX29 - MEM - Sets I to the location of the sprite for the character in Vx.
+				self.I = (self.V[x as usize] as u16) * 0x5;
+				self.pc += 2;
+			},
 
-					0x0015 => { // FX15 - Timer - Sets the delay timer to Vx
-						self.delay_timer = self.V[vxi];
-						self.pc += 2;
-					},
+			BigFont { x } => { // FX30 - MEM - Sets I to the location of the big 8x10 sprite for the digit in Vx.
+				self.I = BIG_FONT_START + (self.V[x as usize] as u16) * 10;
+				self.pc += 2;
+			},
 
-					0x0018 => { // FX18 - Sound - Sets the sound timer to Vx
-						self.sound_timer = self.V[vxi];
-						self.pc += 2;
-					},
+			SetPitch { x } if xo_chip => { // FX3A - Sound - Sets the audio pattern's pitch to Vx
+				self.pitch = self.V[x as usize];
+				self.pc += 2;
+			},
 
-					0x001E => { // FX1E - MEM - Adds Vx to I. Vf not affected
-						self.I = self.I.wrapping_add(self.V[vxi] as u16);
-						self.pc += 2;
-					}
+			Bcd { x } => { // FX33 - BCD - Stores the decimal representation of Vx at the address in I
+				let value = self.V[x as usize];
+				self.check_memory(self.I as usize, 3)?;
+				self.memory[self.I as usize] = value / 100;
+				self.memory[self.I as usize + 1] = (value / 10) % 10;
+				self.memory[self.I as usize + 2] = value % 10;
+				self.invalidate_decoded(self.I as usize, 3);
+				self.record_access(AccessKind::Write, self.I as usize, 3);
+				self.pc += 2;
+			},
 
-					0x0029 => { // FX29 - MEM - Sets I to the location of the sprite for the character in Vx.
-						self.I = (self.V[vxi] as u16) * 0x5;
-						self.pc += 2;
-					},
+			Save { x } => { // FX55 - MEM - Stores V0 to Vx in memory, starts at I, adds X+1 to I
+				let x = x as usize;
+				self.check_memory(self.I as usize, x + 1)?;
+				for i in 0 ..= x { // go through V0 to Vx
+					self.memory[self.I as usize + i] = self.V[i];
+				}
+				self.invalidate_decoded(self.I as usize, x + 1);
+				self.record_access(AccessKind::Write, self.I as usize, x + 1);
+				self.increment_index(x);
+				self.pc += 2;
+			},
 
-					0x0033 => { // FX33 - BCD - Stores the decimal representation of Vx at the address in I
-						self.memory[self.I as usize] = self.V[vxi as usize] / 100;
-						self.memory[(self.I + 1) as usize] = (self.V[vxi as usize] / 10) % 10;
-						self.memory[(self.I + 2) as usize] = (self.V[vxi as usize] % 100) % 10;
-						self.pc += 2;
-					},
+			Load { x } => { // FX65 - MEM - Fills V0 to Vx with values from memory, starts at I, adds X+1 to I
+				let x = x as usize;
+				self.check_memory(self.I as usize, x + 1)?;
+				for i in 0 ..= x { // go through V0 to Vx
+					self.V[i] = self.memory[self.I as usize + i];
+				}
+				self.record_access(AccessKind::Read, self.I as usize, x + 1);
+				self.increment_index(x);
+				self.pc += 2;
+			},
 
-					0x0055 => { // FX55 - MEM - Stores V0 to Vx in memory, starts at I, adds X+1 to I
-						for i in 0 ..= (self.opcode & 0x0F00) >> 8 { // go through V0 to Vx
-							self.memory[(self.I + i) as usize] = self.V[i as usize];
-						}
-						self.I += 1 + (self.opcode & 0x0F00) >> 8;
-						self.pc += 2;
-					},
+			SaveFlags { x } => { // FX75 - MEM - Stores V0 to Vx in the RPL user flags
+				self.rpl[..= x as usize].copy_from_slice(&self.V[..= x as usize]);
+				self.pc += 2;
+			},
 
-					0x0065 => { // FX65 - MEM - Fills V0 to Vx with values from memory, starts at I, adds X+1 to I
-						for i in 0 ..= (self.opcode & 0x0F00) >> 8 { // go through V0 to Vx
-							self.V[i as usize] = self.memory[(self.I + i) as usize];
-						}
-						self.I += 1 + (self.opcode & 0x0F00) >> 8;
-						self.pc += 2;
-					},
+			LoadFlags { x } => { // FX85 - MEM - Fills V0 to Vx with values from the RPL user flags
+				self.V[..= x as usize].copy_from_slice(&self.rpl[..= x as usize]);
+				self.pc += 2;
+			},
 
-					_ => {
-						println!("Unknown opcode [0xF000]: {:X}", self.opcode);
+			// Illegal opcodes, and the XO-CHIP instructions on other platforms
+			_ => {
+				return Err(self.unknown_opcode());
+			}
+		}
+
+		Ok(StepOutcome::Executed)
+	}
+
+	// Moves I past the registers FX55/FX65 just transferred, if the quirks say it should
+	fn increment_index(&mut self, x: usize) {
+		match self.quirks.index_increment {
+			IndexIncrement::Unchanged => (),
+			IndexIncrement::X => self.I = self.I.wrapping_add(x as u16),
+			IndexIncrement::XPlusOne => self.I = self.I.wrapping_add(x as u16 + 1)
+		}
+	}
+
+	// XORs a sprite of width x height pixels stored at I onto the screen at (x, y). Sprites 16 pixels wide
+	// are stored as 2 bytes per row. When more than one plane is selected the sprite for each plane
+	// follows the previous one in memory
+	fn draw_sprite(&mut self, x: u8, y: u8, width: usize, height: usize) -> Result<(), ExecError> {
+		let (w, h) = self.resolution();
+		let bytes_per_row = width / 8;
+		let len = self.plane.count_ones() as usize * height * bytes_per_row;
+		self.check_memory(self.I as usize, len)?;
+		self.record_access(AccessKind::Read, self.I as usize, len);
+
+		// the starting position always wraps around, only the sprite's pixels are affected by clipping
+		let x = x as usize % w;
+		let y = y as usize % h;
+		let selected = self.plane;
+		let mut sprite_start = self.I as usize;
+		self.V[0xF] = 0; // reset the Vf register
+
+		for plane in [0b01, 0b10].iter().filter(|&&p| selected & p != 0) {
+			for yline in 0 .. height { // for each row
+				if self.quirks.clip && y + yline >= h { break; } // the rest of the sprite is below the screen
+
+				for xline in 0 .. width { // for each bit of a row
+					if self.quirks.clip && x + xline >= w { break; }
+					// get the pixel data for this row and the byte this bit is in
+					let sprite = self.memory[sprite_start + yline * bytes_per_row + xline / 8];
+					let cur_pixel = sprite & (0x80 >> (xline % 8)); // scans through the sprite byte, 1 bit at a time.
+					if cur_pixel != 0 { // if the current pixel is set
+						// access the screen's 2D array of pixels with 1D indexing.
+						let screen_coords = ((x + xline) % w) + ((y + yline) % h) * w;
+
+						// check if the pixel on display at that position is already set in this plane,
+						// If it is set the Vf register accordingly.
+						if self.gfx[screen_coords] & plane != 0 { self.V[0xF] = 1; }
+
+						// finally, update the screen's pixels with the new values using XOR
+						self.gfx[screen_coords] ^= plane;
 					}
 				}
-			},
+			}
+
+			sprite_start += height * bytes_per_row;
+		}
+
+		Ok(())
+	}
 
-			_ => { // Illegal opcode
-				println!("Unknown opcode: {:X}", self.opcode);
+	// Moves the selected planes of the screen by dx pixels right and dy pixels down. Pixels moved off
+	// the screen are lost and the space left behind is cleared
+	fn scroll(&mut self, dx: isize, dy: isize) {
+		let (w, h) = self.resolution();
+		let old = self.gfx;
+
+		for y in 0 .. h {
+			for x in 0 .. w {
+				let (src_x, src_y) = (x as isize - dx, y as isize - dy);
+				let moved = if src_x < 0 || src_y < 0 || src_x >= w as isize || src_y >= h as isize {
+					0
+				} else {
+					old[src_y as usize * w + src_x as usize]
+				};
+				// unselected planes stay where they are
+				self.gfx[y * w + x] = (old[y * w + x] & !self.plane) | (moved & self.plane);
 			}
 		}
+
+		self.draw_flag = true;
+	}
+
+	// Skips the next instruction if the condition holds, otherwise moves on to it. XO-CHIP's F000 NNNN is
+	// 4 bytes long so it has to be skipped as a whole
+	fn skip_if(&mut self, condition: bool) -> Result<(), ExecError> {
+		let len = if !condition {
+			2
+		} else if self.platform == Platform::XoChip && self.next_word() == 0xF000 {
+			6
+		} else {
+			4
+		};
+		self.pc = self.pc_after(self.pc, len)?;
+		Ok(())
+	}
+
+	// Where pc would be len bytes after addr. Fails if that's past the end of memory
+	fn pc_after(&self, addr: u16, len: usize) -> Result<u16, ExecError> {
+		let next = addr as usize + len;
+		if next >= self.memory.len() {
+			Err(ExecError::MemoryOutOfBounds { pc: self.pc, opcode: Some(self.opcode), address: next })
+		} else {
+			Ok(next as u16)
+		}
+	}
+
+	// The 2 bytes after the current instruction, or 0 past the end of memory
+	fn next_word(&self) -> u16 {
+		let addr = self.pc as usize + 2;
+		let byte = |a: usize| self.memory.get(a).copied().unwrap_or(0) as u16;
+		byte(addr) << 8 | byte(addr + 1)
+	}
+
+	// Fails if any of the len bytes starting at start are past the end of memory
+	fn check_memory(&self, start: usize, len: usize) -> Result<(), ExecError> {
+		if start + len > self.memory.len() {
+			Err(ExecError::MemoryOutOfBounds { pc: self.pc, opcode: Some(self.opcode), address: start + len - 1 })
+		} else {
+			Ok(())
+		}
+	}
+
+	fn unknown_opcode(&self) -> ExecError {
+		ExecError::UnknownOpcode { pc: self.pc, opcode: self.opcode }
+	}
+}
+
+// Whether pc always goes on to the next instruction, rather than jumping, skipping or waiting
+fn moves_on(instruction: Instruction) -> bool {
+	use Instruction::*;
+
+	!matches!(instruction, Return | Exit | Jump { .. } | Call { .. } | JumpOffset { .. } | WaitKey { .. } | Unknown(_) |
+				SkipIfEqual { .. } | SkipIfNotEqual { .. } | SkipIfRegistersEqual { .. } | SkipIfRegistersNotEqual { .. } |
+				SkipIfKey { .. } | SkipIfNotKey { .. })
+}
+
+// The registers from Vx to Vy, counting down if Y comes before X
+fn register_range(x: usize, y: usize) -> Box<dyn Iterator<Item = usize>> {
+	if x <= y {
+		Box::new(x ..= y)
+	} else {
+		Box::new((y ..= x).rev())
 	}
 }
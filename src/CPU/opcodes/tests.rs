@@ -1,6 +1,6 @@
 #![allow(non_snake_case)]
 
-use crate::CHIP8;
+use crate::{ CHIP8, Chip8Key, Quirks, Platform, ExecError, StepOutcome, ErrorPolicy, ErrorAction, MemoryAccess, AccessKind };
 
 use std::convert::TryInto;
 
@@ -8,7 +8,7 @@ fn get_default_machine(initial_instruction: u16) -> CHIP8 {
 	let first_byte: u8 = (initial_instruction >> 8).try_into().unwrap();
 	let second_byte: u8 = (initial_instruction & 0x00FF).try_into().unwrap();
 
-	let mut machine = CHIP8::new(None).unwrap();
+	let mut machine = CHIP8::new(&[]).unwrap();
 
 	machine.memory[machine.pc as usize] = first_byte;
 	machine.memory[machine.pc as usize + 1] = second_byte;
@@ -22,14 +22,14 @@ fn opcode_00E0() {
 
 	let previous_pc = machine.pc;
 
-	machine.gfx = [1; 2048]; // enable all pixels
+	machine.gfx = [1; 128 * 64]; // enable all pixels
 
-	machine.emulate_cycle();
+	machine.emulate_cycle().unwrap();
 	
-	for i in 0 .. 2048 {
+	for i in 0 .. 128 * 64 {
 		assert_eq!(machine.gfx[i], 0);
 	}
-	assert_eq!(machine.draw_flag, true);
+	assert!(machine.draw_flag);
 	assert_eq!(machine.pc, previous_pc + 2);
 }
 
@@ -40,25 +40,26 @@ fn opcode_00EE() {
 	machine.sp = 11;
 	machine.stack[machine.sp as usize - 1] = 40;
 
-	machine.emulate_cycle();
+	machine.emulate_cycle().unwrap();
 
 	assert_eq!(machine.sp, 10);
 	assert_eq!(machine.pc, 42);
 }
 
 #[test]
-#[should_panic(expected = "Stack pointer 65535 is out of bounds")]
 fn opcode_00EE_out_of_bounds() {
 	let mut machine = get_default_machine(0x00EE);
 
-	machine.emulate_cycle();
+	assert_eq!(machine.emulate_cycle(), Err(ExecError::StackUnderflow { pc: 0x200, opcode: 0x00EE }));
+	assert_eq!(machine.sp, 0);
+	assert_eq!(machine.pc, 0x200);
 }
 
 #[test]
 fn opcode_1NNN() {
 	let mut machine = get_default_machine(0x129A);
 
-	machine.emulate_cycle();
+	machine.emulate_cycle().unwrap();
 
 	assert_eq!(machine.pc, 0x29A);
 }
@@ -69,7 +70,7 @@ fn opcode_2NNN() {
 
 	let previous_pc = machine.pc;
 
-	machine.emulate_cycle();
+	machine.emulate_cycle().unwrap();
 	
 	assert_eq!(machine.stack[machine.sp as usize - 1], previous_pc);
 	assert_eq!(machine.sp, 1);
@@ -84,7 +85,7 @@ fn opcode_3XNN_equals() {
 
 	machine.V[0x1] = 0x2A;
 
-	machine.emulate_cycle();
+	machine.emulate_cycle().unwrap();
 
 	assert_eq!(machine.pc, previous_pc + 4);
 }
@@ -97,7 +98,7 @@ fn opcode_3XNN_not_equals() {
 
 	machine.V[0x1] = 0x2B;
 
-	machine.emulate_cycle();
+	machine.emulate_cycle().unwrap();
 
 	assert_eq!(machine.pc, previous_pc + 2);
 }
@@ -110,7 +111,7 @@ fn opcode_4XNN_equals() {
 
 	machine.V[0x1] = 0x2A;
 
-	machine.emulate_cycle();
+	machine.emulate_cycle().unwrap();
 
 	assert_eq!(machine.pc, previous_pc + 2);
 }
@@ -123,7 +124,7 @@ fn opcode_4XNN_not_equals() {
 
 	machine.V[0x1] = 0x2B;
 
-	machine.emulate_cycle();
+	machine.emulate_cycle().unwrap();
 
 	assert_eq!(machine.pc, previous_pc + 4);
 }
@@ -137,7 +138,7 @@ fn opcode_5XY0_equals() {
 	machine.V[0x1] = 0x2A;
 	machine.V[0x2] = 0x2A;
 
-	machine.emulate_cycle();
+	machine.emulate_cycle().unwrap();
 
 	assert_eq!(machine.pc, previous_pc + 4);
 }
@@ -151,7 +152,7 @@ fn opcode_5XY0_not_equals() {
 	machine.V[0x1] = 0x2A;
 	machine.V[0x2] = 0x2B;
 
-	machine.emulate_cycle();
+	machine.emulate_cycle().unwrap();
 
 	assert_eq!(machine.pc, previous_pc + 2);
 }
@@ -162,7 +163,7 @@ fn opcode_6XNN() {
 
 	let previous_pc = machine.pc;
 
-	machine.emulate_cycle();
+	machine.emulate_cycle().unwrap();
 
 	assert_eq!(machine.V[0x1], 0x2A);
 	assert_eq!(machine.pc, previous_pc + 2);
@@ -176,7 +177,7 @@ fn opcode_7XNN() {
 
 	machine.V[0x1] = 1;
 
-	machine.emulate_cycle();
+	machine.emulate_cycle().unwrap();
 
 	assert_eq!(machine.V[0x1], 0x2A);
 	assert_eq!(machine.V[0xF], 0); // check carry flag isn't changed
@@ -191,7 +192,7 @@ fn opcode_7XNN_with_overflow() {
 
 	machine.V[0x1] = 0xC8;
 
-	machine.emulate_cycle();
+	machine.emulate_cycle().unwrap();
 
 	assert_eq!(machine.V[0x1], 0x2C);
 	assert_eq!(machine.V[0xF], 0); // check carry flag isn't changed
@@ -206,7 +207,7 @@ fn opcode_8XY0() {
 
 	machine.V[0x2] = 0x2A;
 
-	machine.emulate_cycle();
+	machine.emulate_cycle().unwrap();
 
 	assert_eq!(machine.V[0x1], 0x2A);
 	assert_eq!(machine.V[0x2], 0x2A);
@@ -222,7 +223,7 @@ fn opcode_8XY1() {
 	machine.V[0x1] = 0b1010;
 	machine.V[0x2] = 0b0101;
 
-	machine.emulate_cycle();
+	machine.emulate_cycle().unwrap();
 
 	assert_eq!(machine.V[0x1], 0b1111);
 	assert_eq!(machine.V[0x2], 0b0101);
@@ -238,7 +239,7 @@ fn opcode_8XY2() {
 	machine.V[0x1] = 0b1011;
 	machine.V[0x2] = 0b0101;
 
-	machine.emulate_cycle();
+	machine.emulate_cycle().unwrap();
 
 	assert_eq!(machine.V[0x1], 0b0001);
 	assert_eq!(machine.V[0x2], 0b0101);
@@ -254,7 +255,7 @@ fn opcode_8XY3() {
 	machine.V[0x1] = 0b1111;
 	machine.V[0x2] = 0b1001;
 
-	machine.emulate_cycle();
+	machine.emulate_cycle().unwrap();
 
 	assert_eq!(machine.V[0x1], 0b0110);
 	assert_eq!(machine.V[0x2], 0b1001);
@@ -270,7 +271,7 @@ fn opcode_8XY4() {
 	machine.V[0x1] = 0x3C;
 	machine.V[0x2] = 0x9;
 
-	machine.emulate_cycle();
+	machine.emulate_cycle().unwrap();
 
 	assert_eq!(machine.V[0x1], 0x45);
 	assert_eq!(machine.V[0x2], 0x9);
@@ -287,7 +288,7 @@ fn opcode_8XY4_with_overflow() {
 	machine.V[0x1] = 0xFF;
 	machine.V[0x2] = 0xF;
 
-	machine.emulate_cycle();
+	machine.emulate_cycle().unwrap();
 
 	assert_eq!(machine.V[0x1], 0xE);
 	assert_eq!(machine.V[0x2], 0xF);
@@ -304,7 +305,7 @@ fn opcode_8XY5() {
 	machine.V[0x1] = 0x89;
 	machine.V[0x2] = 0x5F;
 
-	machine.emulate_cycle();
+	machine.emulate_cycle().unwrap();
 
 	assert_eq!(machine.V[0x1], 0x2A);
 	assert_eq!(machine.V[0x2], 0x5F);
@@ -321,7 +322,7 @@ fn opcode_8XY5_with_borrow() {
 	machine.V[0x1] = 0x00;
 	machine.V[0x2] = 0xFF;
 
-	machine.emulate_cycle();
+	machine.emulate_cycle().unwrap();
 
 	assert_eq!(machine.V[0x1], 0x1);
 	assert_eq!(machine.V[0x2], 0xFF);
@@ -337,7 +338,7 @@ fn opcode_8XY6_lsb_set() {
 
 	machine.V[0x1] = 0b101;
 
-	machine.emulate_cycle();
+	machine.emulate_cycle().unwrap();
 	
 	assert_eq!(machine.V[0xF], 0b1);
 	assert_eq!(machine.V[0x1], 0b10);
@@ -350,7 +351,7 @@ fn opcode_8XY6_lsb_unset() {
 
 	let previous_pc = machine.pc;
 
-	machine.emulate_cycle();
+	machine.emulate_cycle().unwrap();
 	
 	assert_eq!(machine.V[0xF], 0b0);
 	assert_eq!(machine.V[0x1], 0b0);
@@ -366,7 +367,7 @@ fn opcode_8XY7() {
 	machine.V[0x1] = 0x11;
 	machine.V[0x2] = 0xAA;
 
-	machine.emulate_cycle();
+	machine.emulate_cycle().unwrap();
 
 	assert_eq!(machine.V[0x1], 0x99);
 	assert_eq!(machine.V[0xF], 1);
@@ -382,7 +383,7 @@ fn opcode_8XY7_with_borrow() {
 	machine.V[0x1] = 0x10;
 	machine.V[0x2] = 0xA;
 
-	machine.emulate_cycle();
+	machine.emulate_cycle().unwrap();
 
 	assert_eq!(machine.V[0x1], 0xFA);
 	assert_eq!(machine.V[0xF], 0);
@@ -397,7 +398,7 @@ fn opcode_8XYE_msb_set() {
 
 	machine.V[0x1] = 0b10001001;
 
-	machine.emulate_cycle();
+	machine.emulate_cycle().unwrap();
 
 	assert_eq!(machine.V[0xF], 0b1);
 	assert_eq!(machine.V[0x1], 0b10010);
@@ -412,7 +413,7 @@ fn opcode_8XYE_msb_unset() {
 
 	machine.V[0x1] = 0b00001001;
 
-	machine.emulate_cycle();
+	machine.emulate_cycle().unwrap();
 
 	assert_eq!(machine.V[0xF], 0b0);
 	assert_eq!(machine.V[0x1], 0b10010);
@@ -428,7 +429,7 @@ fn opcode_9XY0_equals() {
 	machine.V[2] = 0x2A;
 	machine.V[3] = 0x2A;
 
-	machine.emulate_cycle();
+	machine.emulate_cycle().unwrap();
 	
 	assert_eq!(machine.pc, previous_pc + 2);
 }
@@ -442,7 +443,7 @@ fn opcode_9XY0_not_equals() {
 	machine.V[2] = 0x2A;
 	machine.V[3] = 0x29;
 
-	machine.emulate_cycle();
+	machine.emulate_cycle().unwrap();
 	
 	assert_eq!(machine.pc, previous_pc + 4);
 }
@@ -453,7 +454,7 @@ fn opcode_ANNN() {
 
 	let previous_pc = machine.pc;
 
-	machine.emulate_cycle();
+	machine.emulate_cycle().unwrap();
 	
 	assert_eq!(machine.I, 0x123);
 	assert_eq!(machine.pc, previous_pc + 2);
@@ -465,7 +466,7 @@ fn opcode_BNNN() {
 
 	machine.V[0x0] = 0x28;
 
-	machine.emulate_cycle();
+	machine.emulate_cycle().unwrap();
 
 	assert_eq!(machine.pc, 0x2A);
 }
@@ -478,7 +479,7 @@ fn opcode_FX07() {
 
 	machine.delay_timer = 0x2A;
 
-	machine.emulate_cycle();
+	machine.emulate_cycle().unwrap();
 
 	assert_eq!(machine.V[0x1], 0x2A);
 	assert_eq!(machine.pc, previous_pc + 2);
@@ -492,9 +493,9 @@ fn opcode_FX15() {
 
 	machine.V[0x0] = 0x2A;
 
-	machine.emulate_cycle();
+	machine.emulate_cycle().unwrap();
 
-	assert_eq!(machine.delay_timer, 0x29);
+	assert_eq!(machine.delay_timer, 0x2A);
 	assert_eq!(machine.V[0x0], 0x2A);
 	assert_eq!(machine.pc, previous_pc + 2);
 }
@@ -507,9 +508,9 @@ fn opcode_FX18() {
 
 	machine.V[0x0] = 0x2A;
 
-	machine.emulate_cycle();
+	machine.emulate_cycle().unwrap();
 
-	assert_eq!(machine.sound_timer, 0x29);
+	assert_eq!(machine.sound_timer, 0x2A);
 	assert_eq!(machine.V[0x0], 0x2A);
 	assert_eq!(machine.pc, previous_pc + 2);
 }
@@ -523,7 +524,7 @@ fn opcode_FX1E() {
 	machine.V[0x3] = 0x2A;
 	machine.I = 0x18;
 
-	machine.emulate_cycle();
+	machine.emulate_cycle().unwrap();
 
 	assert_eq!(machine.I, 0x42);
 	assert_eq!(machine.V[0x3], 0x2A);
@@ -540,10 +541,794 @@ fn opcode_FX1E_with_overflow() {
 	machine.V[0x3] = 0x32;
 	machine.I = 0xFFFE;
 
-	machine.emulate_cycle();
+	machine.emulate_cycle().unwrap();
 
 	assert_eq!(machine.I, 0x30);
 	assert_eq!(machine.V[0x3], 0x32);
 	assert_eq!(machine.V[0xF], 0x0);
 	assert_eq!(machine.pc, previous_pc + 2);
 }
+
+// Quirks
+
+fn get_quirky_machine(initial_instruction: u16, quirks: Quirks) -> CHIP8 {
+	let mut machine = get_default_machine(initial_instruction);
+	machine.set_quirks(quirks);
+
+	machine
+}
+
+#[test]
+fn quirk_shift_8XY6() {
+	let presets = [(Quirks::VIP, 0b1010, 0b0), (Quirks::CHIP48, 0b10, 0b1),
+				   (Quirks::SCHIP, 0b10, 0b1), (Quirks::XOCHIP, 0b1010, 0b0)];
+
+	for &(quirks, expected, carry) in presets.iter() {
+		let mut machine = get_quirky_machine(0x8126, quirks);
+
+		machine.V[0x1] = 0b101;
+		machine.V[0x2] = 0b10100;
+
+		machine.emulate_cycle().unwrap();
+
+		assert_eq!(machine.V[0x1], expected, "{:?}", quirks);
+		assert_eq!(machine.V[0xF], carry, "{:?}", quirks);
+	}
+}
+
+#[test]
+fn quirk_shift_8XYE() {
+	let presets = [(Quirks::VIP, 0b10, 0b1), (Quirks::CHIP48, 0b1010, 0b0),
+				   (Quirks::SCHIP, 0b1010, 0b0), (Quirks::XOCHIP, 0b10, 0b1)];
+
+	for &(quirks, expected, carry) in presets.iter() {
+		let mut machine = get_quirky_machine(0x812E, quirks);
+
+		machine.V[0x1] = 0b101;
+		machine.V[0x2] = 0b10000001;
+
+		machine.emulate_cycle().unwrap();
+
+		assert_eq!(machine.V[0x1], expected, "{:?}", quirks);
+		assert_eq!(machine.V[0xF], carry, "{:?}", quirks);
+	}
+}
+
+#[test]
+fn quirk_index_increment_FX55() {
+	let presets = [(Quirks::VIP, 0x304), (Quirks::CHIP48, 0x303),
+				   (Quirks::SCHIP, 0x300), (Quirks::XOCHIP, 0x304)];
+
+	for &(quirks, expected) in presets.iter() {
+		let mut machine = get_quirky_machine(0xF355, quirks);
+
+		machine.V[0x0..0x4].copy_from_slice(&[1, 2, 3, 4]);
+		machine.I = 0x300;
+
+		machine.emulate_cycle().unwrap();
+
+		assert_eq!(&machine.memory[0x300..0x305], &[1, 2, 3, 4, 0]);
+		assert_eq!(machine.I, expected, "{:?}", quirks);
+	}
+}
+
+#[test]
+fn quirk_index_increment_FX65() {
+	let presets = [(Quirks::VIP, 0x304), (Quirks::CHIP48, 0x303),
+				   (Quirks::SCHIP, 0x300), (Quirks::XOCHIP, 0x304)];
+
+	for &(quirks, expected) in presets.iter() {
+		let mut machine = get_quirky_machine(0xF365, quirks);
+
+		machine.memory[0x300..0x305].copy_from_slice(&[1, 2, 3, 4, 5]);
+		machine.I = 0x300;
+
+		machine.emulate_cycle().unwrap();
+
+		assert_eq!(&machine.V[0x0..0x5], &[1, 2, 3, 4, 0]);
+		assert_eq!(machine.I, expected, "{:?}", quirks);
+	}
+}
+
+#[test]
+fn quirk_jump_vx_BNNN() {
+	let presets = [(Quirks::VIP, 0x312), (Quirks::CHIP48, 0x322),
+				   (Quirks::SCHIP, 0x322), (Quirks::XOCHIP, 0x312)];
+
+	for &(quirks, expected) in presets.iter() {
+		let mut machine = get_quirky_machine(0xB302, quirks);
+
+		machine.V[0x0] = 0x10;
+		machine.V[0x3] = 0x20;
+
+		machine.emulate_cycle().unwrap();
+
+		assert_eq!(machine.pc, expected, "{:?}", quirks);
+	}
+}
+
+#[test]
+fn quirk_clip_DXYN() {
+	let presets = [(Quirks::VIP, false), (Quirks::CHIP48, false),
+				   (Quirks::SCHIP, false), (Quirks::XOCHIP, true)];
+
+	for &(quirks, wraps) in presets.iter() {
+		let mut machine = get_quirky_machine(0xD122, quirks);
+
+		machine.memory[0x300..0x302].copy_from_slice(&[0xFF, 0xFF]);
+		machine.I = 0x300;
+		machine.V[0x1] = 60; // the sprite's right half goes past the right edge
+		machine.V[0x2] = 31; // and its bottom row goes past the bottom edge
+
+		machine.emulate_cycle().unwrap();
+
+		assert_eq!(machine.gfx[31 * 64 + 63], 1, "{:?}", quirks);
+		assert_eq!(machine.gfx[31 * 64], wraps as u8, "{:?}", quirks); // wrapped right
+		assert_eq!(machine.gfx[63], wraps as u8, "{:?}", quirks); // wrapped down
+		assert_eq!(machine.gfx[0], wraps as u8, "{:?}", quirks); // wrapped both
+	}
+}
+
+#[test]
+fn quirk_DXYN_start_position_wraps() {
+	for &quirks in [Quirks::VIP, Quirks::CHIP48, Quirks::SCHIP, Quirks::XOCHIP].iter() {
+		let mut machine = get_quirky_machine(0xD121, quirks);
+
+		machine.memory[0x300] = 0x80;
+		machine.I = 0x300;
+		machine.V[0x1] = 64 + 3;
+		machine.V[0x2] = 32 + 2;
+
+		machine.emulate_cycle().unwrap();
+
+		assert_eq!(machine.gfx[2 * 64 + 3], 1, "{:?}", quirks);
+	}
+}
+
+#[test]
+fn quirk_vf_reset_8XY1_8XY2_8XY3() {
+	let presets = [(Quirks::VIP, 0), (Quirks::CHIP48, 0x2A),
+				   (Quirks::SCHIP, 0x2A), (Quirks::XOCHIP, 0x2A)];
+
+	for &opcode in [0x8121, 0x8122, 0x8123].iter() {
+		for &(quirks, expected) in presets.iter() {
+			let mut machine = get_quirky_machine(opcode, quirks);
+
+			machine.V[0xF] = 0x2A;
+
+			machine.emulate_cycle().unwrap();
+
+			assert_eq!(machine.V[0xF], expected, "{:X} {:?}", opcode, quirks);
+		}
+	}
+}
+
+#[test]
+fn quirk_presets_by_name() {
+	assert_eq!(Quirks::preset("VIP"), Some(Quirks::VIP));
+	assert_eq!(Quirks::preset("chip-48"), Some(Quirks::CHIP48));
+	assert_eq!(Quirks::preset("schip"), Some(Quirks::SCHIP));
+	assert_eq!(Quirks::preset("XO-CHIP"), Some(Quirks::XOCHIP));
+	assert_eq!(Quirks::preset("megachip"), None);
+}
+
+// SUPER-CHIP
+
+#[test]
+fn opcode_00CN() {
+	let mut machine = get_default_machine(0x00C3);
+
+	let previous_pc = machine.pc;
+
+	machine.gfx[5] = 1;
+	machine.gfx[30 * 64 + 7] = 1; // scrolled off the screen
+
+	machine.emulate_cycle().unwrap();
+
+	assert_eq!(machine.gfx[5], 0);
+	assert_eq!(machine.gfx[3 * 64 + 5], 1);
+	assert_eq!(machine.gfx.iter().filter(|&&p| p != 0).count(), 1);
+	assert!(machine.draw_flag);
+	assert_eq!(machine.pc, previous_pc + 2);
+}
+
+#[test]
+fn opcode_00FB() {
+	let mut machine = get_default_machine(0x00FB);
+
+	let previous_pc = machine.pc;
+
+	machine.gfx[64 + 10] = 1;
+	machine.gfx[64 + 62] = 1; // scrolled off the screen
+
+	machine.emulate_cycle().unwrap();
+
+	assert_eq!(machine.gfx[64 + 14], 1);
+	assert_eq!(machine.gfx.iter().filter(|&&p| p != 0).count(), 1);
+	assert_eq!(machine.pc, previous_pc + 2);
+}
+
+#[test]
+fn opcode_00FC() {
+	let mut machine = get_default_machine(0x00FC);
+
+	let previous_pc = machine.pc;
+
+	machine.gfx[64 + 10] = 1;
+	machine.gfx[64 + 2] = 1; // scrolled off the screen
+
+	machine.emulate_cycle().unwrap();
+
+	assert_eq!(machine.gfx[64 + 6], 1);
+	assert_eq!(machine.gfx.iter().filter(|&&p| p != 0).count(), 1);
+	assert_eq!(machine.pc, previous_pc + 2);
+}
+
+#[test]
+fn opcode_00FD() {
+	let mut machine = get_default_machine(0x00FD);
+
+	let previous_pc = machine.pc;
+
+	assert_eq!(machine.emulate_cycle(), Ok(StepOutcome::Exited));
+	assert_eq!(machine.emulate_cycle(), Ok(StepOutcome::Exited));
+
+	assert!(machine.has_exited());
+	assert_eq!(machine.pc, previous_pc);
+}
+
+#[test]
+fn opcode_00FE_00FF() {
+	let mut machine = get_default_machine(0x00FF);
+
+	machine.memory[0x202] = 0x00;
+	machine.memory[0x203] = 0xFE;
+
+	machine.emulate_cycle().unwrap();
+
+	assert_eq!(machine.resolution(), (128, 64));
+	assert_eq!((machine.screen().width(), machine.screen().height()), (128, 64));
+
+	machine.gfx[100] = 1;
+	machine.emulate_cycle().unwrap();
+
+	assert_eq!(machine.resolution(), (64, 32));
+	assert_eq!((machine.screen().width(), machine.screen().height()), (64, 32));
+	assert_eq!(machine.gfx[100], 0); // switching modes clears the screen
+	assert_eq!(machine.pc, 0x204);
+}
+
+#[test]
+fn opcode_DXYN_hires() {
+	let mut machine = get_default_machine(0xD121);
+
+	machine.hires = true;
+	machine.memory[0x300] = 0x81;
+	machine.I = 0x300;
+	machine.V[0x1] = 100;
+	machine.V[0x2] = 50;
+
+	machine.emulate_cycle().unwrap();
+
+	assert_eq!(machine.gfx[50 * 128 + 100], 1);
+	assert_eq!(machine.gfx[50 * 128 + 107], 1);
+	assert_eq!(machine.V[0xF], 0);
+}
+
+#[test]
+fn opcode_DXY0() {
+	let mut machine = get_default_machine(0xD120);
+
+	machine.hires = true;
+	for row in 0 .. 16 {
+		machine.memory[0x300 + row * 2] = 0x80; // leftmost pixel
+		machine.memory[0x300 + row * 2 + 1] = 0x01; // rightmost pixel
+	}
+	machine.I = 0x300;
+	machine.V[0x1] = 10;
+	machine.V[0x2] = 20;
+	machine.gfx[35 * 128 + 25] = 1; // bottom right corner, will collide
+
+	machine.emulate_cycle().unwrap();
+
+	for row in 0 .. 15 {
+		assert_eq!(machine.gfx[(20 + row) * 128 + 10], 1);
+		assert_eq!(machine.gfx[(20 + row) * 128 + 25], 1);
+	}
+	assert_eq!(machine.gfx[35 * 128 + 25], 0);
+	assert_eq!(machine.gfx.iter().filter(|&&p| p != 0).count(), 31);
+	assert_eq!(machine.V[0xF], 1);
+}
+
+#[test]
+fn opcode_FX30() {
+	let mut machine = get_default_machine(0xF330);
+
+	let previous_pc = machine.pc;
+
+	machine.V[0x3] = 0x7;
+
+	machine.emulate_cycle().unwrap();
+
+	assert_eq!(machine.I, 0x50 + 70);
+	assert_eq!(&machine.memory[machine.I as usize .. machine.I as usize + 4], &[0xFF, 0xFF, 0x03, 0x03]);
+	assert_eq!(machine.pc, previous_pc + 2);
+}
+
+#[test]
+fn opcode_FX75_FX85() {
+	let mut machine = get_default_machine(0xF275);
+
+	machine.memory[0x202] = 0xF2;
+	machine.memory[0x203] = 0x85;
+	machine.V[0x0 .. 0x4].copy_from_slice(&[1, 2, 3, 4]);
+
+	machine.emulate_cycle().unwrap();
+
+	assert_eq!(&machine.rpl[0 .. 4], &[1, 2, 3, 0]);
+
+	machine.V = [0; 16];
+	machine.emulate_cycle().unwrap();
+
+	assert_eq!(&machine.V[0 .. 4], &[1, 2, 3, 0]);
+	assert_eq!(machine.pc, 0x204);
+}
+
+// XO-CHIP
+
+fn get_xochip_machine(initial_instruction: u16) -> CHIP8 {
+	let mut machine = CHIP8::with_platform(&[], Platform::XoChip).unwrap();
+
+	machine.memory[machine.pc as usize] = (initial_instruction >> 8) as u8;
+	machine.memory[machine.pc as usize + 1] = initial_instruction as u8;
+
+	machine
+}
+
+#[test]
+fn xochip_memory_size() {
+	assert_eq!(get_default_machine(0x0000).memory.len(), 4096);
+	assert_eq!(get_xochip_machine(0x0000).memory.len(), 65536);
+}
+
+#[test]
+fn opcode_F000_NNNN() {
+	let mut machine = get_xochip_machine(0xF000);
+
+	let previous_pc = machine.pc;
+
+	machine.memory[0x202] = 0xC0;
+	machine.memory[0x203] = 0xDE;
+
+	machine.emulate_cycle().unwrap();
+
+	assert_eq!(machine.I, 0xC0DE);
+	assert_eq!(machine.pc, previous_pc + 4);
+}
+
+#[test]
+fn opcode_F000_NNNN_only_on_xochip() {
+	let mut machine = get_default_machine(0xF000);
+
+	assert_eq!(machine.emulate_cycle(), Err(ExecError::UnknownOpcode { pc: 0x200, opcode: 0xF000 }));
+	assert_eq!(machine.I, 0);
+}
+
+#[test]
+fn xochip_skip_over_F000_NNNN() {
+	let mut machine = get_xochip_machine(0x3100);
+
+	let previous_pc = machine.pc;
+
+	machine.memory[0x202] = 0xF0;
+	machine.memory[0x203] = 0x00;
+
+	machine.emulate_cycle().unwrap();
+
+	assert_eq!(machine.pc, previous_pc + 6);
+}
+
+#[test]
+fn opcode_5XY2() {
+	let mut machine = get_xochip_machine(0x5132);
+
+	let previous_pc = machine.pc;
+
+	machine.V[0x1 ..= 0x3].copy_from_slice(&[1, 2, 3]);
+	machine.I = 0x1000;
+
+	machine.emulate_cycle().unwrap();
+
+	assert_eq!(&machine.memory[0x1000 .. 0x1004], &[1, 2, 3, 0]);
+	assert_eq!(machine.I, 0x1000);
+	assert_eq!(machine.pc, previous_pc + 2);
+}
+
+#[test]
+fn opcode_5XY2_reversed() {
+	let mut machine = get_xochip_machine(0x5312);
+
+	machine.V[0x1 ..= 0x3].copy_from_slice(&[1, 2, 3]);
+	machine.I = 0x1000;
+
+	machine.emulate_cycle().unwrap();
+
+	assert_eq!(&machine.memory[0x1000 .. 0x1003], &[3, 2, 1]);
+}
+
+#[test]
+fn opcode_5XY3() {
+	let mut machine = get_xochip_machine(0x5243);
+
+	let previous_pc = machine.pc;
+
+	machine.memory[0x1000 .. 0x1004].copy_from_slice(&[1, 2, 3, 4]);
+	machine.I = 0x1000;
+
+	machine.emulate_cycle().unwrap();
+
+	assert_eq!(&machine.V[0x1 ..= 0x5], &[0, 1, 2, 3, 0]);
+	assert_eq!(machine.I, 0x1000);
+	assert_eq!(machine.pc, previous_pc + 2);
+}
+
+#[test]
+fn opcode_FN01() {
+	let mut machine = get_xochip_machine(0xF201);
+
+	let previous_pc = machine.pc;
+
+	machine.emulate_cycle().unwrap();
+
+	assert_eq!(machine.plane, 0b10);
+	assert_eq!(machine.pc, previous_pc + 2);
+}
+
+#[test]
+fn xochip_DXYN_both_planes() {
+	let mut machine = get_xochip_machine(0xD011);
+
+	machine.plane = 0b11;
+	machine.memory[0x1000] = 0xC0; // first plane
+	machine.memory[0x1001] = 0x60; // second plane
+	machine.I = 0x1000;
+
+	machine.emulate_cycle().unwrap();
+
+	assert_eq!(&machine.gfx[0 .. 4], &[0b01, 0b11, 0b10, 0b00]);
+	assert_eq!(machine.V[0xF], 0);
+}
+
+#[test]
+fn xochip_00E0_selected_plane() {
+	let mut machine = get_xochip_machine(0x00E0);
+
+	machine.plane = 0b10;
+	machine.gfx[0] = 0b11;
+	machine.gfx[1] = 0b10;
+
+	machine.emulate_cycle().unwrap();
+
+	assert_eq!(&machine.gfx[0 .. 2], &[0b01, 0b00]);
+}
+
+#[test]
+fn opcode_00DN() {
+	let mut machine = get_xochip_machine(0x00D2);
+
+	let previous_pc = machine.pc;
+
+	machine.gfx[3 * 64 + 5] = 1;
+	machine.gfx[64 + 5] = 1; // scrolled off the screen
+
+	machine.emulate_cycle().unwrap();
+
+	assert_eq!(machine.gfx[64 + 5], 1);
+	assert_eq!(machine.gfx.iter().filter(|&&p| p != 0).count(), 1);
+	assert_eq!(machine.pc, previous_pc + 2);
+}
+
+#[cfg(feature = "image")]
+#[test]
+fn xochip_screen_image_colours() {
+	let mut machine = get_xochip_machine(0x0000);
+
+	machine.gfx[0 .. 4].copy_from_slice(&[0b00, 0b01, 0b10, 0b11]);
+
+	let img = machine.create_screen_image();
+	let colours: Vec<_> = (0 .. 4).map(|x| *img.get_pixel(x, 0)).collect();
+
+	for i in 0 .. 4 {
+		for j in i + 1 .. 4 {
+			assert_ne!(colours[i], colours[j]);
+		}
+	}
+}
+
+#[test]
+fn opcode_F002() {
+	let mut machine = get_xochip_machine(0xF002);
+
+	let previous_pc = machine.pc;
+
+	for i in 0 .. 16 {
+		machine.memory[0x1000 + i] = i as u8 * 3;
+	}
+	machine.I = 0x1000;
+
+	machine.emulate_cycle().unwrap();
+
+	assert_eq!(machine.audio_pattern(), &machine.memory[0x1000 .. 0x1010]);
+	assert_eq!(machine.I, 0x1000);
+	assert_eq!(machine.pc, previous_pc + 2);
+}
+
+#[test]
+fn opcode_FX3A() {
+	let mut machine = get_xochip_machine(0xF43A);
+
+	let previous_pc = machine.pc;
+
+	assert_eq!(machine.playback_rate(), 4000.0);
+
+	machine.V[0x4] = 64 + 48; // one octave up
+
+	machine.emulate_cycle().unwrap();
+
+	assert_eq!(machine.pitch, 112);
+	assert!((machine.playback_rate() - 8000.0).abs() < 0.01);
+	assert_eq!(machine.pc, previous_pc + 2);
+}
+
+// Timing
+
+#[test]
+fn emulate_cycle_leaves_timers_alone() {
+	let mut machine = get_default_machine(0x1200); // jump to itself
+
+	machine.delay_timer = 10;
+	machine.sound_timer = 10;
+
+	for _ in 0 .. 100 {
+		machine.emulate_cycle().unwrap();
+	}
+
+	assert_eq!(machine.delay_timer, 10);
+	assert_eq!(machine.sound_timer, 10);
+}
+
+#[test]
+fn run_frame() {
+	let mut machine = get_default_machine(0x7101); // add 1 to V1
+	for i in 1 .. 20 {
+		machine.memory[0x200 + i * 2] = 0x71;
+		machine.memory[0x200 + i * 2 + 1] = 0x01;
+	}
+
+	machine.set_instructions_per_frame(8);
+	machine.delay_timer = 10;
+	machine.sound_timer = 1;
+
+	machine.run_frame(&ErrorPolicy::default()).unwrap();
+
+	assert_eq!(machine.V[0x1], 8);
+	assert_eq!(machine.pc, 0x200 + 16);
+	assert_eq!(machine.delay_timer, 9);
+	assert_eq!(machine.sound_timer, 0);
+	assert!(!machine.is_sound_playing());
+	assert_eq!(machine.frame_count(), 1);
+}
+
+#[test]
+fn timers_tick_at_60_hz() {
+	let mut machine = get_default_machine(0x1200);
+
+	machine.delay_timer = 60;
+
+	for _ in 0 .. 60 { // one second
+		machine.run_frame(&ErrorPolicy::default()).unwrap();
+	}
+
+	assert_eq!(machine.delay_timer, 0);
+	assert_eq!(machine.frame_count(), 60);
+}
+
+// Errors
+
+#[test]
+fn opcode_2NNN_stack_overflow() {
+	let mut machine = get_default_machine(0x2200); // calls itself forever
+
+	for _ in 0 .. 16 {
+		machine.emulate_cycle().unwrap();
+	}
+
+	assert_eq!(machine.emulate_cycle(), Err(ExecError::StackOverflow { pc: 0x200, opcode: 0x2200 }));
+	assert_eq!(machine.sp, 16);
+}
+
+#[test]
+fn unknown_opcode() {
+	let mut machine = get_default_machine(0x8128);
+
+	assert_eq!(machine.emulate_cycle(), Err(ExecError::UnknownOpcode { pc: 0x200, opcode: 0x8128 }));
+	assert_eq!(machine.pc, 0x200);
+}
+
+#[test]
+fn opcode_FX0A_waiting() {
+	let mut machine = get_default_machine(0xF30A);
+
+	assert_eq!(machine.emulate_cycle(), Ok(StepOutcome::WaitingForKey));
+	assert_eq!(machine.pc, 0x200);
+
+	machine.key[0x7] = 1;
+
+	assert_eq!(machine.emulate_cycle(), Ok(StepOutcome::Executed));
+	assert_eq!(machine.V[0x3], 0x7);
+	assert_eq!(machine.pc, 0x202);
+}
+
+#[test]
+fn opcode_EX9E_EXA1() {
+	let mut machine = get_default_machine(0xE59E);
+
+	machine.V[0x5] = 0xB;
+	machine.memory[0x202 .. 0x204].copy_from_slice(&[0xE5, 0xA1]);
+	machine.set_key(Chip8Key::KB, true);
+
+	machine.emulate_cycle().unwrap();
+	assert_eq!(machine.pc, 0x204); // skipped, B is down
+
+	machine.pc = 0x202;
+	machine.emulate_cycle().unwrap();
+	assert_eq!(machine.pc, 0x204); // not skipped
+
+	machine.set_key(Chip8Key::KB, false);
+	assert!(!machine.is_key_pressed(Chip8Key::KB));
+
+	machine.pc = 0x202;
+	machine.emulate_cycle().unwrap();
+	assert_eq!(machine.pc, 0x206);
+}
+
+#[test]
+fn memory_out_of_bounds() {
+	for &(opcode, address) in [(0xF033, 0x1001), (0xF355, 0x1002), (0xF365, 0x1002), (0xD123, 0x1001)].iter() {
+		let mut machine = get_default_machine(opcode);
+
+		machine.I = 0xFFF;
+
+		assert_eq!(machine.emulate_cycle(), Err(ExecError::MemoryOutOfBounds { pc: 0x200, opcode: Some(opcode), address }));
+		assert_eq!(machine.pc, 0x200);
+	}
+}
+
+#[test]
+fn fetch_out_of_bounds() {
+	let mut machine = get_default_machine(0x1FFF);
+
+	machine.emulate_cycle().unwrap();
+
+	assert_eq!(machine.emulate_cycle(), Err(ExecError::MemoryOutOfBounds { pc: 0xFFF, opcode: None, address: 0x1000 }));
+	assert_eq!(machine.emulate_cycle().unwrap_err().to_string(), "---- at FFF accessed memory out of bounds at 1000");
+}
+
+#[test]
+fn end_of_memory() {
+	// XO-CHIP's memory ends where pc does, so moving on from the last instruction has nowhere to go
+	for &(pc, opcode, address) in [(0xFFFE, 0x6123, 0x10000), (0xFFFC, 0x3100, 0x10000), (0xFFFC, 0xF000, 0x10000)].iter() {
+		let mut machine = get_xochip_machine(0x0000);
+		machine.pc = pc;
+		machine.memory[pc as usize] = (opcode >> 8) as u8;
+		machine.memory[pc as usize + 1] = opcode as u8;
+
+		assert_eq!(machine.emulate_cycle(), Err(ExecError::MemoryOutOfBounds { pc, opcode: Some(opcode), address }));
+		assert_eq!((machine.pc, machine.V[1], machine.I), (pc, 0, 0));
+	}
+
+	// jumping away from there is fine
+	let mut machine = get_xochip_machine(0x0000);
+	machine.pc = 0xFFFE;
+	machine.memory[0xFFFE .. 0x10000].copy_from_slice(&[0x12, 0x00]);
+	machine.emulate_cycle().unwrap();
+	assert_eq!(machine.pc, 0x200);
+}
+
+#[test]
+fn run_frame_error_policy() {
+	let halt = ErrorPolicy { unknown_opcode: ErrorAction::Halt, .. ErrorPolicy::default() };
+	let ignore = ErrorPolicy { unknown_opcode: ErrorAction::Ignore, .. ErrorPolicy::default() };
+
+	let mut machine = get_default_machine(0xFFFF);
+	machine.delay_timer = 10;
+
+	assert_eq!(machine.run_frame(&halt), Err(ExecError::UnknownOpcode { pc: 0x200, opcode: 0xFFFF }));
+	assert_eq!(machine.pc, 0x200);
+	assert_eq!(machine.delay_timer, 10); // the frame didn't finish
+
+	assert_eq!(machine.run_frame(&ignore), Ok(()));
+	assert_eq!(machine.pc, 0x200 + 2 * machine.instructions_per_frame() as u16); // skipped over the empty memory after it
+	assert_eq!(machine.delay_timer, 9);
+
+	// running off the end of memory halts whatever the policy says
+	let mut machine = get_default_machine(0x1FFF);
+	assert_eq!(machine.run_frame(&ErrorPolicy::parse("ignore").unwrap()), Err(ExecError::MemoryOutOfBounds { pc: 0xFFF, opcode: None, address: 0x1000 }));
+}
+
+#[test]
+fn error_policies_are_parsed() {
+	assert_eq!(ErrorPolicy::parse("halt"), Ok(ErrorPolicy { unknown_opcode: ErrorAction::Halt, .. ErrorPolicy::default() }));
+	assert_eq!(ErrorPolicy::parse("log, stack-underflow=ignore").unwrap(), ErrorPolicy {
+		unknown_opcode: ErrorAction::Log,
+		stack_overflow: ErrorAction::Log,
+		stack_underflow: ErrorAction::Ignore,
+		memory_out_of_bounds: ErrorAction::Log
+	});
+
+	assert!(ErrorPolicy::parse("skip").is_err());
+	assert!(ErrorPolicy::parse("overflow=log").is_err());
+}
+
+// Decode cache
+
+#[test]
+fn self_modifying_code() {
+	// the loop at 206 runs 6101, then F055 turns it into 6201 before it runs the second time around
+	let mut machine = get_default_machine(0x1206);
+
+	machine.memory[0x206 .. 0x20E].copy_from_slice(&[0x61, 0x01, 0xA2, 0x06, 0x60, 0x62, 0xF0, 0x55]);
+	machine.memory[0x20E .. 0x210].copy_from_slice(&[0x12, 0x06]);
+
+	machine.emulate_cycle().unwrap(); // 1206
+	machine.emulate_cycle().unwrap(); // 6101
+	assert_eq!(machine.V[0x1], 0x01);
+
+	for _ in 0 .. 4 { // A206, 6062, F055, 1206
+		machine.emulate_cycle().unwrap();
+	}
+	assert_eq!(machine.memory[0x206], 0x62);
+
+	machine.emulate_cycle().unwrap(); // now 6201 instead of 6101
+	assert_eq!(machine.V[0x2], 0x01);
+	assert_eq!(machine.pc, 0x208);
+}
+
+#[test]
+fn bcd_invalidates_the_instruction_it_overwrites() {
+	let mut machine = get_default_machine(0x6000);
+
+	machine.memory[0x202 .. 0x204].copy_from_slice(&[0x71, 0x01]); // V1 += 1
+	machine.emulate_cycle().unwrap();
+	machine.emulate_cycle().unwrap();
+	assert_eq!(machine.V[0x1], 0x01);
+
+	// FX33 with Vx = 255 writes 02 05 05, covering the second byte of 7101 and the whole next word
+	machine.V[0x3] = 255;
+	machine.I = 0x203;
+	machine.memory[0x204 .. 0x206].copy_from_slice(&[0xF3, 0x33]);
+	machine.emulate_cycle().unwrap();
+	assert_eq!(&machine.memory[0x202 .. 0x206], &[0x71, 0x02, 0x05, 0x05]);
+
+	machine.pc = 0x202;
+	machine.emulate_cycle().unwrap();
+	assert_eq!(machine.V[0x1], 0x03);
+}
+
+// Memory accesses
+
+#[test]
+fn instructions_record_the_memory_they_access() {
+	let mut machine = get_default_machine(0xF255); // save V0 to V2
+	machine.I = 0x300;
+
+	machine.emulate_cycle().unwrap();
+	assert_eq!(machine.last_access(), Some(MemoryAccess { kind: AccessKind::Write, start: 0x300, len: 3 }));
+
+	machine.memory[0x202 .. 0x206].copy_from_slice(&[0xD0, 0x14, 0x60, 0x01]); // draw 4 rows from I, then V0 = 1
+	machine.emulate_cycle().unwrap();
+	assert_eq!(machine.last_access(), Some(MemoryAccess { kind: AccessKind::Read, start: 0x303, len: 4 }));
+
+	machine.emulate_cycle().unwrap();
+	assert_eq!(machine.last_access(), None);
+}
@@ -0,0 +1,38 @@
+use crate::Quirks;
+
+// The CHIP-8 variant a program was written for. Decides how much memory the machine has and which
+// of the extended instructions are available
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Platform {
+	Chip8,
+	SuperChip,
+	XoChip // Octo's extension, with 64 KiB of memory and two bitplanes
+}
+
+impl Platform {
+	pub fn memory_size(self) -> usize {
+		match self {
+			Platform::Chip8 | Platform::SuperChip => 4096,
+			Platform::XoChip => 65536
+		}
+	}
+
+	// The quirks programs for this platform usually expect
+	pub fn quirks(self) -> Quirks {
+		match self {
+			Platform::Chip8 => Quirks::VIP,
+			Platform::SuperChip => Quirks::SCHIP,
+			Platform::XoChip => Quirks::XOCHIP
+		}
+	}
+
+	// Guesses the platform from a ROM's file extension, as used by Octo and most ROM archives
+	pub fn from_extension(ext: &str) -> Option<Platform> {
+		match ext.to_ascii_lowercase().as_str() {
+			"ch8" | "c8" => Some(Platform::Chip8),
+			"sc8" => Some(Platform::SuperChip),
+			"xo8" => Some(Platform::XoChip),
+			_ => None
+		}
+	}
+}
@@ -0,0 +1,81 @@
+// Several instructions were implemented differently by the interpreters that followed the COSMAC VIP,
+// and ROMs written for one of them often break on the others. Quirks collects every one of those
+// differences so the interpreter can be told which behaviour a program expects.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Quirks {
+	pub shift: bool, // 8XY6/8XYE shift Vx in place. when false Vy is shifted and the result stored in Vx
+	pub index_increment: IndexIncrement, // what FX55/FX65 do to I after transferring the registers
+	pub jump_vx: bool, // BNNN is read as BXNN and jumps to XNN + Vx. when false it jumps to NNN + V0
+	pub clip: bool, // DXYN cuts sprites off at the edges of the screen. when false they wrap around
+	pub vf_reset: bool // 8XY1/8XY2/8XY3 set Vf to 0
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum IndexIncrement {
+	Unchanged, // I is left alone
+	X, // I is increased by X
+	XPlusOne // I is increased by X + 1, ending up right after the last byte transferred
+}
+
+impl Quirks {
+	// The original interpreter for the COSMAC VIP
+	pub const VIP: Quirks = Quirks {
+		shift: false,
+		index_increment: IndexIncrement::XPlusOne,
+		jump_vx: false,
+		clip: true,
+		vf_reset: true
+	};
+
+	// CHIP-48 for the HP-48 calculators
+	pub const CHIP48: Quirks = Quirks {
+		shift: true,
+		index_increment: IndexIncrement::X,
+		jump_vx: true,
+		clip: true,
+		vf_reset: false
+	};
+
+	// SUPER-CHIP 1.1, the successor of CHIP-48
+	pub const SCHIP: Quirks = Quirks {
+		shift: true,
+		index_increment: IndexIncrement::Unchanged,
+		jump_vx: true,
+		clip: true,
+		vf_reset: false
+	};
+
+	// XO-CHIP as implemented by Octo
+	pub const XOCHIP: Quirks = Quirks {
+		shift: false,
+		index_increment: IndexIncrement::XPlusOne,
+		jump_vx: false,
+		clip: false,
+		vf_reset: false
+	};
+
+	// Looks up a preset by name, ignoring case
+	pub fn preset(name: &str) -> Option<Quirks> {
+		match name.to_ascii_lowercase().as_str() {
+			"vip" | "chip8" | "chip-8" => Some(Quirks::VIP),
+			"chip48" | "chip-48" => Some(Quirks::CHIP48),
+			"schip" | "superchip" | "schip1.1" => Some(Quirks::SCHIP),
+			"xochip" | "xo-chip" => Some(Quirks::XOCHIP),
+			_ => None
+		}
+	}
+}
+
+// The behaviour this interpreter had before quirks could be configured, except for FX55/FX65. Those used to
+// add X to I because of an operator precedence bug, and now add X + 1 like the COSMAC VIP
+impl Default for Quirks {
+	fn default() -> Quirks {
+		Quirks {
+			shift: true,
+			index_increment: IndexIncrement::XPlusOne,
+			jump_vx: false,
+			clip: false,
+			vf_reset: false
+		}
+	}
+}
@@ -0,0 +1,267 @@
+use crate::{ CPU::CHIP8, Quirks, IndexIncrement, Platform, HIRES_W, HIRES_H };
+
+use std::{ error::Error, fmt };
+
+#[cfg(test)]
+mod tests;
+
+// Save states start with the magic bytes and the format's version, followed by the hash of the ROM
+// they were made with and the machine's settings, then every piece of the machine in the order
+// save_state writes them. All numbers are big-endian
+const MAGIC: &[u8; 4] = b"C8ST";
+const VERSION: u16 = 1;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum StateError {
+	BadMagic, // not a save state at all
+	UnsupportedVersion(u16), // made by a newer version of the emulator
+	RomMismatch { expected: u64, found: u64 }, // made while running a different program
+	Truncated, // the data ends before the state does
+	Corrupt(&'static str) // one of the values in the state is impossible
+}
+
+impl fmt::Display for StateError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			StateError::BadMagic => write!(f, "not a save state"),
+			StateError::UnsupportedVersion(v) => write!(f, "save state version {} isn't supported, the newest is {}", v, VERSION),
+			StateError::RomMismatch { expected, found } =>
+				write!(f, "save state is for a different ROM (hash {:016X}, this one is {:016X})", found, expected),
+			StateError::Truncated => write!(f, "save state is truncated"),
+			StateError::Corrupt(what) => write!(f, "save state is corrupt: bad {}", what)
+		}
+	}
+}
+
+impl Error for StateError {}
+
+// Hashes a ROM with 64 bit FNV-1a, to tell which program a save state belongs to
+pub fn rom_hash(rom: &[u8]) -> u64 {
+	rom.iter().fold(0xCBF2_9CE4_8422_2325, |hash, &byte| (hash ^ byte as u64).wrapping_mul(0x0000_0100_0000_01B3))
+}
+
+impl CHIP8 {
+	// Serialises the whole machine, so it can be restored later with load_state
+	pub fn save_state(&self) -> Vec<u8> {
+		let mut out = Vec::with_capacity(self.memory.len() + self.gfx.len() + 256);
+
+		out.extend_from_slice(MAGIC);
+		out.extend_from_slice(&VERSION.to_be_bytes());
+		out.extend_from_slice(&self.rom_hash.to_be_bytes());
+
+		// settings
+		out.push(platform_to_byte(self.platform));
+		out.push(self.quirks.shift as u8);
+		out.push(index_increment_to_byte(self.quirks.index_increment));
+		out.push(self.quirks.jump_vx as u8);
+		out.push(self.quirks.clip as u8);
+		out.push(self.quirks.vf_reset as u8);
+		out.extend_from_slice(&self.instructions_per_frame.to_be_bytes());
+
+		// cpu
+		out.extend_from_slice(&self.opcode.to_be_bytes());
+		out.extend_from_slice(&self.V);
+		out.extend_from_slice(&self.I.to_be_bytes());
+		out.extend_from_slice(&self.pc.to_be_bytes());
+		for address in self.stack.iter() {
+			out.extend_from_slice(&address.to_be_bytes());
+		}
+		out.extend_from_slice(&self.sp.to_be_bytes());
+		out.extend_from_slice(&self.rpl);
+		out.push(self.exited as u8);
+
+		// timers, sound and input
+		out.push(self.delay_timer);
+		out.push(self.sound_timer);
+		out.extend_from_slice(&self.pattern);
+		out.push(self.pitch);
+		out.extend_from_slice(&self.key);
+		out.extend_from_slice(&self.frame_count.to_be_bytes());
+
+		// display
+		out.push(self.hires as u8);
+		out.push(self.plane);
+		out.extend_from_slice(&self.gfx);
+
+		// memory
+		out.extend_from_slice(&(self.memory.len() as u32).to_be_bytes());
+		out.extend_from_slice(&self.memory);
+
+		out
+	}
+
+	// Restores a state made by save_state. The state must have been made with the ROM this machine is
+	// running. If it can't be loaded the machine is left untouched
+	pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+		let mut r = Reader { data, pos: 0 };
+
+		if r.bytes(4).map_err(|_| StateError::BadMagic)? != MAGIC {
+			return Err(StateError::BadMagic);
+		}
+		let version = r.u16()?;
+		if version != VERSION {
+			return Err(StateError::UnsupportedVersion(version));
+		}
+		let hash = r.u64()?;
+		if hash != self.rom_hash {
+			return Err(StateError::RomMismatch { expected: self.rom_hash, found: hash });
+		}
+
+		let platform = platform_from_byte(r.u8()?)?;
+		let quirks = Quirks {
+			shift: r.bool()?,
+			index_increment: index_increment_from_byte(r.u8()?)?,
+			jump_vx: r.bool()?,
+			clip: r.bool()?,
+			vf_reset: r.bool()?
+		};
+		let instructions_per_frame = r.u32()?;
+
+		let opcode = r.u16()?;
+		let mut v = [0; 16];
+		v.copy_from_slice(r.bytes(16)?);
+		let i = r.u16()?;
+		let pc = r.u16()?;
+		let mut stack = [0; 16];
+		for address in stack.iter_mut() {
+			*address = r.u16()?;
+		}
+		let sp = r.u16()?;
+		if sp as usize > stack.len() {
+			return Err(StateError::Corrupt("stack pointer"));
+		}
+		let mut rpl = [0; 16];
+		rpl.copy_from_slice(r.bytes(16)?);
+		let exited = r.bool()?;
+
+		let delay_timer = r.u8()?;
+		let sound_timer = r.u8()?;
+		let mut pattern = [0; 16];
+		pattern.copy_from_slice(r.bytes(16)?);
+		let pitch = r.u8()?;
+		r.bytes(16)?; // the keys held down then. the host's keyboard says which are down now
+		let frame_count = r.u64()?;
+
+		let hires = r.bool()?;
+		let plane = r.u8()?;
+		if plane > 0b11 {
+			return Err(StateError::Corrupt("plane"));
+		}
+		let gfx = r.bytes(HIRES_W as usize * HIRES_H as usize)?;
+
+		let memory_size = r.u32()? as usize;
+		if memory_size != platform.memory_size() {
+			return Err(StateError::Corrupt("memory size"));
+		}
+		let memory = r.bytes(memory_size)?;
+
+		// everything was read, it's safe to change the machine now
+		self.platform = platform;
+		self.quirks = quirks;
+		self.instructions_per_frame = instructions_per_frame;
+		self.opcode = opcode;
+		self.V = v;
+		self.I = i;
+		self.pc = pc;
+		self.stack = stack;
+		self.sp = sp;
+		self.rpl = rpl;
+		self.exited = exited;
+		self.delay_timer = delay_timer;
+		self.sound_timer = sound_timer;
+		self.pattern = pattern;
+		self.pitch = pitch;
+		self.frame_count = frame_count;
+		self.hires = hires;
+		self.plane = plane;
+		self.gfx.copy_from_slice(gfx);
+		self.memory = memory.to_vec();
+		self.decoded = vec![None; memory_size];
+		self.draw_flag = true; // the screen has to show the restored frame
+
+		Ok(())
+	}
+
+	// The hash of the loaded ROM, as stored in save states
+	pub fn rom_hash(&self) -> u64 {
+		self.rom_hash
+	}
+}
+
+fn platform_to_byte(platform: Platform) -> u8 {
+	match platform {
+		Platform::Chip8 => 0,
+		Platform::SuperChip => 1,
+		Platform::XoChip => 2
+	}
+}
+
+fn platform_from_byte(byte: u8) -> Result<Platform, StateError> {
+	match byte {
+		0 => Ok(Platform::Chip8),
+		1 => Ok(Platform::SuperChip),
+		2 => Ok(Platform::XoChip),
+		_ => Err(StateError::Corrupt("platform"))
+	}
+}
+
+fn index_increment_to_byte(increment: IndexIncrement) -> u8 {
+	match increment {
+		IndexIncrement::Unchanged => 0,
+		IndexIncrement::X => 1,
+		IndexIncrement::XPlusOne => 2
+	}
+}
+
+fn index_increment_from_byte(byte: u8) -> Result<IndexIncrement, StateError> {
+	match byte {
+		0 => Ok(IndexIncrement::Unchanged),
+		1 => Ok(IndexIncrement::X),
+		2 => Ok(IndexIncrement::XPlusOne),
+		_ => Err(StateError::Corrupt("quirks"))
+	}
+}
+
+// Reads big-endian values from the front of a save state
+struct Reader<'a> {
+	data: &'a [u8],
+	pos: usize
+}
+
+impl<'a> Reader<'a> {
+	fn bytes(&mut self, n: usize) -> Result<&'a [u8], StateError> {
+		let bytes = self.data.get(self.pos .. self.pos + n).ok_or(StateError::Truncated)?;
+		self.pos += n;
+		Ok(bytes)
+	}
+
+	fn u8(&mut self) -> Result<u8, StateError> {
+		Ok(self.bytes(1)?[0])
+	}
+
+	fn bool(&mut self) -> Result<bool, StateError> {
+		match self.u8()? {
+			0 => Ok(false),
+			1 => Ok(true),
+			_ => Err(StateError::Corrupt("flag"))
+		}
+	}
+
+	fn u16(&mut self) -> Result<u16, StateError> {
+		let mut buf = [0; 2];
+		buf.copy_from_slice(self.bytes(2)?);
+		Ok(u16::from_be_bytes(buf))
+	}
+
+	fn u32(&mut self) -> Result<u32, StateError> {
+		let mut buf = [0; 4];
+		buf.copy_from_slice(self.bytes(4)?);
+		Ok(u32::from_be_bytes(buf))
+	}
+
+	fn u64(&mut self) -> Result<u64, StateError> {
+		let mut buf = [0; 8];
+		buf.copy_from_slice(self.bytes(8)?);
+		Ok(u64::from_be_bytes(buf))
+	}
+}
@@ -0,0 +1,94 @@
+use crate::{ CHIP8, Quirks, Platform, StateError };
+
+fn get_busy_machine() -> CHIP8 {
+	let mut machine = CHIP8::new(&[]).unwrap();
+
+	machine.memory[0x300 .. 0x304].copy_from_slice(&[0xDE, 0xAD, 0xBE, 0xEF]);
+	machine.V[0x3] = 0x2A;
+	machine.I = 0x300;
+	machine.pc = 0x244;
+	machine.stack[0] = 0x222;
+	machine.sp = 1;
+	machine.gfx[100] = 1;
+	machine.hires = true;
+	machine.delay_timer = 30;
+	machine.sound_timer = 5;
+	machine.key[0xA] = 1;
+	machine.rpl[2] = 7;
+	machine.frame_count = 1234;
+	machine.set_quirks(Quirks::SCHIP);
+
+	machine
+}
+
+#[test]
+fn save_and_load_state() {
+	let original = get_busy_machine();
+	let state = original.save_state();
+
+	let mut machine = CHIP8::new(&[]).unwrap();
+	machine.load_state(&state).unwrap();
+
+	assert_eq!(machine.memory, original.memory);
+	assert_eq!(machine.V, original.V);
+	assert_eq!(machine.I, 0x300);
+	assert_eq!(machine.pc, 0x244);
+	assert_eq!(machine.stack, original.stack);
+	assert_eq!(machine.sp, 1);
+	assert_eq!(&machine.gfx[..], &original.gfx[..]);
+	assert!(machine.hires);
+	assert_eq!(machine.delay_timer, 30);
+	assert_eq!(machine.sound_timer, 5);
+	assert_eq!(machine.key, [0; 16]); // the keys stay as the frontend set them
+	assert_eq!(machine.rpl, original.rpl);
+	assert_eq!(machine.frame_count(), 1234);
+	assert_eq!(machine.quirks(), Quirks::SCHIP);
+	assert!(machine.draw_flag);
+
+	machine.key = original.key;
+	assert_eq!(machine.save_state(), state);
+}
+
+#[test]
+fn save_and_load_state_xochip() {
+	let mut original = CHIP8::with_platform(&[], Platform::XoChip).unwrap();
+	original.memory[0xFFFF] = 0x42;
+	original.plane = 0b11;
+	original.pitch = 100;
+
+	let mut machine = CHIP8::new(&[]).unwrap();
+	machine.load_state(&original.save_state()).unwrap();
+
+	assert_eq!(machine.platform(), Platform::XoChip);
+	assert_eq!(machine.memory.len(), 65536);
+	assert_eq!(machine.memory[0xFFFF], 0x42);
+	assert_eq!(machine.plane, 0b11);
+	assert_eq!(machine.pitch, 100);
+}
+
+#[test]
+fn load_state_for_another_rom() {
+	let mut original = get_busy_machine();
+	original.rom_hash = 0x1234;
+	let state = original.save_state();
+
+	let mut machine = CHIP8::new(&[]).unwrap();
+	let hash = machine.rom_hash();
+
+	assert_eq!(machine.load_state(&state), Err(StateError::RomMismatch { expected: hash, found: 0x1234 }));
+	assert_eq!(machine.pc, 0x200); // nothing was loaded
+}
+
+#[test]
+fn load_state_bad_data() {
+	let mut machine = CHIP8::new(&[]).unwrap();
+	let mut state = get_busy_machine().save_state();
+
+	assert_eq!(machine.load_state(b"PNG"), Err(StateError::BadMagic));
+	assert_eq!(machine.load_state(b"\x89PNG\r\n\x1a\n"), Err(StateError::BadMagic));
+	assert_eq!(machine.load_state(&state[.. state.len() - 1]), Err(StateError::Truncated));
+
+	state[5] = 99; // version
+	assert_eq!(machine.load_state(&state), Err(StateError::UnsupportedVersion(99)));
+	assert_eq!(machine.pc, 0x200);
+}
@@ -0,0 +1,791 @@
+use std::{
+	collections::{ BTreeMap, HashMap },
+	error::Error,
+	fmt,
+	fs,
+	io,
+	path::{ Path, PathBuf }
+};
+
+#[cfg(test)]
+mod tests;
+
+// Where programs are loaded, the first instruction is assembled here
+const PROGRAM_START: u16 = 0x200;
+
+// Stops macros that expand into themselves
+const MAX_MACRO_DEPTH: usize = 64;
+
+// An error in the source, with the line it's on. Line numbers start at 1
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct AsmError {
+	pub line: usize,
+	pub message: String
+}
+
+impl fmt::Display for AsmError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "line {}: {}", self.line, self.message)
+	}
+}
+
+impl Error for AsmError {}
+
+// Which line of the source each instruction was assembled from
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct SourceMap {
+	lines: BTreeMap<u16, usize>
+}
+
+impl SourceMap {
+	pub fn line(&self, addr: u16) -> Option<usize> {
+		self.lines.get(&addr).copied()
+	}
+
+	// The address of the first instruction assembled from a line
+	pub fn address(&self, line: usize) -> Option<u16> {
+		self.lines.iter().find(|&(_, &l)| l == line).map(|(&addr, _)| addr)
+	}
+
+	pub fn iter(&self) -> impl Iterator<Item = (u16, usize)> + '_ {
+		self.lines.iter().map(|(&addr, &line)| (addr, line))
+	}
+
+	// The map as text, one "<address> <line>" pair per line, the way it's saved next to a ROM
+	pub fn to_text(&self) -> String {
+		self.lines.iter().map(|(addr, line)| format!("{:03X} {}\n", addr, line)).collect()
+	}
+
+	pub fn parse(text: &str) -> Result<SourceMap, String> {
+		let mut map = SourceMap::default();
+
+		for (number, line) in text.lines().enumerate().filter(|(_, l)| !l.trim().is_empty()) {
+			let mut parts = line.split_whitespace();
+			let entry = match (parts.next(), parts.next(), parts.next()) {
+				(Some(addr), Some(source_line), None) =>
+					u16::from_str_radix(addr, 16).ok().zip(source_line.parse().ok()),
+				_ => None
+			};
+
+			match entry {
+				Some((addr, source_line)) => { map.lines.insert(addr, source_line); },
+				None => return Err(format!("bad source map entry on line {}: {}", number + 1, line))
+			}
+		}
+
+		Ok(map)
+	}
+
+	pub fn load(path: &Path) -> io::Result<SourceMap> {
+		SourceMap::parse(&fs::read_to_string(path)?).map_err(|e| io::Error::new(io::ErrorKind::InvalidData, e))
+	}
+
+	pub fn save(&self, path: &Path) -> io::Result<()> {
+		fs::write(path, self.to_text())
+	}
+}
+
+// The source map of a ROM is kept next to it, as <rom>.map
+pub fn source_map_path(rom_path: &str) -> PathBuf {
+	PathBuf::from(format!("{}.map", rom_path))
+}
+
+// A program assembled into the image to load at 0x200
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Assembly {
+	pub rom: Vec<u8>,
+	pub source_map: SourceMap
+}
+
+// Assembles Octo source. Supports labels, :const, :alias, :macro, :org, :byte, :call, loop/again/while,
+// if ... then and if ... begin/else/end, and bare numbers as data. Like Octo, the program starts with a
+// jump to the main label, left out when main comes first
+pub fn assemble(source: &str) -> Result<Assembly, AsmError> {
+	let mut assembler = Assembler::new(tokenize(source));
+	assembler.run()?;
+	assembler.finish()
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+struct Token {
+	text: String,
+	line: usize
+}
+
+fn tokenize(source: &str) -> Vec<Token> {
+	source.lines().enumerate()
+		.flat_map(|(number, line)| {
+			let code = line.split('#').next().unwrap();
+			code.split_whitespace().map(move |text| Token { text: text.to_string(), line: number + 1 })
+		})
+		.collect()
+}
+
+#[derive(Clone)]
+struct Macro {
+	params: Vec<String>,
+	body: Vec<Token>
+}
+
+// Where an address that isn't known yet has to be filled in
+enum Fixup {
+	Low12 { at: u16, name: String, line: usize }, // the NNN of an opcode
+	Word { at: u16, name: String, line: usize } // a whole 16 bit word, for i := long
+}
+
+// The blocks that are still open
+enum Block {
+	Loop { start: u16, breaks: Vec<u16> }, // the jumps out of the loop left by while
+	If { jump: u16 }, // the jump over the if's body
+	Else { jump: u16 } // the jump over the else's body
+}
+
+// The conditions if and while test
+enum Condition {
+	EqualByte(u8, u8),
+	NotEqualByte(u8, u8),
+	Equal(u8, u8),
+	NotEqual(u8, u8),
+	Key(u8),
+	NotKey(u8)
+}
+
+impl Condition {
+	// The instruction that skips the next one when the condition is true
+	fn skip_when_true(&self) -> u16 {
+		match *self {
+			Condition::EqualByte(x, n) => 0x3000 | (x as u16) << 8 | n as u16,
+			Condition::NotEqualByte(x, n) => 0x4000 | (x as u16) << 8 | n as u16,
+			Condition::Equal(x, y) => 0x5000 | (x as u16) << 8 | (y as u16) << 4,
+			Condition::NotEqual(x, y) => 0x9000 | (x as u16) << 8 | (y as u16) << 4,
+			Condition::Key(x) => 0xE09E | (x as u16) << 8,
+			Condition::NotKey(x) => 0xE0A1 | (x as u16) << 8
+		}
+	}
+
+	fn negated(&self) -> Condition {
+		match *self {
+			Condition::EqualByte(x, n) => Condition::NotEqualByte(x, n),
+			Condition::NotEqualByte(x, n) => Condition::EqualByte(x, n),
+			Condition::Equal(x, y) => Condition::NotEqual(x, y),
+			Condition::NotEqual(x, y) => Condition::Equal(x, y),
+			Condition::Key(x) => Condition::NotKey(x),
+			Condition::NotKey(x) => Condition::Key(x)
+		}
+	}
+}
+
+struct Assembler {
+	tokens: Vec<Token>,
+	pos: usize,
+	line: usize, // the line of the statement being assembled
+	rom: Vec<u8>,
+	here: u16, // the address the next byte goes to
+	labels: HashMap<String, u16>,
+	consts: HashMap<String, i64>,
+	aliases: HashMap<String, u8>,
+	macros: HashMap<String, Macro>,
+	macro_depth: usize,
+	fixups: Vec<Fixup>,
+	blocks: Vec<Block>,
+	source_map: SourceMap,
+	jump_to_main: bool // whether 200 holds a jump to main
+}
+
+impl Assembler {
+	fn new(tokens: Vec<Token>) -> Assembler {
+		Assembler {
+			tokens,
+			pos: 0,
+			line: 1,
+			rom: vec![0x10, 0x00], // jump main, filled in once main is known
+			here: PROGRAM_START + 2,
+			labels: HashMap::new(),
+			consts: HashMap::new(),
+			aliases: HashMap::new(),
+			macros: HashMap::new(),
+			macro_depth: 0,
+			fixups: Vec::new(),
+			blocks: Vec::new(),
+			source_map: SourceMap::default(),
+			jump_to_main: true
+		}
+	}
+
+	fn error<T>(&self, message: String) -> Result<T, AsmError> {
+		Err(AsmError { line: self.line, message })
+	}
+
+	fn next(&mut self) -> Result<String, AsmError> {
+		match self.tokens.get(self.pos) {
+			Some(token) => {
+				self.pos += 1;
+				self.line = token.line;
+				Ok(token.text.clone())
+			},
+			None => self.error("unexpected end of the source".to_string())
+		}
+	}
+
+	fn peek(&self) -> Option<&str> {
+		self.tokens.get(self.pos).map(|t| t.text.as_str())
+	}
+
+	fn expect(&mut self, expected: &str) -> Result<(), AsmError> {
+		let token = self.next()?;
+		if token == expected {
+			Ok(())
+		} else {
+			self.error(format!("expected {}, found {}", expected, token))
+		}
+	}
+
+	fn run(&mut self) -> Result<(), AsmError> {
+		while self.pos < self.tokens.len() {
+			self.statement()?;
+		}
+
+		match self.blocks.last() {
+			Some(Block::Loop { .. }) => self.error("loop without again".to_string()),
+			Some(_) => self.error("begin without end".to_string()),
+			None => Ok(())
+		}
+	}
+
+	fn finish(mut self) -> Result<Assembly, AsmError> {
+		let main = match self.labels.get("main") {
+			Some(&main) => main,
+			None => return Err(AsmError { line: 1, message: "there's no main label to start the program at".to_string() })
+		};
+		if self.jump_to_main {
+			self.patch_jump(PROGRAM_START, main)?;
+		}
+
+		for fixup in std::mem::take(&mut self.fixups) {
+			match fixup {
+				Fixup::Low12 { at, name, line } => {
+					let addr = self.resolve_label(&name, line)?;
+					if addr > 0xFFF {
+						return Err(AsmError { line, message: format!("{} is at {:X}, out of reach of a 12 bit address", name, addr) });
+					}
+					let offset = (at - PROGRAM_START) as usize;
+					self.rom[offset] |= (addr >> 8) as u8;
+					self.rom[offset + 1] = addr as u8;
+				},
+				Fixup::Word { at, name, line } => {
+					let addr = self.resolve_label(&name, line)?;
+					let offset = (at - PROGRAM_START) as usize;
+					self.rom[offset .. offset + 2].copy_from_slice(&addr.to_be_bytes());
+				}
+			}
+		}
+
+		Ok(Assembly { rom: self.rom, source_map: self.source_map })
+	}
+
+	fn resolve_label(&self, name: &str, line: usize) -> Result<u16, AsmError> {
+		self.labels.get(name).copied().ok_or_else(|| AsmError { line, message: format!("undefined name {}", name) })
+	}
+
+	fn emit_byte(&mut self, byte: u8) -> Result<(), AsmError> {
+		if self.here < PROGRAM_START {
+			return self.error(format!("can't assemble below {:X}", PROGRAM_START));
+		}
+		let offset = (self.here - PROGRAM_START) as usize;
+		if self.rom.len() <= offset {
+			self.rom.resize(offset + 1, 0);
+		}
+		self.rom[offset] = byte;
+		self.here = match self.here.checked_add(1) {
+			Some(h) => h,
+			None => return self.error("program is bigger than the address space".to_string())
+		};
+		Ok(())
+	}
+
+	// Emits an instruction and remembers which line it came from
+	fn emit(&mut self, opcode: u16) -> Result<u16, AsmError> {
+		let at = self.here;
+		self.source_map.lines.insert(at, self.line);
+		self.emit_byte((opcode >> 8) as u8)?;
+		self.emit_byte(opcode as u8)?;
+		Ok(at)
+	}
+
+	// Emits an instruction with a 12 bit address, filling it in later if the address isn't known yet
+	fn emit_with_address(&mut self, opcode: u16, target: &str) -> Result<(), AsmError> {
+		match self.address_value(target)? {
+			Some(addr) if addr > 0xFFF => return self.error(format!("{} is out of reach of a 12 bit address", target)),
+			Some(addr) => { self.emit(opcode | addr)?; },
+			None => {
+				let at = self.emit(opcode)?;
+				self.fixups.push(Fixup::Low12 { at, name: target.to_string(), line: self.line });
+			}
+		}
+		Ok(())
+	}
+
+	// Replaces the address of a jump emitted earlier
+	fn patch_jump(&mut self, at: u16, target: u16) -> Result<(), AsmError> {
+		if target > 0xFFF {
+			return self.error(format!("jump target out of range at {:X}", target));
+		}
+		let offset = (at - PROGRAM_START) as usize;
+		let opcode = 0x1000 | target;
+		self.rom[offset .. offset + 2].copy_from_slice(&opcode.to_be_bytes());
+		Ok(())
+	}
+
+	fn statement(&mut self) -> Result<(), AsmError> {
+		let token = self.next()?;
+
+		if let Some(m) = self.macros.get(&token).cloned() {
+			return self.expand_macro(&token, m);
+		}
+
+		match token.as_str() {
+			":" => {
+				let name = self.name()?;
+				// main right at the start doesn't need the jump to it, unless another label is there too
+				let main_first = name == "main" && self.jump_to_main && self.rom.len() == 2 && self.here == PROGRAM_START + 2
+					&& !self.labels.values().any(|&addr| addr == self.here);
+				if main_first {
+					self.rom.clear();
+					self.here = PROGRAM_START;
+					self.jump_to_main = false;
+				}
+				if self.labels.insert(name.clone(), self.here).is_some() {
+					return self.error(format!("{} is defined twice", name));
+				}
+			},
+			":const" => {
+				let name = self.name()?;
+				let value = self.next()?;
+				let value = self.number(&value)?;
+				self.consts.insert(name, value);
+			},
+			":alias" => {
+				let name = self.name()?;
+				let register = self.next()?;
+				let register = self.register(&register)?;
+				self.aliases.insert(name, register);
+			},
+			":macro" => self.define_macro()?,
+			":org" => {
+				let addr = self.next()?;
+				self.here = self.word(&addr)?;
+			},
+			":byte" => {
+				let value = self.next()?;
+				let byte = self.byte(&value)?;
+				self.emit_byte(byte)?;
+			},
+			":call" => {
+				let target = self.next()?;
+				self.emit_with_address(0x2000, &target)?;
+			},
+
+			"clear" => { self.emit(0x00E0)?; },
+			"return" => { self.emit(0x00EE)?; },
+			"scroll-down" => { let n = self.nibble_operand()?; self.emit(0x00C0 | n)?; },
+			"scroll-up" => { let n = self.nibble_operand()?; self.emit(0x00D0 | n)?; },
+			"scroll-right" => { self.emit(0x00FB)?; },
+			"scroll-left" => { self.emit(0x00FC)?; },
+			"exit" => { self.emit(0x00FD)?; },
+			"lores" => { self.emit(0x00FE)?; },
+			"hires" => { self.emit(0x00FF)?; },
+			"jump" => { let target = self.next()?; self.emit_with_address(0x1000, &target)?; },
+			"jump0" => { let target = self.next()?; self.emit_with_address(0xB000, &target)?; },
+			"sprite" => {
+				let x = self.register_operand()?;
+				let y = self.register_operand()?;
+				let n = self.nibble_operand()?;
+				self.emit(0xD000 | x << 8 | y << 4 | n)?;
+			},
+			"plane" => { let n = self.nibble_operand()?; self.emit(0xF001 | n << 8)?; },
+			"audio" => { self.emit(0xF002)?; },
+			"bcd" => { let x = self.register_operand()?; self.emit(0xF033 | x << 8)?; },
+			"saveflags" => { let x = self.register_operand()?; self.emit(0xF075 | x << 8)?; },
+			"loadflags" => { let x = self.register_operand()?; self.emit(0xF085 | x << 8)?; },
+			"save" | "load" => {
+				let x = self.register_operand()?;
+				if self.peek() == Some("-") { // save vx - vy, XO-CHIP's ranges
+					self.next()?;
+					let y = self.register_operand()?;
+					let kind = if token == "save" { 0x2 } else { 0x3 };
+					self.emit(0x5000 | x << 8 | y << 4 | kind)?;
+				} else {
+					self.emit(if token == "save" { 0xF055 } else { 0xF065 } | x << 8)?;
+				}
+			},
+			"delay" | "buzzer" | "pitch" => {
+				self.expect(":=")?;
+				let x = self.register_operand()?;
+				let low = match token.as_str() { "delay" => 0x15, "buzzer" => 0x18, _ => 0x3A };
+				self.emit(0xF000 | x << 8 | low)?;
+			},
+			"i" => self.index_statement()?,
+
+			"if" => {
+				let condition = self.condition()?;
+				match self.next()?.as_str() {
+					// the next statement only runs when the condition is true
+					"then" => { self.emit(condition.negated().skip_when_true())?; },
+					// the body is jumped over when the condition is false
+					"begin" => {
+						self.emit(condition.skip_when_true())?;
+						let jump = self.emit(0x1000)?;
+						self.blocks.push(Block::If { jump });
+					},
+					other => return self.error(format!("expected then or begin, found {}", other))
+				}
+			},
+			"else" => match self.blocks.pop() {
+				Some(Block::If { jump }) => {
+					let over_else = self.emit(0x1000)?;
+					self.patch_jump(jump, self.here)?;
+					self.blocks.push(Block::Else { jump: over_else });
+				},
+				_ => return self.error("else without if ... begin".to_string())
+			},
+			"end" => match self.blocks.pop() {
+				Some(Block::If { jump }) | Some(Block::Else { jump }) => self.patch_jump(jump, self.here)?,
+				_ => return self.error("end without begin".to_string())
+			},
+			"loop" => self.blocks.push(Block::Loop { start: self.here, breaks: Vec::new() }),
+			"while" => {
+				let condition = self.condition()?;
+				self.emit(condition.skip_when_true())?;
+				let jump = self.emit(0x1000)?; // out of the loop, filled in by again
+				match self.blocks.iter_mut().rev().find_map(|b| match b { Block::Loop { breaks, .. } => Some(breaks), _ => None }) {
+					Some(breaks) => breaks.push(jump),
+					None => return self.error("while outside of a loop".to_string())
+				}
+			},
+			"again" => match self.blocks.pop() {
+				Some(Block::Loop { start, breaks }) => {
+					if start > 0xFFF {
+						return self.error(format!("jump target out of range at {:X}", start));
+					}
+					self.emit(0x1000 | start)?;
+					for jump in breaks {
+						self.patch_jump(jump, self.here)?;
+					}
+				},
+				_ => return self.error("again without loop".to_string())
+			},
+
+			_ => {
+				if let Some(x) = self.try_register(&token) {
+					return self.register_statement(x as u16);
+				}
+				if let Some(value) = self.try_number(&token) {
+					return match to_byte(value) { // data, like sprites
+						Some(byte) => self.emit_byte(byte),
+						None => self.error(format!("{} doesn't fit in a byte", token))
+					};
+				}
+				if is_name(&token) { // calling a subroutine by its label
+					return self.emit_with_address(0x2000, &token);
+				}
+				return self.error(format!("unexpected {}", token));
+			}
+		}
+
+		Ok(())
+	}
+
+	fn define_macro(&mut self) -> Result<(), AsmError> {
+		let name = self.name()?;
+		let mut params = Vec::new();
+		loop {
+			let token = self.next()?;
+			if token == "{" { break; }
+			params.push(token);
+		}
+
+		let mut body = Vec::new();
+		let mut depth = 1; // braces can be nested inside the body
+		while depth > 0 {
+			let token = self.tokens.get(self.pos).cloned();
+			let token = match token {
+				Some(t) => t,
+				None => return self.error(format!("macro {} has no closing }}", name))
+			};
+			self.pos += 1;
+			match token.text.as_str() {
+				"{" => depth += 1,
+				"}" => depth -= 1,
+				_ => ()
+			}
+			if depth > 0 {
+				body.push(token);
+			}
+		}
+
+		self.macros.insert(name, Macro { params, body });
+		Ok(())
+	}
+
+	// Replaces the macro's name and arguments in the token stream with its body
+	fn expand_macro(&mut self, name: &str, m: Macro) -> Result<(), AsmError> {
+		self.macro_depth += 1;
+		if self.macro_depth > MAX_MACRO_DEPTH {
+			return self.error(format!("macro {} expands forever", name));
+		}
+
+		let mut args = HashMap::new();
+		for param in m.params.iter() {
+			let arg = self.next()?;
+			args.insert(param.clone(), arg);
+		}
+
+		// the body's tokens keep the line the macro was used on
+		let line = self.line;
+		let expanded: Vec<Token> = m.body.iter()
+									.map(|t| Token { text: args.get(&t.text).unwrap_or(&t.text).clone(), line })
+									.collect();
+		let end = self.pos + expanded.len();
+		self.tokens.splice(self.pos .. self.pos, expanded);
+
+		while self.pos < end {
+			self.statement()?;
+		}
+		self.macro_depth -= 1;
+		Ok(())
+	}
+
+	fn index_statement(&mut self) -> Result<(), AsmError> {
+		let op = self.next()?;
+		match op.as_str() {
+			":=" => {
+				let value = self.next()?;
+				match value.as_str() {
+					"hex" => { let x = self.register_operand()?; self.emit(0xF029 | x << 8)?; },
+					"bighex" => { let x = self.register_operand()?; self.emit(0xF030 | x << 8)?; },
+					"long" => {
+						let target = self.next()?;
+						self.emit(0xF000)?;
+						let at = self.here;
+						match self.address_value(&target)? {
+							Some(addr) => {
+								self.emit_byte((addr >> 8) as u8)?;
+								self.emit_byte(addr as u8)?;
+							},
+							None => {
+								self.emit_byte(0)?;
+								self.emit_byte(0)?;
+								self.fixups.push(Fixup::Word { at, name: target, line: self.line });
+							}
+						}
+					},
+					_ => self.emit_with_address(0xA000, &value)?
+				}
+			},
+			"+=" => { let x = self.register_operand()?; self.emit(0xF01E | x << 8)?; },
+			_ => return self.error(format!("unknown operation i {}", op))
+		}
+		Ok(())
+	}
+
+	fn register_statement(&mut self, x: u16) -> Result<(), AsmError> {
+		let op = self.next()?;
+		let operand = self.next()?;
+
+		// the operations that only take a register
+		let register_op = match op.as_str() {
+			"|=" => Some(0x1),
+			"&=" => Some(0x2),
+			"^=" => Some(0x3),
+			">>=" => Some(0x6),
+			"=-" => Some(0x7),
+			"<<=" => Some(0xE),
+			_ => None
+		};
+		if let Some(n) = register_op {
+			let y = self.register(&operand)? as u16;
+			self.emit(0x8000 | x << 8 | y << 4 | n)?;
+			return Ok(());
+		}
+
+		let y = self.try_register(&operand).map(|y| y as u16);
+		match (op.as_str(), y) {
+			(":=", Some(y)) => { self.emit(0x8000 | x << 8 | y << 4)?; },
+			("+=", Some(y)) => { self.emit(0x8004 | x << 8 | y << 4)?; },
+			("-=", Some(y)) => { self.emit(0x8005 | x << 8 | y << 4)?; },
+			(":=", None) => match operand.as_str() {
+				"random" => {
+					let mask = self.next()?;
+					let mask = self.byte(&mask)?;
+					self.emit(0xC000 | x << 8 | mask as u16)?;
+				},
+				"key" => { self.emit(0xF00A | x << 8)?; },
+				"delay" => { self.emit(0xF007 | x << 8)?; },
+				_ => {
+					let n = self.byte(&operand)?;
+					self.emit(0x6000 | x << 8 | n as u16)?;
+				}
+			},
+			("+=", None) => {
+				let n = self.byte(&operand)?;
+				self.emit(0x7000 | x << 8 | n as u16)?;
+			},
+			("-=", None) => { // adding the two's complement subtracts
+				let n = self.byte(&operand)?;
+				self.emit(0x7000 | x << 8 | n.wrapping_neg() as u16)?;
+			},
+			_ => return self.error(format!("unknown operation {}", op))
+		}
+		Ok(())
+	}
+
+	fn condition(&mut self) -> Result<Condition, AsmError> {
+		let register = self.next()?;
+		let x = self.register(&register)?;
+		let op = self.next()?;
+
+		match op.as_str() {
+			"key" => return Ok(Condition::Key(x)),
+			"-key" => return Ok(Condition::NotKey(x)),
+			"==" | "!=" => (),
+			_ => return self.error(format!("unknown comparison {}", op))
+		}
+
+		let operand = self.next()?;
+		let equal = op == "==";
+		Ok(match self.try_register(&operand) {
+			Some(y) if equal => Condition::Equal(x, y),
+			Some(y) => Condition::NotEqual(x, y),
+			None => {
+				let n = self.byte(&operand)?;
+				if equal { Condition::EqualByte(x, n) } else { Condition::NotEqualByte(x, n) }
+			}
+		})
+	}
+
+	fn name(&mut self) -> Result<String, AsmError> {
+		let name = self.next()?;
+		if is_name(&name) && self.try_register(&name).is_none() {
+			Ok(name)
+		} else {
+			self.error(format!("{} can't be used as a name", name))
+		}
+	}
+
+	fn try_register(&self, token: &str) -> Option<u8> {
+		if let Some(&r) = self.aliases.get(token) {
+			return Some(r);
+		}
+
+		let mut chars = token.chars();
+		match (chars.next(), chars.next(), chars.next()) {
+			(Some('v'), Some(digit), None) | (Some('V'), Some(digit), None) => digit.to_digit(16).map(|d| d as u8),
+			_ => None
+		}
+	}
+
+	fn register(&self, token: &str) -> Result<u8, AsmError> {
+		match self.try_register(token) {
+			Some(r) => Ok(r),
+			None => self.error(format!("expected a register, found {}", token))
+		}
+	}
+
+	fn register_operand(&mut self) -> Result<u16, AsmError> {
+		let token = self.next()?;
+		self.register(&token).map(|r| r as u16)
+	}
+
+	fn try_number(&self, token: &str) -> Option<i64> {
+		if let Some(&value) = self.consts.get(token) {
+			return Some(value);
+		}
+		parse_number(token)
+	}
+
+	fn number(&self, token: &str) -> Result<i64, AsmError> {
+		match self.try_number(token) {
+			Some(value) => Ok(value),
+			None => self.error(format!("expected a number, found {}", token))
+		}
+	}
+
+	fn byte(&self, token: &str) -> Result<u8, AsmError> {
+		let value = self.number(token)?;
+		match to_byte(value) {
+			Some(byte) => Ok(byte),
+			None => self.error(format!("{} doesn't fit in a byte", token))
+		}
+	}
+
+	fn word(&self, token: &str) -> Result<u16, AsmError> {
+		let value = self.number(token)?;
+		if (0 ..= 0xFFFF).contains(&value) {
+			Ok(value as u16)
+		} else {
+			self.error(format!("{} isn't an address", token))
+		}
+	}
+
+	fn nibble_operand(&mut self) -> Result<u16, AsmError> {
+		let token = self.next()?;
+		let value = self.number(&token)?;
+		if (0 ..= 0xF).contains(&value) {
+			Ok(value as u16)
+		} else {
+			self.error(format!("{} doesn't fit in 4 bits", token))
+		}
+	}
+
+	// An address operand: a number, a constant or a label. None for labels that aren't defined yet
+	fn address_value(&self, token: &str) -> Result<Option<u16>, AsmError> {
+		if self.try_number(token).is_some() {
+			return self.word(token).map(Some);
+		}
+		if let Some(&addr) = self.labels.get(token) {
+			return Ok(Some(addr));
+		}
+		if is_name(token) {
+			Ok(None)
+		} else {
+			self.error(format!("expected an address, found {}", token))
+		}
+	}
+}
+
+// Byte values can be written from -128 to 255, negative ones are stored as their two's complement
+fn to_byte(value: i64) -> Option<u8> {
+	if (-128 ..= 255).contains(&value) {
+		Some(value as u8)
+	} else {
+		None
+	}
+}
+
+// Decimal, hex with 0x and binary with 0b, any of them negative
+fn parse_number(token: &str) -> Option<i64> {
+	let (negative, digits) = match token.strip_prefix('-') {
+		Some(rest) => (true, rest),
+		None => (false, token)
+	};
+
+	let value = if let Some(hex) = digits.strip_prefix("0x").or_else(|| digits.strip_prefix("0X")) {
+		i64::from_str_radix(hex, 16).ok()?
+	} else if let Some(binary) = digits.strip_prefix("0b").or_else(|| digits.strip_prefix("0B")) {
+		i64::from_str_radix(binary, 2).ok()?
+	} else if !digits.is_empty() && digits.chars().all(|c| c.is_ascii_digit()) {
+		digits.parse().ok()?
+	} else {
+		return None;
+	};
+
+	Some(if negative { -value } else { value })
+}
+
+fn is_name(token: &str) -> bool {
+	let mut chars = token.chars();
+	match chars.next() {
+		Some(c) if c.is_ascii_alphabetic() || c == '_' => chars.all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-'),
+		_ => false
+	}
+}
@@ -0,0 +1,257 @@
+use super::{ assemble, AsmError, SourceMap };
+use crate::{ CHIP8, Platform, Instruction, decode, disasm::{ self, Syntax } };
+
+fn machine_with(source: &str, platform: Platform) -> CHIP8 {
+	let rom = assemble(source).unwrap().rom;
+	let mut machine = CHIP8::with_platform(&[], platform).unwrap();
+	machine.memory[0x200 .. 0x200 + rom.len()].copy_from_slice(&rom);
+	machine
+}
+
+// runs until the program exits, at most a few thousand cycles
+fn run(machine: &mut CHIP8) {
+	for _ in 0 .. 5000 {
+		if machine.exited {
+			return;
+		}
+		machine.emulate_cycle().unwrap();
+	}
+	panic!("the program didn't exit");
+}
+
+#[test]
+fn every_opcode_form() {
+	let cases: &[(&str, &[u8])] = &[
+		("clear", &[0x00, 0xE0]), ("return", &[0x00, 0xEE]), ("scroll-down 3", &[0x00, 0xC3]),
+		("scroll-up 4", &[0x00, 0xD4]), ("scroll-right", &[0x00, 0xFB]), ("scroll-left", &[0x00, 0xFC]),
+		("exit", &[0x00, 0xFD]), ("lores", &[0x00, 0xFE]), ("hires", &[0x00, 0xFF]),
+		("jump 0x234", &[0x12, 0x34]), (":call 0x345", &[0x23, 0x45]),
+		("if va != 0x12 then", &[0x3A, 0x12]), ("if vb == 0x34 then", &[0x4B, 0x34]),
+		("if v1 != v2 then", &[0x51, 0x20]), ("save v1 - v2", &[0x51, 0x22]), ("load v1 - v2", &[0x51, 0x23]),
+		("vc := 0x56", &[0x6C, 0x56]), ("vd += 0x78", &[0x7D, 0x78]), ("vd -= 1", &[0x7D, 0xFF]),
+		("v1 := v2", &[0x81, 0x20]), ("v1 |= v2", &[0x81, 0x21]), ("v1 &= v2", &[0x81, 0x22]),
+		("v1 ^= v2", &[0x81, 0x23]), ("v1 += v2", &[0x81, 0x24]), ("v1 -= v2", &[0x81, 0x25]),
+		("v1 >>= v2", &[0x81, 0x26]), ("v1 =- v2", &[0x81, 0x27]), ("v1 <<= v2", &[0x81, 0x2E]),
+		("if v3 == v4 then", &[0x93, 0x40]), ("i := 0x456", &[0xA4, 0x56]), ("jump0 0x567", &[0xB5, 0x67]),
+		("v6 := random 0xFF", &[0xC6, 0xFF]), ("sprite v7 v8 5", &[0xD7, 0x85]),
+		("if v9 -key then", &[0xE9, 0x9E]), ("if va key then", &[0xEA, 0xA1]),
+		("i := long 0x1234", &[0xF0, 0x00, 0x12, 0x34]), ("plane 3", &[0xF3, 0x01]), ("audio", &[0xF0, 0x02]),
+		("v1 := delay", &[0xF1, 0x07]), ("v2 := key", &[0xF2, 0x0A]), ("delay := v3", &[0xF3, 0x15]),
+		("buzzer := v4", &[0xF4, 0x18]), ("i += v5", &[0xF5, 0x1E]), ("i := hex v6", &[0xF6, 0x29]),
+		("i := bighex v7", &[0xF7, 0x30]), ("bcd v8", &[0xF8, 0x33]), ("pitch := v9", &[0xF9, 0x3A]),
+		("save va", &[0xFA, 0x55]), ("load vb", &[0xFB, 0x65]), ("saveflags vc", &[0xFC, 0x75]),
+		("loadflags vd", &[0xFD, 0x85])
+	];
+
+	for &(source, bytes) in cases.iter() {
+		let rom = assemble(&format!(": main {}", source)).unwrap().rom;
+		assert_eq!(rom, bytes, "{}", source);
+		assert_ne!(decode(u16::from_be_bytes([rom[0], rom[1]])), Instruction::Unknown(u16::from_be_bytes([rom[0], rom[1]])), "{}", source);
+	}
+}
+
+#[test]
+fn arithmetic_executes() {
+	let mut machine = machine_with("
+	: main
+		v0 := 200
+		v1 := 100
+		v0 += v1    # 44, carry
+		v2 := vf
+		v3 := 0b1010
+		v3 |= v1    # 0x6E
+		v4 := 0xF0
+		v4 &= v3    # 0x60
+		v5 := v4
+		v5 ^= v1    # 0x04
+		v6 := 10
+		v6 -= 3
+		v7 := 3
+		v7 =- v6    # 4
+		i := data
+		i += v7
+		v8 := delay
+		exit
+	: data
+		1 2 3 4 0x55
+	", Platform::Chip8);
+
+	run(&mut machine);
+
+	assert_eq!(machine.V[0], 44);
+	assert_eq!(machine.V[2], 1);
+	assert_eq!(machine.V[3], 0x6E);
+	assert_eq!(machine.V[4], 0x60);
+	assert_eq!(machine.V[5], 0x04);
+	assert_eq!(machine.V[6], 7);
+	assert_eq!(machine.V[7], 4);
+	assert_eq!(machine.memory[machine.I as usize], 0x55);
+}
+
+#[test]
+fn control_flow_executes() {
+	let mut machine = machine_with("
+		:const LIMIT 5
+		:alias counter v3
+		:macro double reg { reg += reg }
+
+	: main
+		counter := 0
+		loop
+			counter += 1
+			while counter != LIMIT
+		again
+
+		v4 := 1
+		double v4
+		double v4    # 4
+
+		if v4 == 4 begin
+			v5 := 0xAA
+		else
+			v5 := 0xBB
+		end
+
+		if v4 != 4 then v6 := 0xCC
+		set-v7
+		exit
+
+	: set-v7
+		v7 := 0x77
+		return
+	", Platform::Chip8);
+
+	run(&mut machine);
+
+	assert_eq!(machine.V[3], 5);
+	assert_eq!(machine.V[4], 4);
+	assert_eq!(machine.V[5], 0xAA);
+	assert_eq!(machine.V[6], 0);
+	assert_eq!(machine.V[7], 0x77);
+}
+
+#[test]
+fn extended_instructions_execute() {
+	let mut machine = machine_with("
+	: main
+		v0 := 1
+		v1 := 2
+		v2 := 3
+		i := long target
+		save v0 - v2
+		i := long target
+		load v5 - v7
+		bcd v2
+		exit
+	:org 0x400
+	: target
+	", Platform::XoChip);
+
+	run(&mut machine);
+
+	assert_eq!(&machine.V[5 ..= 7], &[1, 2, 3]);
+	assert_eq!(&machine.memory[0x400 .. 0x403], &[0, 0, 3]);
+	assert_eq!(machine.I, 0x400);
+}
+
+#[test]
+fn programs_jump_to_main() {
+	let source = ": helper v1 := 7 return : main v0 := 1 helper exit";
+	assert_eq!(assemble(source).unwrap().rom, vec![0x12, 0x06, 0x61, 0x07, 0x00, 0xEE, 0x60, 0x01, 0x22, 0x02, 0x00, 0xFD]);
+
+	let mut machine = machine_with(source, Platform::XoChip);
+	run(&mut machine);
+	assert_eq!(&machine.V[.. 2], &[1, 7]);
+
+	// there's nothing to jump over when main comes first, even after constants and macros
+	assert_eq!(assemble(":const X 2 : main v0 := X exit").unwrap().rom, vec![0x60, 0x02, 0x00, 0xFD]);
+	// but another label at the start still needs the jump
+	assert_eq!(assemble(": start : main jump start").unwrap().rom, vec![0x12, 0x02, 0x12, 0x02]);
+}
+
+#[test]
+fn labels_sprites_and_org() {
+	let assembly = assemble("
+	: main
+		i := smiley    # forward reference
+		jump end
+	: smiley
+		0b00100100
+		0b00000000
+		0b10000001
+		0b01111110
+	: end
+		:byte -1
+	:org 0x210
+		exit
+	").unwrap();
+
+	assert_eq!(assembly.rom, vec![
+		0xA2, 0x04, 0x12, 0x08, 0x24, 0x00, 0x81, 0x7E, 0xFF, 0, 0, 0, 0, 0, 0, 0, 0x00, 0xFD
+	]);
+}
+
+#[test]
+fn source_map() {
+	let assembly = assemble("\
+: main
+	v0 := 1
+	sprite v0 v0 5   # line 3
+
+	1 2 3 4
+	exit
+").unwrap();
+
+	let map = &assembly.source_map;
+	assert_eq!(map.iter().collect::<Vec<_>>(), vec![(0x200, 2), (0x202, 3), (0x208, 6)]);
+	assert_eq!(map.line(0x202), Some(3));
+	assert_eq!(map.line(0x204), None); // data isn't mapped
+	assert_eq!(map.address(6), Some(0x208));
+
+	assert_eq!(map.to_text(), "200 2\n202 3\n208 6\n");
+	assert_eq!(&SourceMap::parse(&map.to_text()).unwrap(), map);
+	assert!(SourceMap::parse("200 two").is_err());
+}
+
+#[test]
+fn disassembly_reassembles() {
+	let source = "
+	: main
+		hires
+		v0 := 0
+		loop
+			i := hex v0
+			sprite v0 v0 5
+			v0 += 1
+			if v0 != 16 then
+		again
+		plot
+		i := long sprite
+		exit
+	: plot
+		scroll-down 2
+		return
+	: sprite
+		0xFF 0x81 0xFF
+	";
+
+	let rom = assemble(source).unwrap().rom;
+	let listing = disasm::disassemble(&rom, Syntax::Octo);
+
+	assert_eq!(assemble(&listing).unwrap().rom, rom);
+}
+
+#[test]
+fn errors_have_line_numbers() {
+	let error = |source: &str| assemble(source).unwrap_err();
+
+	assert_eq!(error(": main clear\njump nowhere"), AsmError { line: 2, message: "undefined name nowhere".to_string() });
+	assert_eq!(error(": main v0 := 256"), AsmError { line: 1, message: "256 doesn't fit in a byte".to_string() });
+	assert_eq!(error(": main\n\nsprite v0 vg 1"), AsmError { line: 3, message: "expected a register, found vg".to_string() });
+	assert_eq!(error("loop\nclear"), AsmError { line: 2, message: "loop without again".to_string() });
+	assert_eq!(error(": a\n: a"), AsmError { line: 2, message: "a is defined twice".to_string() });
+	assert_eq!(error(":macro m { m }\nm"), AsmError { line: 2, message: "macro m expands forever".to_string() });
+	assert_eq!(error("clear"), AsmError { line: 1, message: "there's no main label to start the program at".to_string() });
+	assert_eq!(error(": main\n:org 0x1000\nloop\nagain"), AsmError { line: 4, message: "jump target out of range at 1000".to_string() });
+	assert_eq!(error(": main\n:org 0xFFE\nif v0 == 0 begin\nend"), AsmError { line: 4, message: "jump target out of range at 1002".to_string() });
+}
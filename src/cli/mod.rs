@@ -0,0 +1,364 @@
+// The command line: which subcommand to run and the options for it, and the config file with the defaults
+// for the options
+use crate::{ CHIP8, Config, ErrorPolicy, Quirks, Palette, Filter, TextMode, Waveform, RecordFormat, Watchpoint, Condition, debugger, disasm::Syntax };
+
+use std::{
+	error::Error,
+	fmt, fs, io,
+	path::{ Path, PathBuf }
+};
+
+mod toml;
+#[cfg(test)]
+mod tests;
+
+// Where the defaults are read from when --config isn't given, in the directory the emulator is started from
+pub const CONFIG_FILE: &str = "chip8.toml";
+
+// How many frames headless runs and test last when --frames isn't given
+pub const DEFAULT_FRAMES: u64 = 600;
+
+pub const USAGE: &str = "\
+Usage: chip8 [run] <rom> [options]
+       chip8 disasm [--classic] <rom>
+       chip8 asm <source> [-o <rom>]
+       chip8 info <rom>
+       chip8 test <rom> [--frames <count>] [--screenshot <png> [--scale <factor>]]
+
+Options for run:
+  --ips <count>               instructions per second
+  --scale <factor>            window size, in screen pixels per CHIP-8 pixel
+  --quirks <preset>           vip, chip48, schip or xochip
+  --palette <palette>         classic, amber, green, lcd, octo or up to 4 colours like #000000,#ffffff for
+                              no plane, the first, the second and both. Tab cycles through them
+  --filter <filter>           off, phosphor[:<decay>] to fade pixels out or persist[:<frames>] to show
+                              pixels lit in any of the last frames, against sprites flickering
+  --keymap <file>             key bindings, chip8.keymap by default
+  --mute                      no sound
+  --tone-frequency <hz>       the pitch of the buzzer, 440 by default
+  --waveform <waveform>       the buzzer's tone, square or sine
+  --beep <file>               a sound file looped instead of the tone
+  --screenshot-scale <factor> PNG pixels per CHIP-8 pixel in the screenshots print screen saves
+  --on-error <policy>         halt, log or ignore failing instructions, all of them or by kind, like
+                              log,stack-underflow=ignore. Running off the end of memory always halts
+  --terminal <mode>           draw in the terminal instead of a window, with half-blocks or braille
+  --record <file>             record the screen from the start, as a .gif, .y4m or .ppm stream
+  --headless                  run without a window, for --frames frames (600 unless given)
+  --frames <count>
+  --config <file>             defaults for the options above, chip8.toml by default
+  --debug                     run under the debugger, with its prompt when headless
+  --break <addr>              stop at an address. --watch <start>[-<end>][:r|:w|:rw] stops on memory
+  --iwatch <start>[-<end>]    accesses, --iwatch when I points into a range and --when <condition>
+  --when <condition>          when a condition like v3 == 0x10 becomes true
+  --gdb <port>                wait for GDB on the port and let it drive the machine
+  --trace <file>              log every instruction to the file, only the ones in --trace-range if given
+  --trace-range <start>[-<end>]
+  --trace-dump <file>         where the last --trace-history instructions go when one fails
+  --trace-history <count>
+";
+
+// What the command line asked for
+#[derive(Clone, Debug, PartialEq)]
+pub enum Command {
+	Run(Box<RunOptions>),
+	Disasm { rom: String, syntax: Syntax },
+	Asm { source: String, output: Option<String> },
+	Info { rom: String },
+	Test { rom: String, frames: u64, screenshot: Option<String>, scale: u32 }, // the screenshot is saved at the end
+	Help
+}
+
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct RunOptions {
+	pub rom: String,
+	pub settings: Settings,
+	pub config_file: Option<PathBuf>,
+	pub headless: bool,
+	pub frames: Option<u64>,
+	pub record: Option<PathBuf>,
+	pub debug: bool,
+	pub breakpoints: Vec<u16>,
+	pub watchpoints: Vec<Watchpoint>,
+	pub conditions: Vec<Condition>,
+	pub gdb_port: Option<u16>,
+	pub trace: Option<String>,
+	pub trace_ranges: Vec<(u16, u16)>,
+	pub trace_dump: Option<String>,
+	pub trace_history: Option<usize>
+}
+
+impl RunOptions {
+	// Puts the debugging options in a config
+	pub fn configure_debugging(&self, config: &mut Config) {
+		config.debug = self.debug;
+		config.breakpoints = self.breakpoints.clone();
+		config.watchpoints = self.watchpoints.clone();
+		config.conditions = self.conditions.clone();
+		config.gdb_port = self.gdb_port;
+	}
+}
+
+// The options that can also be set in the config file. None leaves the setting as it was
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct Settings {
+	pub ips: Option<u32>,
+	pub scale: Option<u32>,
+	pub quirks: Option<Quirks>,
+	pub palette: Option<Palette>,
+	pub filter: Option<Filter>,
+	pub keymap: Option<PathBuf>,
+	pub mute: Option<bool>,
+	pub tone_frequency: Option<f32>,
+	pub waveform: Option<Waveform>,
+	pub beep: Option<PathBuf>,
+	pub screenshot_scale: Option<u32>,
+	pub on_error: Option<ErrorPolicy>,
+	pub terminal: Option<TextMode>
+}
+
+#[derive(Debug)]
+pub enum ConfigError {
+	Io(io::Error),
+	Syntax(String)
+}
+
+impl fmt::Display for ConfigError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			ConfigError::Io(e) => write!(f, "couldn't read the config file: {}", e),
+			ConfigError::Syntax(reason) => write!(f, "config file: {}", reason)
+		}
+	}
+}
+
+impl Error for ConfigError {}
+
+impl From<io::Error> for ConfigError {
+	fn from(e: io::Error) -> ConfigError {
+		ConfigError::Io(e)
+	}
+}
+
+impl Settings {
+	pub fn load(path: &Path) -> Result<Settings, ConfigError> {
+		Settings::parse(&fs::read_to_string(path)?)
+	}
+
+	// Reads a config file:
+	//
+	//     ips = 700
+	//     scale = 10
+	//     quirks = "schip"
+	//     palette = ["#000000", "#ffffff"] # or a theme, like "amber"
+	//     filter = "phosphor:0.5"
+	//     keymap = "my.keymap"
+	//     mute = true
+	//     tone-frequency = 220
+	//     waveform = "sine"
+	//     beep = "beep.wav"
+	//     on-error = "halt"
+	//     terminal = "braille"
+	pub fn parse(text: &str) -> Result<Settings, ConfigError> {
+		let mut settings = Settings::default();
+		for (key, value) in toml::parse(text).map_err(ConfigError::Syntax)? {
+			let text = match &value {
+				toml::Value::String(s) => s.clone(),
+				toml::Value::Integer(n) => n.to_string(),
+				toml::Value::Float(f) => f.to_string(),
+				toml::Value::Bool(b) => b.to_string(),
+				toml::Value::Array(items) if key == "palette" => items.iter().map(|item| match item {
+					toml::Value::String(s) => Ok(s.clone()),
+					other => Err(ConfigError::Syntax(format!("palette should be a list of colours, not {}", other.type_name())))
+				}).collect::<Result<Vec<_>, _>>()?.join(","),
+				other => return Err(ConfigError::Syntax(format!("{} can't be {}", key, other.type_name())))
+			};
+			if !settings.set(&key, &text).map_err(ConfigError::Syntax)? {
+				return Err(ConfigError::Syntax(format!("unknown setting {}", key)));
+			}
+		}
+		Ok(settings)
+	}
+
+	// Sets the setting called name, as it's written on the command line without the --. Returns false if
+	// there's no setting with that name
+	fn set(&mut self, name: &str, value: &str) -> Result<bool, String> {
+		match name {
+			"ips" => self.ips = Some(parse_count(value, "instructions per second")?),
+			"scale" => self.scale = Some(parse_count(value, "scale")?),
+			"quirks" => self.quirks = Some(Quirks::preset(value).ok_or_else(|| format!("unknown quirks {}, try vip, chip48, schip or xochip", value))?),
+			"palette" => self.palette = Some(Palette::parse(value)?),
+			"filter" => self.filter = Some(Filter::parse(value)?),
+			"keymap" => self.keymap = Some(PathBuf::from(value)),
+			"mute" => self.mute = Some(value.parse().map_err(|_| format!("mute should be true or false, not {}", value))?),
+			"tone-frequency" => self.tone_frequency = Some(value.parse().ok().filter(|f| (20.0 ..= 20000.0).contains(f))
+											.ok_or_else(|| format!("the tone's frequency is from 20 to 20000 Hz, not {}", value))?),
+			"waveform" => self.waveform = Some(Waveform::parse(value).ok_or_else(|| format!("unknown waveform {}, try square or sine", value))?),
+			"beep" => self.beep = Some(PathBuf::from(value)),
+			"screenshot-scale" => self.screenshot_scale = Some(parse_count(value, "screenshot scale")?),
+			"on-error" => self.on_error = Some(ErrorPolicy::parse(value)?),
+			"terminal" => self.terminal = Some(TextMode::parse(value).ok_or_else(|| format!("unknown terminal mode {}, try half-blocks or braille", value))?),
+			_ => return Ok(false)
+		}
+		Ok(true)
+	}
+
+	// Changes the machine and config for the settings that are given. The keymap is only a path, it's up to
+	// the caller to load it
+	pub fn apply(&self, machine: &mut CHIP8, config: &mut Config) {
+		if let Some(ips) = self.ips {
+			machine.set_instructions_per_frame(((ips + 30) / 60).max(1));
+		}
+		if let Some(scale) = self.scale {
+			config.scale = scale;
+		}
+		if let Some(quirks) = self.quirks {
+			machine.set_quirks(quirks);
+		}
+		if let Some(palette) = self.palette {
+			config.palette = palette;
+		}
+		if let Some(filter) = self.filter {
+			config.filter = filter;
+		}
+		if self.mute == Some(true) {
+			config.audio.volume = 0.0;
+		}
+		if let Some(frequency) = self.tone_frequency {
+			config.audio.frequency = frequency;
+		}
+		if let Some(waveform) = self.waveform {
+			config.audio.waveform = waveform;
+		}
+		if let Some(beep) = &self.beep {
+			config.audio.sample = Some(beep.clone());
+		}
+		if let Some(scale) = self.screenshot_scale {
+			config.screenshot_scale = scale;
+		}
+		if let Some(policy) = self.on_error {
+			config.errors = policy;
+		}
+		if let Some(mode) = self.terminal {
+			config.terminal = Some(mode);
+		}
+	}
+}
+
+fn parse_count<T: std::str::FromStr + PartialOrd + Default>(text: &str, what: &str) -> Result<T, String> {
+	text.parse().ok().filter(|n| *n > T::default()).ok_or_else(|| format!("bad {}: {}", what, text))
+}
+
+// Reads the arguments after the program's name
+pub fn parse(args: &[String]) -> Result<Command, String> {
+	let (command, rest) = match args.split_first() {
+		Some((command, rest)) => (command.as_str(), rest),
+		None => return Err("no ROM given".to_string())
+	};
+
+	match command {
+		"run" => parse_run(rest).map(|o| Command::Run(Box::new(o))),
+		"disasm" => {
+			let syntax = if rest.iter().any(|a| a == "--classic") { Syntax::Classic } else { Syntax::Octo };
+			let rom = single_path(rest.iter().filter(|a| *a != "--classic"), "disasm needs one ROM")?;
+			Ok(Command::Disasm { rom, syntax })
+		},
+		"asm" => match rest {
+			[source] => Ok(Command::Asm { source: source.clone(), output: None }),
+			[source, flag, output] if flag == "-o" => Ok(Command::Asm { source: source.clone(), output: Some(output.clone()) }),
+			_ => Err("asm needs a source file, and optionally -o <rom>".to_string())
+		},
+		"info" => Ok(Command::Info { rom: single_path(rest.iter(), "info needs one ROM")? }),
+		"test" => {
+			let mut frames = DEFAULT_FRAMES;
+			let mut screenshot = None;
+			let mut scale = 1; // golden images are compared pixel for pixel
+			let mut paths = Vec::new();
+			let mut args = rest.iter();
+			while let Some(arg) = args.next() {
+				match arg.as_str() {
+					"--frames" => frames = parse_count(flag_value(&mut args, arg)?, "number of frames")?,
+					"--screenshot" => screenshot = Some(flag_value(&mut args, arg)?.to_string()),
+					"--scale" => scale = parse_count(flag_value(&mut args, arg)?, "scale")?,
+					_ => paths.push(arg)
+				}
+			}
+			Ok(Command::Test { rom: single_path(paths.into_iter(), "test needs one ROM")?, frames, screenshot, scale })
+		},
+		"help" | "--help" | "-h" => Ok(Command::Help),
+		_ => parse_run(args).map(|o| Command::Run(Box::new(o))) // run is the default
+	}
+}
+
+fn parse_run(args: &[String]) -> Result<RunOptions, String> {
+	let mut options = RunOptions::default();
+	let mut rom = None;
+
+	let mut args = args.iter();
+	while let Some(arg) = args.next() {
+		let flag = match arg.strip_prefix("--") {
+			Some(flag) => flag,
+			None if rom.is_none() => {
+				rom = Some(arg.clone());
+				continue;
+			},
+			None => return Err(format!("unexpected {}, only one ROM can be run", arg))
+		};
+
+		match flag {
+			"mute" => options.settings.mute = Some(true),
+			"headless" => options.headless = true,
+			"debug" => options.debug = true,
+			_ => {
+				let value = flag_value(&mut args, arg)?;
+				let error = |e: String| format!("{} {}: {}", arg, value, e);
+				if options.settings.set(flag, value).map_err(error)? {
+					continue;
+				}
+
+				match flag {
+					"frames" => options.frames = Some(parse_count(value, "number of frames").map_err(error)?),
+					"config" => options.config_file = Some(PathBuf::from(value)),
+					"record" => {
+						RecordFormat::from_path(Path::new(value)).ok_or_else(|| error("recordings are .gif, .y4m or .ppm files".to_string()))?;
+						options.record = Some(PathBuf::from(value));
+					},
+					"break" => options.breakpoints.push(parse_address(value).map_err(error)?),
+					"watch" => options.watchpoints.push(Watchpoint::parse(value).map_err(error)?),
+					"iwatch" => {
+						let (start, end) = parse_range(value).map_err(error)?;
+						options.conditions.push(Condition::index_in(start, end));
+					},
+					"when" => options.conditions.push(Condition::parse(value).map_err(error)?),
+					"gdb" => options.gdb_port = Some(value.parse().map_err(|_| error("not a port".to_string()))?),
+					"trace" => options.trace = Some(value.to_string()),
+					"trace-range" => options.trace_ranges.push(parse_range(value).map_err(error)?),
+					"trace-dump" => options.trace_dump = Some(value.to_string()),
+					"trace-history" => options.trace_history = Some(value.parse().map_err(|_| error("not a number".to_string()))?),
+					_ => return Err(format!("unknown option {}", arg))
+				}
+			}
+		}
+	}
+
+	options.rom = rom.ok_or("no ROM given")?;
+	Ok(options)
+}
+
+fn flag_value<'a, I: Iterator<Item = &'a String>>(args: &mut I, flag: &str) -> Result<&'a str, String> {
+	args.next().map(String::as_str).ok_or_else(|| format!("{} needs a value", flag))
+}
+
+fn single_path<'a, I: Iterator<Item = &'a String>>(mut args: I, usage: &str) -> Result<String, String> {
+	match (args.next(), args.next()) {
+		(Some(path), None) if !path.starts_with("--") => Ok(path.clone()),
+		_ => Err(usage.to_string())
+	}
+}
+
+fn parse_address(text: &str) -> Result<u16, String> {
+	parse_range(text).ok().filter(|(start, end)| start == end).map(|(addr, _)| addr).ok_or_else(|| "not an address".to_string())
+}
+
+fn parse_range(text: &str) -> Result<(u16, u16), String> {
+	debugger::parse_range(text).ok_or_else(|| "not an address range".to_string())
+}
@@ -0,0 +1,113 @@
+use super::{ parse, Command, RunOptions, Settings, ConfigError, DEFAULT_FRAMES };
+use crate::{ CHIP8, Config, ErrorPolicy, Quirks, Palette, Filter, Waveform, Condition, disasm::Syntax };
+
+use std::path::PathBuf;
+
+fn args(line: &str) -> Vec<String> {
+	line.split_whitespace().map(str::to_string).collect()
+}
+
+#[test]
+fn subcommands() {
+	assert_eq!(parse(&args("disasm --classic game.ch8")), Ok(Command::Disasm { rom: "game.ch8".to_string(), syntax: Syntax::Classic }));
+	assert_eq!(parse(&args("asm game.8o -o out.ch8")), Ok(Command::Asm { source: "game.8o".to_string(), output: Some("out.ch8".to_string()) }));
+	assert_eq!(parse(&args("info game.ch8")), Ok(Command::Info { rom: "game.ch8".to_string() }));
+	assert_eq!(parse(&args("test game.ch8")), Ok(Command::Test { rom: "game.ch8".to_string(), frames: DEFAULT_FRAMES, screenshot: None, scale: 1 }));
+	assert_eq!(parse(&args("test --frames 30 game.ch8 --screenshot end.png --scale 2")),
+				Ok(Command::Test { rom: "game.ch8".to_string(), frames: 30, screenshot: Some("end.png".to_string()), scale: 2 }));
+	assert_eq!(parse(&args("--help")), Ok(Command::Help));
+
+	// run is the default
+	let run = Command::Run(Box::new(RunOptions { rom: "game.ch8".to_string(), ..RunOptions::default() }));
+	assert_eq!(parse(&args("game.ch8")), Ok(run.clone()));
+	assert_eq!(parse(&args("run game.ch8")), Ok(run));
+
+	assert!(parse(&[]).is_err());
+	assert!(parse(&args("info")).is_err());
+	assert!(parse(&args("asm game.8o -x out.ch8")).is_err());
+	assert!(parse(&args("test game.ch8 --frames 0")).is_err());
+}
+
+#[test]
+fn run_options() {
+	let options = match parse(&args("game.ch8 --ips 700 --quirks schip --mute --headless --frames 10 --record clip.y4m --break 0x204 --when v3==0x10 --gdb 9000 --trace-range 0x200-0x210")).unwrap() {
+		Command::Run(options) => options,
+		other => panic!("{:?}", other)
+	};
+
+	assert_eq!(options.rom, "game.ch8");
+	assert_eq!(options.settings, Settings { ips: Some(700), quirks: Some(Quirks::SCHIP), mute: Some(true), ..Settings::default() });
+	assert!(options.headless);
+	assert_eq!(options.frames, Some(10));
+	assert_eq!(options.record, Some("clip.y4m".into()));
+	assert_eq!(options.breakpoints, vec![0x204]);
+	assert_eq!(options.conditions, vec![Condition::parse("v3==0x10").unwrap()]);
+	assert_eq!(options.gdb_port, Some(9000));
+	assert_eq!(options.trace_ranges, vec![(0x200, 0x210)]);
+
+	assert_eq!(parse(&args("game.ch8 --ips")), Err("--ips needs a value".to_string()));
+	assert_eq!(parse(&args("game.ch8 --ips fast")), Err("--ips fast: bad instructions per second: fast".to_string()));
+	assert_eq!(parse(&args("game.ch8 --colour red")), Err("unknown option --colour".to_string()));
+	assert!(parse(&args("game.ch8 other.ch8")).is_err());
+	assert!(parse(&args("--mute")).is_err());
+	assert_eq!(parse(&args("game.ch8 --record clip.mp4")), Err("--record clip.mp4: recordings are .gif, .y4m or .ppm files".to_string()));
+}
+
+#[test]
+fn config_file() {
+	let settings = Settings::parse("
+		# defaults
+		ips = 1_200
+		scale = 4
+		palette = ['#102030', \"#FFFFFF\"]
+
+		[unused]
+	").unwrap();
+	assert_eq!(settings.ips, Some(1200));
+	assert_eq!(settings.scale, Some(4));
+	assert_eq!(settings.palette, Some(Palette::CLASSIC.with(&[[0x10, 0x20, 0x30, 255], [255, 255, 255, 255]])));
+	assert_eq!(Settings::parse("palette = \"Amber\"").unwrap().palette, Some(Palette::AMBER));
+	assert_eq!(Settings::parse("filter = 'phosphor:0.5'").unwrap().filter, Some(Filter::Phosphor(0.5)));
+	assert_eq!(Settings::parse("on-error = 'log'").unwrap().on_error, Some(ErrorPolicy::parse("log").unwrap()));
+
+	assert!(matches!(Settings::parse("colour = 3"), Err(ConfigError::Syntax(_))));
+	assert!(matches!(Settings::parse("ips = \"fast\""), Err(ConfigError::Syntax(_))));
+	assert!(matches!(Settings::parse("mute = 1.5"), Err(ConfigError::Syntax(_))));
+	assert_eq!(Settings::parse("\nscale = [1").unwrap_err().to_string(), "config file: line 2: expected , or ] in a list");
+	assert!(Settings::parse("palette = '#1234'").is_err());
+}
+
+#[test]
+fn sound_settings() {
+	let file = Settings::parse("tone-frequency = 220.5\nwaveform = 'sine'").unwrap();
+	let options = match parse(&args("game.ch8 --tone-frequency 880 --beep beep.wav")).unwrap() {
+		Command::Run(options) => options,
+		other => panic!("{:?}", other)
+	};
+
+	let mut machine = CHIP8::new(&[]).unwrap();
+	let mut config = Config::default();
+	file.apply(&mut machine, &mut config);
+	assert_eq!((config.audio.frequency, config.audio.waveform), (220.5, Waveform::Sine));
+	options.settings.apply(&mut machine, &mut config);
+	assert_eq!(config.audio.frequency, 880.0);
+	assert_eq!(config.audio.sample, Some(PathBuf::from("beep.wav")));
+
+	assert!(Settings::parse("tone-frequency = 5").is_err());
+	assert!(parse(&args("game.ch8 --waveform saw")).is_err());
+}
+
+#[test]
+fn command_line_settings_take_priority() {
+	let file = Settings::parse("ips = 600\nscale = 4\nmute = true").unwrap();
+	let command_line = Settings { ips: Some(1200), ..Settings::default() };
+
+	// the way run applies them, the command line after the file
+	let mut machine = CHIP8::new(&[]).unwrap();
+	let mut config = Config::default();
+	file.apply(&mut machine, &mut config);
+	command_line.apply(&mut machine, &mut config);
+	assert_eq!(machine.instructions_per_frame(), 20);
+	assert_eq!(config.scale, 4);
+	assert_eq!(config.audio.volume, 0.0);
+}
@@ -0,0 +1,138 @@
+// Just enough TOML for the config file: key = value lines, with strings, integers, floats, booleans and
+// arrays of them, and [section] headers. Keys under a header are read as section.key
+#[derive(Clone, Debug, PartialEq)]
+pub enum Value {
+	String(String),
+	Integer(i64),
+	Float(f64),
+	Bool(bool),
+	Array(Vec<Value>)
+}
+
+impl Value {
+	pub fn type_name(&self) -> &'static str {
+		match self {
+			Value::String(_) => "a string",
+			Value::Integer(_) => "an integer",
+			Value::Float(_) => "a number",
+			Value::Bool(_) => "true or false",
+			Value::Array(_) => "a list"
+		}
+	}
+}
+
+// The keys and values in the order they're written. Errors say which line they're on
+pub fn parse(text: &str) -> Result<Vec<(String, Value)>, String> {
+	let mut section = String::new();
+	let mut entries = Vec::new();
+
+	for (number, line) in text.lines().enumerate() {
+		let error = |reason: String| format!("line {}: {}", number + 1, reason);
+		let line = strip_comment(line).trim();
+		if line.is_empty() { continue; }
+
+		if let Some(header) = line.strip_prefix('[') {
+			let name = header.strip_suffix(']').map(str::trim).filter(|n| is_key(n))
+							.ok_or_else(|| error(format!("bad section header {}", line)))?;
+			section = format!("{}.", name);
+			continue;
+		}
+
+		let (key, value) = line.split_once('=').ok_or_else(|| error(format!("expected <key> = <value>, found {}", line)))?;
+		let key = key.trim().trim_matches('"');
+		if !is_key(key) {
+			return Err(error(format!("bad key {}", key)));
+		}
+
+		let (value, rest) = parse_value(value.trim()).map_err(error)?;
+		if !rest.trim().is_empty() {
+			return Err(error(format!("unexpected {} after the value", rest.trim())));
+		}
+		entries.push((format!("{}{}", section, key), value));
+	}
+
+	Ok(entries)
+}
+
+fn is_key(text: &str) -> bool {
+	!text.is_empty() && text.chars().all(|c| c.is_ascii_alphanumeric() || c == '_' || c == '-' || c == '.')
+}
+
+// Everything before a # that isn't in a string
+fn strip_comment(line: &str) -> &str {
+	let mut in_string = None;
+	for (i, c) in line.char_indices() {
+		match (c, in_string) {
+			('"', None) | ('\'', None) => in_string = Some(c),
+			(c, Some(quote)) if c == quote => in_string = None,
+			('#', None) => return &line[.. i],
+			_ => ()
+		}
+	}
+	line
+}
+
+// Reads a value from the start of text, returning what's after it
+fn parse_value(text: &str) -> Result<(Value, &str), String> {
+	if let Some(rest) = text.strip_prefix('"') {
+		return parse_string(rest);
+	}
+	if let Some(rest) = text.strip_prefix('\'') { // a literal string, without escapes
+		let end = rest.find('\'').ok_or("the string isn't closed")?;
+		return Ok((Value::String(rest[.. end].to_string()), &rest[end + 1 ..]));
+	}
+	if let Some(mut rest) = text.strip_prefix('[') {
+		let mut items = Vec::new();
+		loop {
+			rest = rest.trim_start();
+			if let Some(after) = rest.strip_prefix(']') {
+				return Ok((Value::Array(items), after));
+			}
+			let (item, after) = parse_value(rest)?;
+			items.push(item);
+			rest = after.trim_start();
+			match rest.strip_prefix(',') {
+				Some(after) => rest = after,
+				None if rest.starts_with(']') => (),
+				None => return Err("expected , or ] in a list".to_string())
+			}
+		}
+	}
+
+	let end = text.find(|c: char| c == ',' || c == ']' || c.is_whitespace()).unwrap_or(text.len());
+	let (word, rest) = text.split_at(end);
+	let value = match word {
+		"true" => Value::Bool(true),
+		"false" => Value::Bool(false),
+		_ => {
+			let digits = word.replace('_', "");
+			if let Some(hex) = digits.strip_prefix("0x") {
+				Value::Integer(i64::from_str_radix(hex, 16).map_err(|_| format!("bad number {}", word))?)
+			} else if let Ok(n) = digits.parse() {
+				Value::Integer(n)
+			} else {
+				Value::Float(digits.parse().map_err(|_| format!("bad value {}", word))?)
+			}
+		}
+	};
+	Ok((value, rest))
+}
+
+fn parse_string(text: &str) -> Result<(Value, &str), String> {
+	let mut value = String::new();
+	let mut chars = text.char_indices();
+	while let Some((i, c)) = chars.next() {
+		match c {
+			'"' => return Ok((Value::String(value), &text[i + 1 ..])),
+			'\\' => value.push(match chars.next().map(|(_, c)| c) {
+				Some('n') => '\n',
+				Some('t') => '\t',
+				Some(c @ ('"' | '\\')) => c,
+				Some(c) => return Err(format!("unknown escape \\{}", c)),
+				None => break
+			}),
+			c => value.push(c)
+		}
+	}
+	Err("the string isn't closed".to_string())
+}
@@ -0,0 +1,224 @@
+// Just enough of a JSON reader for the ROM database
+use std::{ iter::Peekable, str::Chars };
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum Json {
+	Null,
+	Bool(bool),
+	Number(f64),
+	String(String),
+	Array(Vec<Json>),
+	Object(Vec<(String, Json)>) // in the order they're written
+}
+
+impl Json {
+	pub fn parse(text: &str) -> Result<Json, String> {
+		let mut parser = Parser { chars: text.chars().peekable(), line: 1 };
+		let value = parser.value()?;
+		parser.skip_space();
+		match parser.chars.peek().copied() {
+			None => Ok(value),
+			Some(c) => Err(parser.error(&format!("unexpected {} after the end", c)))
+		}
+	}
+
+	// The member called key, if this is an object that has one
+	pub fn get(&self, key: &str) -> Option<&Json> {
+		match self {
+			Json::Object(members) => members.iter().find(|(k, _)| k == key).map(|(_, v)| v),
+			_ => None
+		}
+	}
+
+	pub fn as_str(&self) -> Option<&str> {
+		match self {
+			Json::String(s) => Some(s),
+			_ => None
+		}
+	}
+
+	pub fn as_f64(&self) -> Option<f64> {
+		match self {
+			Json::Number(n) => Some(*n),
+			_ => None
+		}
+	}
+
+	pub fn as_bool(&self) -> Option<bool> {
+		match self {
+			Json::Bool(b) => Some(*b),
+			_ => None
+		}
+	}
+
+	pub fn as_array(&self) -> Option<&[Json]> {
+		match self {
+			Json::Array(items) => Some(items),
+			_ => None
+		}
+	}
+
+	pub fn members(&self) -> &[(String, Json)] {
+		match self {
+			Json::Object(members) => members,
+			_ => &[]
+		}
+	}
+}
+
+struct Parser<'a> {
+	chars: Peekable<Chars<'a>>,
+	line: usize // for error messages
+}
+
+impl Parser<'_> {
+	fn error(&self, message: &str) -> String {
+		format!("line {}: {}", self.line, message)
+	}
+
+	fn next(&mut self) -> Option<char> {
+		let c = self.chars.next();
+		if c == Some('\n') {
+			self.line += 1;
+		}
+		c
+	}
+
+	fn skip_space(&mut self) {
+		while self.chars.peek().is_some_and(|c| c.is_whitespace()) {
+			self.next();
+		}
+	}
+
+	fn expect(&mut self, expected: char) -> Result<(), String> {
+		self.skip_space();
+		match self.next() {
+			Some(c) if c == expected => Ok(()),
+			Some(c) => Err(self.error(&format!("expected {}, found {}", expected, c))),
+			None => Err(self.error(&format!("expected {}, the text ends too early", expected)))
+		}
+	}
+
+	fn value(&mut self) -> Result<Json, String> {
+		self.skip_space();
+		match self.chars.peek().copied() {
+			Some('{') => self.object(),
+			Some('[') => self.array(),
+			Some('"') => self.string().map(Json::String),
+			Some(c) if c == '-' || c.is_ascii_digit() => self.number(),
+			Some(c) if c.is_ascii_alphabetic() => {
+				let mut word = String::new();
+				while let Some(c) = self.chars.peek().copied().filter(char::is_ascii_alphabetic) {
+					word.push(c);
+					self.next();
+				}
+				match word.as_str() {
+					"true" => Ok(Json::Bool(true)),
+					"false" => Ok(Json::Bool(false)),
+					"null" => Ok(Json::Null),
+					_ => Err(self.error(&format!("unexpected {}", word)))
+				}
+			},
+			Some(c) => Err(self.error(&format!("unexpected {}", c))),
+			None => Err(self.error("the text ends too early"))
+		}
+	}
+
+	fn object(&mut self) -> Result<Json, String> {
+		self.expect('{')?;
+		let mut members = Vec::new();
+		self.skip_space();
+		if self.chars.peek() == Some(&'}') {
+			self.next();
+			return Ok(Json::Object(members));
+		}
+
+		loop {
+			self.skip_space();
+			let key = self.string()?;
+			self.expect(':')?;
+			members.push((key, self.value()?));
+
+			self.skip_space();
+			match self.next() {
+				Some(',') => (),
+				Some('}') => return Ok(Json::Object(members)),
+				_ => return Err(self.error("expected , or } in an object"))
+			}
+		}
+	}
+
+	fn array(&mut self) -> Result<Json, String> {
+		self.expect('[')?;
+		let mut items = Vec::new();
+		self.skip_space();
+		if self.chars.peek() == Some(&']') {
+			self.next();
+			return Ok(Json::Array(items));
+		}
+
+		loop {
+			items.push(self.value()?);
+			self.skip_space();
+			match self.next() {
+				Some(',') => (),
+				Some(']') => return Ok(Json::Array(items)),
+				_ => return Err(self.error("expected , or ] in an array"))
+			}
+		}
+	}
+
+	fn string(&mut self) -> Result<String, String> {
+		self.expect('"')?;
+		let mut text = String::new();
+		loop {
+			match self.next() {
+				Some('"') => return Ok(text),
+				Some('\\') => {
+					let escaped = match self.next() {
+						Some('n') => '\n',
+						Some('t') => '\t',
+						Some('r') => '\r',
+						Some('b') => '\u{8}',
+						Some('f') => '\u{c}',
+						Some('u') => self.unicode_escape()?,
+						Some(c) => c, // \" \\ and \/
+						None => return Err(self.error("the text ends in a string"))
+					};
+					text.push(escaped);
+				},
+				Some(c) => text.push(c),
+				None => return Err(self.error("the text ends in a string"))
+			}
+		}
+	}
+
+	// \uXXXX, with the second half of a surrogate pair after it if it's the first
+	fn unicode_escape(&mut self) -> Result<char, String> {
+		let first = self.hex4()?;
+		let code = if (0xD800 .. 0xDC00).contains(&first) {
+			if self.next() != Some('\\') || self.next() != Some('u') {
+				return Err(self.error("half of a surrogate pair"));
+			}
+			let second = self.hex4()?;
+			0x10000 + ((first - 0xD800) << 10) + (second.wrapping_sub(0xDC00) & 0x3FF)
+		} else {
+			first
+		};
+		Ok(char::from_u32(code).unwrap_or(char::REPLACEMENT_CHARACTER))
+	}
+
+	fn hex4(&mut self) -> Result<u32, String> {
+		let digits: String = (0 .. 4).filter_map(|_| self.next()).collect();
+		u32::from_str_radix(&digits, 16).map_err(|_| self.error(&format!("bad escape \\u{}", digits)))
+	}
+
+	fn number(&mut self) -> Result<Json, String> {
+		let mut text = String::new();
+		while let Some(c) = self.chars.peek().copied().filter(|c| c.is_ascii_digit() || "+-.eE".contains(*c)) {
+			text.push(c);
+			self.next();
+		}
+		text.parse().map(Json::Number).map_err(|_| self.error(&format!("bad number {}", text)))
+	}
+}
@@ -0,0 +1,227 @@
+use crate::{ CHIP8, Config, Platform, Quirks, IndexIncrement, Chip8Key, HostKey };
+
+use std::{
+	collections::HashMap,
+	error::Error,
+	fmt, fs, io,
+	path::Path
+};
+
+mod json;
+mod sha1;
+#[cfg(test)]
+mod tests;
+
+use json::Json;
+pub use sha1::{ sha1, sha1_hex };
+
+// The programs this emulator knows the settings for, in the format of the community chip-8-database's
+// programs.json (https://github.com/chip-8/chip-8-database). Replace it with a newer copy of that file
+// to recognise more programs
+const BUNDLED_DATABASE: &str = include_str!("programs.json");
+
+#[derive(Debug)]
+pub enum DatabaseError {
+	Io(io::Error),
+	Syntax(String)
+}
+
+impl fmt::Display for DatabaseError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			DatabaseError::Io(e) => write!(f, "couldn't read the ROM database: {}", e),
+			DatabaseError::Syntax(reason) => write!(f, "bad ROM database: {}", reason)
+		}
+	}
+}
+
+impl Error for DatabaseError {}
+
+impl From<io::Error> for DatabaseError {
+	fn from(e: io::Error) -> DatabaseError {
+		DatabaseError::Io(e)
+	}
+}
+
+// What the database knows about one ROM. Anything it doesn't say is left as it was
+#[derive(Clone, Debug, PartialEq)]
+pub struct RomInfo {
+	pub title: String,
+	pub platform: Option<Platform>,
+	pub quirks: Option<Quirks>,
+	pub instructions_per_frame: Option<u32>,
+	pub colours: Vec<[u8; 4]>, // for pixels set in no plane, the first, the second and both. Often only the first two
+	pub keys: Vec<(Chip8Key, HostKey)> // the CHIP-8 keys the program uses for moving and acting, on the arrow keys
+}
+
+impl RomInfo {
+	// Sets the machine and the frontend up the way the program expects
+	pub fn apply(&self, machine: &mut CHIP8, config: &mut Config) {
+		if let Some(platform) = self.platform {
+			machine.set_platform(platform);
+		}
+		if let Some(quirks) = self.quirks {
+			machine.set_quirks(quirks);
+		}
+		if let Some(ipf) = self.instructions_per_frame {
+			machine.set_instructions_per_frame(ipf);
+		}
+		config.palette = config.palette.with(&self.colours);
+		for (key, host_key) in self.keys.iter() {
+			config.keymap.bind(*key, host_key.clone());
+		}
+		config.title = Some(self.title.clone());
+	}
+}
+
+// ROMs by the SHA-1 of their contents, in lowercase hex
+#[derive(Clone, Debug, Default)]
+pub struct RomDatabase {
+	roms: HashMap<String, RomInfo>
+}
+
+impl RomDatabase {
+	// The database that comes with the emulator
+	pub fn bundled() -> RomDatabase {
+		RomDatabase::parse(BUNDLED_DATABASE).expect("the bundled ROM database is valid")
+	}
+
+	// The bundled database, with the ROMs in the file at path replacing it where they're in both. A missing
+	// file is the same as an empty one
+	pub fn with_overrides(path: &Path) -> Result<RomDatabase, DatabaseError> {
+		let mut database = RomDatabase::bundled();
+		if path.exists() {
+			database.merge(RomDatabase::load(path)?);
+		}
+		Ok(database)
+	}
+
+	pub fn load(path: &Path) -> Result<RomDatabase, DatabaseError> {
+		RomDatabase::parse(&fs::read_to_string(path)?)
+	}
+
+	// Reads a list of programs in chip-8-database's format. Settings this emulator has no use for are skipped
+	pub fn parse(text: &str) -> Result<RomDatabase, DatabaseError> {
+		let programs = Json::parse(text).map_err(DatabaseError::Syntax)?;
+		let programs = programs.as_array().ok_or_else(|| DatabaseError::Syntax("expected a list of programs".to_string()))?;
+
+		let mut roms = HashMap::new();
+		for program in programs {
+			let title = program.get("title").and_then(Json::as_str).unwrap_or("Untitled");
+			for (hash, rom) in program.get("roms").map_or(&[][..], Json::members) {
+				roms.insert(hash.to_ascii_lowercase(), rom_info(title, rom));
+			}
+		}
+		Ok(RomDatabase { roms })
+	}
+
+	// Adds other's ROMs, replacing the ones already known
+	pub fn merge(&mut self, other: RomDatabase) {
+		self.roms.extend(other.roms);
+	}
+
+	pub fn lookup(&self, sha1: &str) -> Option<&RomInfo> {
+		self.roms.get(&sha1.to_ascii_lowercase())
+	}
+
+	pub fn len(&self) -> usize {
+		self.roms.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.roms.is_empty()
+	}
+}
+
+fn rom_info(title: &str, rom: &Json) -> RomInfo {
+	// the first platform the program runs on that this emulator has
+	let platform = rom.get("platforms").and_then(Json::as_array).unwrap_or(&[]).iter()
+						.filter_map(Json::as_str)
+						.find_map(|id| platform_quirks(id).map(|(platform, quirks)| (id, platform, quirks)));
+
+	// the platform's quirks, with the ones the program needs changed for it
+	let quirks = platform.map(|(id, _, quirks)| {
+		let overrides = rom.get("quirkyPlatforms").and_then(|q| q.get(id));
+		overrides.map_or(quirks, |o| apply_quirks(quirks, o))
+	});
+
+	let colours = rom.get("colors").and_then(|c| c.get("pixels")).and_then(Json::as_array).unwrap_or(&[]).iter()
+						.map_while(|c| c.as_str().and_then(parse_colour))
+						.take(4)
+						.collect();
+
+	let keys = rom.get("keys").map_or(&[][..], Json::members).iter()
+					.filter_map(|(action, key)| {
+						let host_key = match action.as_str() {
+							"up" => "Up",
+							"down" => "Down",
+							"left" => "Left",
+							"right" => "Right",
+							"a" => "Space",
+							"b" => "LShift",
+							_ => return None // the second player's keys are already on the keypad's keys
+						};
+						let key = Chip8Key::from_index(key.as_f64()? as u8)?;
+						Some((key, HostKey::Named(host_key.to_string())))
+					})
+					.collect();
+
+	RomInfo {
+		title: title.to_string(),
+		platform: platform.map(|(_, platform, _)| platform),
+		quirks,
+		instructions_per_frame: rom.get("tickrate").and_then(Json::as_f64).filter(|&t| t >= 1.0).map(|t| t as u32),
+		colours,
+		keys
+	}
+}
+
+// The platforms of chip-8-database this emulator can run programs for, and their quirks
+fn platform_quirks(id: &str) -> Option<(Platform, Quirks)> {
+	let modern = Quirks { vf_reset: false, ..Quirks::VIP };
+	match id {
+		"originalChip8" | "hybridVIP" => Some((Platform::Chip8, Quirks::VIP)),
+		"modernChip8" => Some((Platform::Chip8, modern)),
+		"chip48" => Some((Platform::Chip8, Quirks::CHIP48)),
+		"superchip1" | "superchip" => Some((Platform::SuperChip, Quirks::SCHIP)),
+		"xochip" => Some((Platform::XoChip, Quirks::XOCHIP)),
+		_ => None
+	}
+}
+
+// Applies quirks named like chip-8-database's on top of a platform's
+fn apply_quirks(mut quirks: Quirks, overrides: &Json) -> Quirks {
+	let (mut leave_i, mut increment_by_x) = (None, None);
+	for (name, value) in overrides.members() {
+		let on = match value.as_bool() {
+			Some(on) => on,
+			None => continue
+		};
+		match name.as_str() {
+			"shift" => quirks.shift = on,
+			"memoryLeaveIUnchanged" => leave_i = Some(on),
+			"memoryIncrementByX" => increment_by_x = Some(on),
+			"wrap" => quirks.clip = !on,
+			"jump" => quirks.jump_vx = on,
+			"logic" => quirks.vf_reset = on,
+			_ => () // vblank isn't emulated
+		}
+	}
+
+	// the two memory quirks both decide how I moves, so they're looked at together whatever order they're in.
+	// turning one off only undoes that one, and leaving I alone wins if both are on
+	quirks.index_increment = match (leave_i, increment_by_x, quirks.index_increment) {
+		(Some(true), _, _) => IndexIncrement::Unchanged,
+		(_, Some(true), _) => IndexIncrement::X,
+		(Some(false), _, IndexIncrement::Unchanged) | (_, Some(false), IndexIncrement::X) => IndexIncrement::XPlusOne,
+		(_, _, increment) => increment
+	};
+	quirks
+}
+
+// #rrggbb
+fn parse_colour(text: &str) -> Option<[u8; 4]> {
+	let hex = text.strip_prefix('#').filter(|h| h.len() == 6 && h.chars().all(|c| c.is_ascii_hexdigit()))?;
+	let channel = |i: usize| u8::from_str_radix(&hex[i .. i + 2], 16).ok();
+	Some([channel(0)?, channel(2)?, channel(4)?, 255])
+}
@@ -0,0 +1,53 @@
+// SHA-1, which the ROM database identifies programs by
+pub fn sha1(data: &[u8]) -> [u8; 20] {
+	let mut h: [u32; 5] = [0x6745_2301, 0xEFCD_AB89, 0x98BA_DCFE, 0x1032_5476, 0xC3D2_E1F0];
+
+	// the message is padded with a 1 bit, zeros, and its length in bits, to a multiple of 64 bytes
+	let mut message = data.to_vec();
+	message.push(0x80);
+	while message.len() % 64 != 56 {
+		message.push(0);
+	}
+	message.extend_from_slice(&((data.len() as u64) * 8).to_be_bytes());
+
+	for block in message.chunks(64) {
+		let mut w = [0u32; 80];
+		for (i, word) in block.chunks(4).enumerate() {
+			w[i] = u32::from_be_bytes([word[0], word[1], word[2], word[3]]);
+		}
+		for i in 16 .. 80 {
+			w[i] = (w[i - 3] ^ w[i - 8] ^ w[i - 14] ^ w[i - 16]).rotate_left(1);
+		}
+
+		let [mut a, mut b, mut c, mut d, mut e] = h;
+		for (i, &word) in w.iter().enumerate() {
+			let (f, k) = match i {
+				0 ..= 19 => ((b & c) | (!b & d), 0x5A82_7999),
+				20 ..= 39 => (b ^ c ^ d, 0x6ED9_EBA1),
+				40 ..= 59 => ((b & c) | (b & d) | (c & d), 0x8F1B_BCDC),
+				_ => (b ^ c ^ d, 0xCA62_C1D6)
+			};
+			let temp = a.rotate_left(5).wrapping_add(f).wrapping_add(e).wrapping_add(k).wrapping_add(word);
+			e = d;
+			d = c;
+			c = b.rotate_left(30);
+			b = a;
+			a = temp;
+		}
+
+		for (h, value) in h.iter_mut().zip([a, b, c, d, e]) {
+			*h = h.wrapping_add(value);
+		}
+	}
+
+	let mut digest = [0; 20];
+	for (bytes, value) in digest.chunks_mut(4).zip(h.iter()) {
+		bytes.copy_from_slice(&value.to_be_bytes());
+	}
+	digest
+}
+
+// The digest in lowercase hex, the way the database writes it
+pub fn sha1_hex(data: &[u8]) -> String {
+	sha1(data).iter().map(|b| format!("{:02x}", b)).collect()
+}
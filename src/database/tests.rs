@@ -0,0 +1,110 @@
+use super::{ RomDatabase, sha1_hex, json::Json };
+use crate::{ CHIP8, Config, Platform, Quirks, IndexIncrement, Chip8Key, HostKey };
+
+use std::fs;
+
+const PROGRAMS: &str = r##"[
+	{
+		"title": "Racer",
+		"authors": ["Someone"],
+		"roms": {
+			"0123456789ABCDEF0123456789ABCDEF01234567": {
+				"file": "racer.ch8",
+				"platforms": ["megachip8", "superchip"],
+				"quirkyPlatforms": { "superchip": { "memoryIncrementByX": true, "wrap": true, "vblank": true } },
+				"tickrate": 30,
+				"keys": { "left": 7, "right": 9, "player2Up": 1 },
+				"colors": { "pixels": ["#101820", "#F0e0d0"], "buzzer": "#ff0000" }
+			}
+		}
+	},
+	{ "title": "Café \"Demo\"", "roms": { "ffffffffffffffffffffffffffffffffffffffff": { "platforms": ["xochip"] } } }
+]"##;
+
+#[test]
+fn sha1_matches_the_standard_vectors() {
+	assert_eq!(sha1_hex(b""), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+	assert_eq!(sha1_hex(b"abc"), "a9993e364706816aba3e25717850c26c9cd0d89d");
+	assert_eq!(sha1_hex(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"), "84983e441c3bd26ebaae4aa1f95129e5e54670f1");
+}
+
+#[test]
+fn json() {
+	let value = Json::parse(r#" { "a": [1, -2.5e1, true, null], "b": "x\nyA", "c": {} } "#).unwrap();
+	assert_eq!(value.get("a"), Some(&Json::Array(vec![Json::Number(1.0), Json::Number(-25.0), Json::Bool(true), Json::Null])));
+	assert_eq!(value.get("b").and_then(Json::as_str), Some("x\nyA"));
+	assert_eq!(value.get("c"), Some(&Json::Object(Vec::new())));
+
+	assert!(Json::parse("[1, 2").is_err());
+	assert!(Json::parse("{\"a\" 1}").is_err());
+	assert_eq!(Json::parse("[\n1,\n]").unwrap_err(), "line 3: unexpected ]");
+}
+
+#[test]
+fn programs_are_found_by_hash() {
+	let database = RomDatabase::parse(PROGRAMS).unwrap();
+	assert_eq!(database.len(), 2);
+
+	let racer = database.lookup("0123456789abcdef0123456789abcdef01234567").unwrap();
+	assert_eq!(racer.title, "Racer");
+	assert_eq!(racer.platform, Some(Platform::SuperChip)); // there's no MegaChip
+	assert_eq!(racer.quirks, Some(Quirks { index_increment: IndexIncrement::X, clip: false, ..Quirks::SCHIP }));
+	assert_eq!(racer.instructions_per_frame, Some(30));
+	assert_eq!(racer.colours, vec![[0x10, 0x18, 0x20, 255], [0xF0, 0xE0, 0xD0, 255]]);
+	assert_eq!(racer.keys, vec![
+		(Chip8Key::from_index(7).unwrap(), HostKey::Named("Left".to_string())),
+		(Chip8Key::from_index(9).unwrap(), HostKey::Named("Right".to_string()))
+	]);
+
+	assert_eq!(database.lookup("ffffffffffffffffffffffffffffffffffffffff").unwrap().title, "Café \"Demo\"");
+	assert!(database.lookup("da39a3ee5e6b4b0d3255bfef95601890afd80709").is_none());
+	assert!(RomDatabase::parse("{}").is_err());
+
+	// colours that aren't hex, even ones 6 bytes long, are left out
+	let odd = RomDatabase::parse(r##"[{ "roms": { "aa": { "colors": { "pixels": ["#aé€", "#zzzzzz"] } } } }]"##).unwrap();
+	assert!(odd.lookup("aa").unwrap().colours.is_empty());
+	assert!(RomDatabase::bundled().lookup("").is_none());
+}
+
+#[test]
+fn memory_quirks_dont_depend_on_their_order() {
+	let quirks = |overrides: &str| {
+		let programs = format!(r#"[{{ "roms": {{ "aa": {{ "platforms": ["superchip"], "quirkyPlatforms": {{ "superchip": {} }} }} }} }}]"#, overrides);
+		RomDatabase::parse(&programs).unwrap().lookup("aa").unwrap().quirks.unwrap().index_increment
+	};
+
+	assert_eq!(quirks(r#"{ "memoryIncrementByX": true, "memoryLeaveIUnchanged": false }"#), IndexIncrement::X);
+	assert_eq!(quirks(r#"{ "memoryLeaveIUnchanged": false, "memoryIncrementByX": true }"#), IndexIncrement::X);
+	assert_eq!(quirks(r#"{ "memoryLeaveIUnchanged": false, "memoryIncrementByX": false }"#), IndexIncrement::XPlusOne);
+	assert_eq!(quirks(r#"{ "memoryIncrementByX": false }"#), Quirks::SCHIP.index_increment);
+}
+
+#[test]
+fn overrides_take_priority() {
+	let mut database = RomDatabase::parse(PROGRAMS).unwrap();
+	database.merge(RomDatabase::parse(r#"[{ "title": "Mine", "roms": { "FFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFFF": {} } }]"#).unwrap());
+
+	let mine = database.lookup("ffffffffffffffffffffffffffffffffffffffff").unwrap();
+	assert_eq!((mine.title.as_str(), mine.platform), ("Mine", None));
+	assert_eq!(database.len(), 2);
+}
+
+#[test]
+fn loaded_programs_are_hashed_and_set_up() {
+	let path = std::env::temp_dir().join(format!("chip8-database-{}.ch8", std::process::id()));
+	fs::write(&path, b"abc").unwrap();
+	let mut machine = CHIP8::open(&path, Platform::Chip8).unwrap();
+	fs::remove_file(&path).unwrap();
+	assert_eq!(machine.rom_sha1(), "a9993e364706816aba3e25717850c26c9cd0d89d");
+
+	let programs = PROGRAMS.replace("ffffffffffffffffffffffffffffffffffffffff", &machine.rom_sha1());
+	let database = RomDatabase::parse(&programs).unwrap();
+	let mut config = Config::default();
+	database.lookup(&machine.rom_sha1()).unwrap().apply(&mut machine, &mut config);
+
+	assert_eq!(machine.platform(), Platform::XoChip);
+	assert_eq!(machine.quirks(), Quirks::XOCHIP);
+	assert_eq!(machine.memory.len(), 65536);
+	assert_eq!(&machine.memory[0x200 .. 0x203], b"abc");
+	assert_eq!(config.title.as_deref(), Some("Café \"Demo\""));
+}
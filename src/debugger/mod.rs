@@ -0,0 +1,315 @@
+use crate::{
+	CHIP8, Config, ExecError, ErrorPolicy, StepOutcome, Instruction, MemoryAccess, AccessKind, decode,
+	asm::{ self, SourceMap },
+	disasm::{ self, Syntax }
+};
+
+use std::{ collections::BTreeSet, fmt };
+
+mod repl;
+mod watch;
+#[cfg(test)]
+mod tests;
+
+pub use repl::repl;
+pub use watch::{ Watchpoint, WatchKind, Condition, parse_range };
+
+// How many frames continue runs in the REPL before giving control back, when it isn't told
+const DEFAULT_CONTINUE_FRAMES: u64 = 600;
+
+// Why the debugger paused the machine
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Stop {
+	Breakpoint(u16),
+	Watchpoint(MemoryAccess), // the instruction that was just run accessed watched memory
+	Condition(String), // the condition with this text became true
+	Stepped // a step, step-over or step-out finished
+}
+
+impl fmt::Display for Stop {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Stop::Breakpoint(addr) => write!(f, "Breakpoint at {:03X}", addr),
+			Stop::Watchpoint(access) => {
+				let kind = match access.kind { AccessKind::Read => "Read", AccessKind::Write => "Wrote" };
+				write!(f, "Watchpoint: {} {} bytes at {:03X}", kind, access.len, access.start)
+			},
+			Stop::Condition(text) => write!(f, "Condition is true: {}", text),
+			Stop::Stepped => write!(f, "Stepped")
+		}
+	}
+}
+
+// What's left to run before a step-over or step-out pauses again
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Until {
+	StackBelow(u16) // the stack has been popped below this depth
+}
+
+// Pauses, steps and breaks a machine. Frontends run frames through the debugger instead of the machine,
+// instructions are run one at a time so it can stop in the middle of a frame and carry on from there
+#[derive(Clone, Debug, Default)]
+pub struct Debugger {
+	breakpoints: BTreeSet<u16>,
+	paused: bool,
+	until: Option<Until>,
+	stopped_at: Option<u16>, // the breakpoint the machine is paused on, which resuming doesn't stop at again
+	cycle: u32, // how many of the current frame's instructions have been run
+	source_map: Option<SourceMap>,
+	watchpoints: Vec<Watchpoint>,
+	conditions: Vec<Condition>
+}
+
+impl Debugger {
+	pub fn new() -> Debugger {
+		Debugger::default()
+	}
+
+	// A debugger with the configured breakpoints, and the source map next to the program if it has one
+	pub fn for_program(config: &Config, program_path: Option<&str>) -> Debugger {
+		let mut debugger = Debugger::new();
+		for &addr in config.breakpoints.iter() {
+			debugger.add_breakpoint(addr);
+		}
+		for &watchpoint in config.watchpoints.iter() {
+			debugger.add_watchpoint(watchpoint);
+		}
+		for condition in config.conditions.iter() {
+			debugger.add_condition(condition.clone());
+		}
+
+		let map_path = program_path.map(asm::source_map_path).filter(|p| p.exists());
+		if let Some(path) = map_path {
+			match SourceMap::load(&path) {
+				Ok(map) => debugger.set_source_map(Some(map)),
+				Err(e) => eprintln!("Couldn't load the source map {}: {}", path.display(), e)
+			}
+		}
+		debugger
+	}
+
+	// Shows which source line each instruction came from, for programs built with the assembler
+	pub fn set_source_map(&mut self, source_map: Option<SourceMap>) {
+		self.source_map = source_map;
+	}
+
+	pub fn is_paused(&self) -> bool {
+		self.paused
+	}
+
+	pub fn pause(&mut self) {
+		self.paused = true;
+		self.until = None;
+	}
+
+	pub fn resume(&mut self) {
+		self.paused = false;
+	}
+
+	pub fn add_breakpoint(&mut self, addr: u16) {
+		self.breakpoints.insert(addr);
+	}
+
+	// Returns whether there was a breakpoint there
+	pub fn remove_breakpoint(&mut self, addr: u16) -> bool {
+		self.breakpoints.remove(&addr)
+	}
+
+	pub fn breakpoints(&self) -> impl Iterator<Item = u16> + '_ {
+		self.breakpoints.iter().copied()
+	}
+
+	pub fn add_watchpoint(&mut self, watchpoint: Watchpoint) {
+		self.watchpoints.push(watchpoint);
+	}
+
+	// Returns whether there was a watchpoint like it
+	pub fn remove_watchpoint(&mut self, watchpoint: &Watchpoint) -> bool {
+		let before = self.watchpoints.len();
+		self.watchpoints.retain(|w| w != watchpoint);
+		self.watchpoints.len() != before
+	}
+
+	pub fn watchpoints(&self) -> &[Watchpoint] {
+		&self.watchpoints
+	}
+
+	// Stops when the condition goes from false to true. Watching I is a condition on it, see Condition::index_in
+	pub fn add_condition(&mut self, condition: Condition) {
+		self.conditions.push(condition);
+	}
+
+	pub fn conditions(&self) -> &[Condition] {
+		&self.conditions
+	}
+
+	// Removes every watchpoint and condition
+	pub fn clear_watches(&mut self) {
+		self.watchpoints.clear();
+		self.conditions.clear();
+	}
+
+	// Runs what's left of the current frame, unless paused. Returns why it stopped if it paused partway,
+	// the rest of the frame is run once it's resumed
+	pub fn run_frame(&mut self, machine: &mut CHIP8, policy: &ErrorPolicy) -> Result<Option<Stop>, ExecError> {
+		while !self.paused {
+			if self.breakpoints.contains(&machine.pc) && self.stopped_at != Some(machine.pc) && !machine.exited {
+				self.paused = true;
+				self.until = None;
+				self.stopped_at = Some(machine.pc);
+				return Ok(Some(Stop::Breakpoint(machine.pc)));
+			}
+
+			let (finished_frame, triggered) = self.cycle(machine, policy)?;
+
+			if let Some(stop) = triggered {
+				self.pause();
+				return Ok(Some(stop));
+			}
+
+			if let Some(Until::StackBelow(depth)) = self.until {
+				if machine.sp < depth {
+					self.pause();
+					return Ok(Some(Stop::Stepped));
+				}
+			}
+
+			if finished_frame {
+				break;
+			}
+		}
+
+		Ok(None)
+	}
+
+	// Runs a single instruction and pauses. Returns the watchpoint or condition it set off, if any
+	pub fn step(&mut self, machine: &mut CHIP8, policy: &ErrorPolicy) -> Result<Stop, ExecError> {
+		self.pause();
+		let (_, triggered) = self.cycle(machine, policy)?;
+		Ok(triggered.unwrap_or(Stop::Stepped))
+	}
+
+	// Starts running until the subroutine the machine is about to call returns. Anything other than a call
+	// is just stepped
+	pub fn step_over(&mut self, machine: &mut CHIP8, policy: &ErrorPolicy) -> Result<Option<Stop>, ExecError> {
+		match current_instruction(machine) {
+			Instruction::Call { .. } => {
+				self.until = Some(Until::StackBelow(machine.sp + 1));
+				self.resume();
+				Ok(None)
+			},
+			_ => self.step(machine, policy).map(Some)
+		}
+	}
+
+	// Starts running until the current subroutine returns. Outside of a subroutine it just resumes
+	pub fn step_out(&mut self, machine: &CHIP8) {
+		self.until = if machine.sp > 0 { Some(Until::StackBelow(machine.sp)) } else { None };
+		self.resume();
+	}
+
+	// Runs one instruction, finishing the frame if it was the frame's last. Returns whether it was, and the
+	// watchpoint or condition the instruction set off
+	fn cycle(&mut self, machine: &mut CHIP8, policy: &ErrorPolicy) -> Result<(bool, Option<Stop>), ExecError> {
+		let before: Vec<bool> = self.conditions.iter().map(|c| c.eval(machine)).collect();
+
+		let outcome = machine.run_instruction(policy)?;
+		self.stopped_at = None;
+		self.cycle += 1;
+
+		let finished_frame = self.cycle >= machine.instructions_per_frame || outcome == StepOutcome::Exited;
+		if finished_frame {
+			machine.end_frame();
+			self.cycle = 0;
+		}
+
+		let watched = machine.last_access().filter(|access| self.watchpoints.iter().any(|w| w.matches(access)));
+		let became_true = self.conditions.iter().zip(before)
+							.find(|(condition, was_true)| !was_true && condition.eval(machine));
+
+		let triggered = match (watched, became_true) {
+			(Some(access), _) => Some(Stop::Watchpoint(access)),
+			(None, Some((condition, _))) => Some(Stop::Condition(condition.text().to_string())),
+			(None, None) => None
+		};
+		Ok((finished_frame, triggered))
+	}
+
+	// What the machine is doing, shown whenever it pauses: the instruction it's on and the registers
+	pub fn status(&self, machine: &CHIP8) -> String {
+		format!("{}\n{}", self.describe_instruction(machine), Registers::of(machine))
+	}
+
+	// The instruction at pc, with the source line it came from if there's a source map
+	pub fn describe_instruction(&self, machine: &CHIP8) -> String {
+		let pc = machine.pc;
+		let instruction = current_instruction(machine);
+		let long = read_word(machine, pc.wrapping_add(2));
+		let mut text = format!("{:03X}  {}", pc, disasm::format_instruction(instruction, long, Syntax::Octo));
+
+		if let Some(line) = self.source_map.as_ref().and_then(|m| m.line(pc)) {
+			text.push_str(&format!("    (line {})", line));
+		}
+		text
+	}
+}
+
+fn read_word(machine: &CHIP8, addr: u16) -> u16 {
+	let byte = |a: u16| machine.memory.get(a as usize).copied().unwrap_or(0) as u16;
+	byte(addr) << 8 | byte(addr.wrapping_add(1))
+}
+
+fn current_instruction(machine: &CHIP8) -> Instruction {
+	decode(read_word(machine, machine.pc))
+}
+
+// A snapshot of everything a program can see of the machine besides memory and the screen
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Registers {
+	pub v: [u8; 16],
+	pub i: u16,
+	pub pc: u16,
+	pub sp: u16,
+	pub stack: Vec<u16>, // the return addresses in use, oldest first
+	pub delay_timer: u8,
+	pub sound_timer: u8,
+	pub keys: [bool; 16]
+}
+
+impl Registers {
+	pub fn of(machine: &CHIP8) -> Registers {
+		let mut keys = [false; 16];
+		for (pressed, &key) in keys.iter_mut().zip(machine.key.iter()) {
+			*pressed = key != 0;
+		}
+
+		Registers {
+			v: machine.V,
+			i: machine.I,
+			pc: machine.pc,
+			sp: machine.sp,
+			stack: machine.stack[.. machine.sp as usize].to_vec(),
+			delay_timer: machine.delay_timer,
+			sound_timer: machine.sound_timer,
+			keys
+		}
+	}
+}
+
+impl fmt::Display for Registers {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		writeln!(f, "pc {:03X}  i {:03X}  sp {}  delay {:02X}  sound {:02X}", self.pc, self.i, self.sp, self.delay_timer, self.sound_timer)?;
+
+		for (half, registers) in self.v.chunks(8).enumerate() {
+			let values: Vec<String> = registers.iter().enumerate()
+										.map(|(n, value)| format!("v{:x} {:02X}", half * 8 + n, value))
+										.collect();
+			writeln!(f, "{}", values.join("  "))?;
+		}
+
+		let stack: Vec<String> = self.stack.iter().map(|addr| format!("{:03X}", addr)).collect();
+		let keys: Vec<String> = (0 .. 16).filter(|&k| self.keys[k]).map(|k| format!("{:X}", k)).collect();
+		writeln!(f, "stack {}", if stack.is_empty() { "-".to_string() } else { stack.join(" ") })?;
+		write!(f, "keys {}", if keys.is_empty() { "-".to_string() } else { keys.join(" ") })
+	}
+}
@@ -0,0 +1,199 @@
+use crate::{
+	CHIP8, ErrorPolicy, decode,
+	debugger::{
+		Debugger, Stop, Registers, Watchpoint, Condition, DEFAULT_CONTINUE_FRAMES, read_word,
+		watch::{ parse_address, parse_range }
+	},
+	disasm::{ self, Syntax }
+};
+
+use std::io::{ self, BufRead, Write };
+
+const HELP: &str = "\
+c, continue [frames]   run until a breakpoint, for at most 600 frames unless told otherwise
+s, step [count]        run one instruction, or count of them
+n, next                step over a subroutine call
+o, out                 run until the current subroutine returns
+b, break [addr]        set a breakpoint, or list them with the watchpoints and conditions
+d, delete <addr>       remove a breakpoint
+w, watch <range>[:k]   stop when memory in the range is read (k = r), written (w) or either (rw)
+iw, iwatch <range>     stop when I points into the range
+when <condition>       stop when a condition like v3 == 0x10 && delay == 0 becomes true
+unwatch                remove every watchpoint and condition
+r, regs                show the registers, timers, stack and keys
+l, list [count]        show the instructions from pc
+q, quit                stop debugging
+";
+
+// A debugger prompt on a terminal, for headless runs. Reads commands until quit or the end of the input
+pub fn repl<R: BufRead, W: Write>(machine: &mut CHIP8, debugger: &mut Debugger, policy: &ErrorPolicy,
+									input: R, mut output: W) -> io::Result<()> {
+	debugger.pause();
+	writeln!(output, "{}", debugger.describe_instruction(machine))?;
+
+	let mut lines = input.lines();
+	loop {
+		write!(output, "(chip8) ")?;
+		output.flush()?;
+
+		let line = match lines.next() {
+			Some(line) => line?,
+			None => return Ok(())
+		};
+		let mut words = line.split_whitespace();
+		let command = match words.next() {
+			Some(c) => c,
+			None => continue
+		};
+		let argument = words.next();
+		let rest = line.trim_start()[command.len() ..].trim(); // everything after the command
+
+		match command {
+			"c" | "continue" => {
+				let frames = match argument.map(str::parse) {
+					None => DEFAULT_CONTINUE_FRAMES,
+					Some(Ok(frames)) => frames,
+					Some(Err(_)) => { writeln!(output, "Not a number of frames: {}", argument.unwrap())?; continue; }
+				};
+				debugger.resume();
+				run(machine, debugger, policy, frames, &mut output)?;
+			},
+			"s" | "step" => {
+				let count = match argument.map(str::parse) {
+					None => 1,
+					Some(Ok(count)) => count,
+					Some(Err(_)) => { writeln!(output, "Not a number of steps: {}", argument.unwrap())?; continue; }
+				};
+				for _ in 0 .. count {
+					if machine.has_exited() { break; }
+					match debugger.step(machine, policy) {
+						Ok(Stop::Stepped) => (),
+						Ok(stop) => {
+							writeln!(output, "{}", stop)?;
+							break;
+						},
+						Err(e) => {
+							writeln!(output, "Halted: {}", e)?;
+							break;
+						}
+					}
+				}
+				show_stop(machine, debugger, &mut output)?;
+			},
+			"n" | "next" => match debugger.step_over(machine, policy) {
+				Ok(Some(stop)) => {
+					if stop != Stop::Stepped {
+						writeln!(output, "{}", stop)?;
+					}
+					show_stop(machine, debugger, &mut output)?;
+				},
+				Ok(None) => run(machine, debugger, policy, DEFAULT_CONTINUE_FRAMES, &mut output)?,
+				Err(e) => writeln!(output, "Halted: {}", e)?
+			},
+			"o" | "out" | "finish" => {
+				debugger.step_out(machine);
+				run(machine, debugger, policy, DEFAULT_CONTINUE_FRAMES, &mut output)?;
+			},
+			"b" | "break" => match argument {
+				Some(a) => match parse_address(a) {
+					Some(addr) => {
+						debugger.add_breakpoint(addr);
+						writeln!(output, "Breakpoint at {:03X}", addr)?;
+					},
+					None => writeln!(output, "Not an address: {}", a)?
+				},
+				None => {
+					let breakpoints: Vec<String> = debugger.breakpoints().map(|b| format!("{:03X}", b)).collect();
+					writeln!(output, "Breakpoints: {}", if breakpoints.is_empty() { "none".to_string() } else { breakpoints.join(" ") })?;
+					for watchpoint in debugger.watchpoints() {
+						writeln!(output, "Watching {}", watchpoint)?;
+					}
+					for condition in debugger.conditions() {
+						writeln!(output, "When {}", condition)?;
+					}
+				}
+			},
+			"w" | "watch" => match Watchpoint::parse(rest) {
+				Ok(watchpoint) => {
+					debugger.add_watchpoint(watchpoint);
+					writeln!(output, "Watching {}", watchpoint)?;
+				},
+				Err(e) => writeln!(output, "Usage: watch <start>[-<end>][:r|:w|:rw], {}", e)?
+			},
+			"iw" | "iwatch" => match parse_range(rest) {
+				Some((start, end)) => {
+					let condition = Condition::index_in(start, end);
+					writeln!(output, "When {}", condition)?;
+					debugger.add_condition(condition);
+				},
+				None => writeln!(output, "Usage: iwatch <start>[-<end>]")?
+			},
+			"when" => match Condition::parse(rest) {
+				Ok(condition) => {
+					writeln!(output, "When {}", condition)?;
+					debugger.add_condition(condition);
+				},
+				Err(e) => writeln!(output, "Bad condition: {}", e)?
+			},
+			"unwatch" => {
+				debugger.clear_watches();
+				writeln!(output, "Removed every watchpoint and condition")?;
+			},
+			"d" | "delete" => match argument.and_then(parse_address) {
+				Some(addr) if debugger.remove_breakpoint(addr) => writeln!(output, "Removed the breakpoint at {:03X}", addr)?,
+				Some(addr) => writeln!(output, "No breakpoint at {:03X}", addr)?,
+				None => writeln!(output, "Usage: delete <addr>")?
+			},
+			"r" | "regs" => writeln!(output, "{}", Registers::of(machine))?,
+			"l" | "list" => {
+				let count = argument.and_then(|a| a.parse().ok()).unwrap_or(8);
+				list(machine, count, &mut output)?;
+			},
+			"h" | "help" => write!(output, "{}", HELP)?,
+			"q" | "quit" => return Ok(()),
+			_ => writeln!(output, "Unknown command {}, try help", command)?
+		}
+	}
+}
+
+// Runs frames until the debugger pauses, the program exits or stops on an error, or frames have passed
+fn run<W: Write>(machine: &mut CHIP8, debugger: &mut Debugger, policy: &ErrorPolicy, frames: u64, output: &mut W) -> io::Result<()> {
+	for _ in 0 .. frames {
+		match debugger.run_frame(machine, policy) {
+			Ok(Some(Stop::Stepped)) => break,
+			Ok(Some(stop)) => {
+				writeln!(output, "{}", stop)?;
+				break;
+			},
+			Ok(None) => (),
+			Err(e) => {
+				writeln!(output, "Halted: {}", e)?;
+				break;
+			}
+		}
+		if machine.has_exited() {
+			writeln!(output, "The program exited")?;
+			break;
+		}
+	}
+
+	debugger.pause();
+	show_stop(machine, debugger, output)
+}
+
+fn show_stop<W: Write>(machine: &CHIP8, debugger: &Debugger, output: &mut W) -> io::Result<()> {
+	writeln!(output, "{}", debugger.status(machine))
+}
+
+fn list<W: Write>(machine: &CHIP8, count: usize, output: &mut W) -> io::Result<()> {
+	let mut addr = machine.pc;
+	for _ in 0 .. count {
+		if addr as usize >= machine.memory.len() { break; }
+
+		let instruction = decode(read_word(machine, addr));
+		let long = read_word(machine, addr.wrapping_add(2));
+		writeln!(output, "{:03X}  {}", addr, disasm::format_instruction(instruction, long, Syntax::Octo))?;
+		addr = addr.wrapping_add(instruction.size());
+	}
+	Ok(())
+}
@@ -0,0 +1,237 @@
+use super::{ Debugger, Stop, Registers, Watchpoint, WatchKind, Condition, repl };
+use crate::{ CHIP8, Config, ErrorPolicy, HeadlessHost, MemoryAccess, AccessKind, asm::assemble, frontend::run_host };
+
+fn machine_with(source: &str) -> CHIP8 {
+	let rom = assemble(source).unwrap().rom;
+	let mut machine = CHIP8::new(&[]).unwrap();
+	machine.memory[0x200 .. 0x200 + rom.len()].copy_from_slice(&rom);
+	machine
+}
+
+// 200: v0 += 1, 202: call 208, 204: v2 := 2, 206: jump 206, 208: v1 := 1, 20A: return
+const PROGRAM: &str = "
+: main
+	v0 += 1
+	sub
+	v2 := 2
+: stop
+	jump stop
+: sub
+	v1 := 1
+	return
+";
+
+#[test]
+fn breakpoints_pause_partway_through_a_frame() {
+	let mut machine = machine_with(PROGRAM);
+	let mut debugger = Debugger::new();
+	let policy = ErrorPolicy::default();
+	debugger.add_breakpoint(0x208);
+
+	assert_eq!(debugger.run_frame(&mut machine, &policy), Ok(Some(Stop::Breakpoint(0x208))));
+	assert!(debugger.is_paused());
+	assert_eq!(machine.pc, 0x208);
+	assert_eq!(machine.V[1], 0);
+	assert_eq!(machine.frame_count(), 0);
+
+	// nothing runs while paused
+	assert_eq!(debugger.run_frame(&mut machine, &policy), Ok(None));
+	assert_eq!(machine.pc, 0x208);
+
+	// resuming carries on from the breakpoint instead of stopping at it again
+	debugger.resume();
+	assert_eq!(debugger.run_frame(&mut machine, &policy), Ok(None));
+	assert_eq!(machine.V[1], 1);
+	assert_eq!(machine.frame_count(), 1);
+
+	assert!(debugger.remove_breakpoint(0x208));
+	assert_eq!(debugger.breakpoints().count(), 0);
+}
+
+#[test]
+fn steps_count_towards_frames() {
+	let mut machine = machine_with(PROGRAM);
+	let mut debugger = Debugger::new();
+	let policy = ErrorPolicy::default();
+	machine.set_instructions_per_frame(3);
+	machine.delay_timer = 10;
+
+	for _ in 0 .. 2 {
+		assert_eq!(debugger.step(&mut machine, &policy), Ok(Stop::Stepped));
+	}
+	assert_eq!(machine.pc, 0x208);
+	assert_eq!((machine.frame_count(), machine.delay_timer), (0, 10));
+
+	debugger.step(&mut machine, &policy).unwrap();
+	assert_eq!((machine.frame_count(), machine.delay_timer), (1, 9));
+	assert!(debugger.is_paused());
+}
+
+#[test]
+fn step_over_and_step_out() {
+	let mut machine = machine_with(PROGRAM);
+	let mut debugger = Debugger::new();
+	let policy = ErrorPolicy::default();
+
+	debugger.step(&mut machine, &policy).unwrap();
+
+	// stepping over the call runs all of the subroutine
+	assert_eq!(debugger.step_over(&mut machine, &policy), Ok(None));
+	assert_eq!(debugger.run_frame(&mut machine, &policy), Ok(Some(Stop::Stepped)));
+	assert_eq!((machine.pc, machine.sp, machine.V[1]), (0x204, 0, 1));
+
+	// anything else is a single step
+	assert_eq!(debugger.step_over(&mut machine, &policy), Ok(Some(Stop::Stepped)));
+	assert_eq!(machine.pc, 0x206);
+
+	// stepping out of the subroutine stops back at the caller
+	let mut machine = machine_with(PROGRAM);
+	debugger.step(&mut machine, &policy).unwrap();
+	debugger.step(&mut machine, &policy).unwrap();
+	assert_eq!(machine.pc, 0x208);
+	debugger.step_out(&machine);
+	assert_eq!(debugger.run_frame(&mut machine, &policy), Ok(Some(Stop::Stepped)));
+	assert_eq!((machine.pc, machine.sp), (0x204, 0));
+}
+
+#[test]
+fn register_view() {
+	let mut machine = machine_with(PROGRAM);
+	machine.V[0xA] = 0x12;
+	machine.I = 0x345;
+	machine.pc = 0x208;
+	machine.stack[0] = 0x202;
+	machine.sp = 1;
+	machine.delay_timer = 0x3C;
+	machine.key[0xB] = 1;
+
+	assert_eq!(Registers::of(&machine).to_string(), "\
+pc 208  i 345  sp 1  delay 3C  sound 00
+v0 00  v1 00  v2 00  v3 00  v4 00  v5 00  v6 00  v7 00
+v8 00  v9 00  va 12  vb 00  vc 00  vd 00  ve 00  vf 00
+stack 202
+keys B");
+}
+
+#[test]
+fn repl_session() {
+	let mut machine = machine_with(PROGRAM);
+	let mut debugger = Debugger::new();
+	let mut output = Vec::new();
+
+	let commands = "b 20A\nc\nregs\ns\nlist 2\nd 20A\nbogus\nq\ns\n";
+	repl(&mut machine, &mut debugger, &ErrorPolicy::default(), commands.as_bytes(), &mut output).unwrap();
+
+	let output = String::from_utf8(output).unwrap();
+	assert!(output.starts_with("200  v0 += 0x01\n(chip8) Breakpoint at 20A\n"), "{}", output);
+	assert!(output.contains("(chip8) Breakpoint at 20A\n20A  return\npc 20A"), "{}", output);
+	assert!(output.contains("stack 202\n"), "{}", output);
+	assert!(output.contains("(chip8) 204  v2 := 0x02\n"), "{}", output);
+	assert!(output.contains("(chip8) 204  v2 := 0x02\n206  jump 0x206\n"), "{}", output);
+	assert!(output.contains("Removed the breakpoint at 20A"), "{}", output);
+	assert!(output.contains("Unknown command bogus"), "{}", output);
+
+	// quit stops reading commands, the step after it isn't run
+	assert_eq!(machine.pc, 0x204);
+}
+
+#[test]
+fn run_host_stops_at_breakpoints() {
+	let mut machine = machine_with(PROGRAM);
+	let mut host = HeadlessHost::new(10);
+	let config = Config { debug: true, breakpoints: vec![0x204], ..Config::default() };
+
+	run_host(&mut machine, &mut host, &config, None).unwrap();
+
+	assert_eq!((machine.pc, machine.V[1], machine.V[2]), (0x204, 1, 0));
+	assert_eq!(machine.frame_count(), 0);
+}
+
+#[test]
+fn watchpoints_stop_on_reads_and_writes() {
+	let mut machine = machine_with("
+	: main
+		i := 0x300
+		v0 := 123
+		bcd v0      # writes 300-302
+		load v2     # reads 300-302
+		i := hex v0 # v0 was loaded with 1, its digit is at 5
+		sprite v0 v0 5
+	: stop
+		jump stop
+	");
+	let mut debugger = Debugger::new();
+	let policy = ErrorPolicy::default();
+	debugger.add_watchpoint(Watchpoint::parse("302-310:w").unwrap());
+	debugger.add_watchpoint(Watchpoint::parse("0-9:r").unwrap());
+
+	let write = MemoryAccess { kind: AccessKind::Write, start: 0x300, len: 3 };
+	assert_eq!(debugger.run_frame(&mut machine, &policy), Ok(Some(Stop::Watchpoint(write))));
+	assert_eq!(machine.pc, 0x206);
+	assert_eq!(&machine.memory[0x300 .. 0x303], &[1, 2, 3]);
+
+	// reading the watched bytes back doesn't stop, the first is only watched for writes
+	debugger.resume();
+	let read = MemoryAccess { kind: AccessKind::Read, start: 0x005, len: 5 };
+	assert_eq!(debugger.run_frame(&mut machine, &policy), Ok(Some(Stop::Watchpoint(read))));
+	assert_eq!(machine.pc, 0x20C);
+	assert_eq!(Stop::Watchpoint(read).to_string(), "Watchpoint: Read 5 bytes at 005");
+
+	debugger.clear_watches();
+	assert!(debugger.watchpoints().is_empty());
+	debugger.resume();
+	assert_eq!(debugger.run_frame(&mut machine, &policy), Ok(None));
+}
+
+#[test]
+fn conditions_stop_when_they_become_true() {
+	let mut machine = machine_with("
+	: main
+		v3 := 0x10
+		v3 := 0
+		v3 := 0x10
+		i := 0x305
+	: stop
+		jump stop
+	");
+	let mut debugger = Debugger::new();
+	let policy = ErrorPolicy::default();
+	debugger.add_condition(Condition::parse("V3 == 0x10 && delay_timer == 0").unwrap());
+	debugger.add_condition(Condition::index_in(0x300, 0x30F));
+
+	assert_eq!(debugger.step(&mut machine, &policy), Ok(Stop::Condition("V3 == 0x10 && delay_timer == 0".to_string())));
+
+	// it's still true after the next instruction, so that doesn't stop
+	debugger.resume();
+	assert_eq!(debugger.run_frame(&mut machine, &policy), Ok(Some(Stop::Condition("V3 == 0x10 && delay_timer == 0".to_string()))));
+	assert_eq!(machine.pc, 0x206);
+
+	debugger.resume();
+	assert_eq!(debugger.run_frame(&mut machine, &policy), Ok(Some(Stop::Condition("i >= 0x300 && i <= 0x30F".to_string()))));
+	assert_eq!(machine.pc, 0x208);
+}
+
+#[test]
+fn parsing_conditions_and_watchpoints() {
+	let mut machine = CHIP8::new(&[]).unwrap();
+	machine.V[1] = 5;
+	machine.I = 0x200;
+	machine.sound_timer = 3;
+
+	let eval = |text: &str| Condition::parse(text).unwrap().eval(&machine);
+	assert!(eval("v1==5"));
+	assert!(eval("v1 > 4 && i >= 0x200 && st != 0"));
+	assert!(eval("v1 == 0 || v1 == 5 && sound == 3"));
+	assert!(!eval("(v1 == 0 || v1 == 5) && sound == 4"));
+	assert!(eval("v1 < pc"));
+
+	assert!(Condition::parse("v1 ==").is_err());
+	assert!(Condition::parse("v1 = 5").is_err());
+	assert!(Condition::parse("vg == 5").is_err());
+	assert!(Condition::parse("(v1 == 5").is_err());
+
+	assert_eq!(Watchpoint::parse("30F-300"), Ok(Watchpoint::new(0x300, 0x30F, WatchKind::ReadWrite)));
+	assert_eq!(Watchpoint::parse("0x400:r"), Ok(Watchpoint::new(0x400, 0x400, WatchKind::Read)));
+	assert!(Watchpoint::parse("400:x").is_err());
+	assert!(Watchpoint::parse("zz").is_err());
+}
@@ -0,0 +1,290 @@
+use crate::{ CHIP8, MemoryAccess, AccessKind };
+
+use std::fmt;
+
+// Which accesses a watchpoint stops on
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum WatchKind {
+	Read,
+	Write,
+	ReadWrite
+}
+
+// Stops when an instruction reads or writes memory from start to end, both included
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Watchpoint {
+	pub start: u16,
+	pub end: u16,
+	pub kind: WatchKind
+}
+
+impl Watchpoint {
+	pub fn new(start: u16, end: u16, kind: WatchKind) -> Watchpoint {
+		Watchpoint { start: start.min(end), end: start.max(end), kind }
+	}
+
+	// Reads watchpoints written as <start>[-<end>][:r|:w|:rw], with hex addresses. Without a kind both
+	// reads and writes are watched
+	pub fn parse(text: &str) -> Result<Watchpoint, String> {
+		let (range, kind) = match text.split_once(':') {
+			Some((range, kind)) => (range, kind),
+			None => (text, "rw")
+		};
+
+		let kind = match kind {
+			"r" => WatchKind::Read,
+			"w" => WatchKind::Write,
+			"rw" => WatchKind::ReadWrite,
+			_ => return Err(format!("unknown kind of watchpoint {}, it can be r, w or rw", kind))
+		};
+		let (start, end) = parse_range(range).ok_or_else(|| format!("not an address range: {}", range))?;
+
+		Ok(Watchpoint::new(start, end, kind))
+	}
+
+	pub fn matches(&self, access: &MemoryAccess) -> bool {
+		let kind = matches!((self.kind, access.kind),
+						(WatchKind::ReadWrite, _) | (WatchKind::Read, AccessKind::Read) | (WatchKind::Write, AccessKind::Write));
+		kind && access.overlaps(self.start, self.end)
+	}
+}
+
+impl fmt::Display for Watchpoint {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		let kind = match self.kind { WatchKind::Read => "r", WatchKind::Write => "w", WatchKind::ReadWrite => "rw" };
+		write!(f, "{:03X}-{:03X}:{}", self.start, self.end, kind)
+	}
+}
+
+// Addresses are hex, with or without 0x. A range is one address, or two separated by a -
+pub(crate) fn parse_address(text: &str) -> Option<u16> {
+	let digits = text.strip_prefix("0x").unwrap_or(text);
+	u16::from_str_radix(digits, 16).ok()
+}
+
+pub fn parse_range(text: &str) -> Option<(u16, u16)> {
+	match text.split_once('-') {
+		Some((start, end)) => Some((parse_address(start)?, parse_address(end)?)),
+		None => parse_address(text).map(|addr| (addr, addr))
+	}
+}
+
+// A condition on the registers and timers, like v3 == 0x10 && delay == 0. The debugger stops when one
+// becomes true
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Condition {
+	text: String,
+	expr: Expr
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Expr {
+	Or(Box<Expr>, Box<Expr>),
+	And(Box<Expr>, Box<Expr>),
+	Compare(Operand, Comparison, Operand)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Operand {
+	V(u8),
+	I,
+	Pc,
+	Sp,
+	Delay,
+	Sound,
+	Number(u16)
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+enum Comparison {
+	Equal,
+	NotEqual,
+	Less,
+	LessOrEqual,
+	Greater,
+	GreaterOrEqual
+}
+
+impl Condition {
+	// Conditions compare v0 to vf, i, pc, sp, delay (or dt) and sound (or st) with each other or numbers,
+	// and join the comparisons with && and ||, && going first. Parentheses group them
+	pub fn parse(text: &str) -> Result<Condition, String> {
+		let tokens = tokenize(text)?;
+		let mut parser = Parser { tokens: &tokens, pos: 0 };
+		let expr = parser.or()?;
+		match parser.tokens.get(parser.pos) {
+			None => Ok(Condition { text: text.trim().to_string(), expr }),
+			Some(token) => Err(format!("unexpected {}", token))
+		}
+	}
+
+	// Stops when I points at start to end, both included
+	pub fn index_in(start: u16, end: u16) -> Condition {
+		let (start, end) = (start.min(end), start.max(end));
+		Condition {
+			text: format!("i >= 0x{:03X} && i <= 0x{:03X}", start, end),
+			expr: Expr::And(
+				Box::new(Expr::Compare(Operand::I, Comparison::GreaterOrEqual, Operand::Number(start))),
+				Box::new(Expr::Compare(Operand::I, Comparison::LessOrEqual, Operand::Number(end)))
+			)
+		}
+	}
+
+	pub fn text(&self) -> &str {
+		&self.text
+	}
+
+	pub fn eval(&self, machine: &CHIP8) -> bool {
+		self.expr.eval(machine)
+	}
+}
+
+impl fmt::Display for Condition {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		write!(f, "{}", self.text)
+	}
+}
+
+impl Expr {
+	fn eval(&self, machine: &CHIP8) -> bool {
+		match self {
+			Expr::Or(a, b) => a.eval(machine) || b.eval(machine),
+			Expr::And(a, b) => a.eval(machine) && b.eval(machine),
+			Expr::Compare(a, comparison, b) => {
+				let (a, b) = (a.value(machine), b.value(machine));
+				match comparison {
+					Comparison::Equal => a == b,
+					Comparison::NotEqual => a != b,
+					Comparison::Less => a < b,
+					Comparison::LessOrEqual => a <= b,
+					Comparison::Greater => a > b,
+					Comparison::GreaterOrEqual => a >= b
+				}
+			}
+		}
+	}
+}
+
+impl Operand {
+	fn value(&self, machine: &CHIP8) -> u16 {
+		match *self {
+			Operand::V(x) => machine.V[x as usize] as u16,
+			Operand::I => machine.I,
+			Operand::Pc => machine.pc,
+			Operand::Sp => machine.sp,
+			Operand::Delay => machine.delay_timer as u16,
+			Operand::Sound => machine.sound_timer as u16,
+			Operand::Number(n) => n
+		}
+	}
+
+	fn parse(token: &str) -> Option<Operand> {
+		let lower = token.to_ascii_lowercase();
+		let operand = match lower.as_str() {
+			"i" => Operand::I,
+			"pc" => Operand::Pc,
+			"sp" => Operand::Sp,
+			"delay" | "dt" | "delay_timer" => Operand::Delay,
+			"sound" | "st" | "sound_timer" => Operand::Sound,
+			_ => match lower.strip_prefix('v') {
+				Some(digit) if digit.len() == 1 => Operand::V(u8::from_str_radix(digit, 16).ok()?),
+				_ => Operand::Number(parse_number(&lower)?)
+			}
+		};
+		Some(operand)
+	}
+}
+
+// Numbers are decimal, or hex with 0x
+fn parse_number(text: &str) -> Option<u16> {
+	match text.strip_prefix("0x") {
+		Some(hex) => u16::from_str_radix(hex, 16).ok(),
+		None => text.parse().ok()
+	}
+}
+
+// Splits a condition into names, numbers, operators and parentheses. Spaces are optional
+fn tokenize(text: &str) -> Result<Vec<String>, String> {
+	const OPERATORS: [&str; 10] = ["&&", "||", "==", "!=", "<=", ">=", "<", ">", "(", ")"];
+
+	let mut tokens = Vec::new();
+	let mut rest = text.trim_start();
+	while !rest.is_empty() {
+		if let Some(op) = OPERATORS.iter().find(|op| rest.starts_with(*op)) {
+			tokens.push(op.to_string());
+			rest = &rest[op.len() ..];
+		} else {
+			let len = rest.find(|c: char| !(c.is_ascii_alphanumeric() || c == '_')).unwrap_or(rest.len());
+			if len == 0 {
+				return Err(format!("unexpected {}", rest.chars().next().unwrap()));
+			}
+			tokens.push(rest[.. len].to_string());
+			rest = &rest[len ..];
+		}
+		rest = rest.trim_start();
+	}
+	Ok(tokens)
+}
+
+struct Parser<'a> {
+	tokens: &'a [String],
+	pos: usize
+}
+
+impl Parser<'_> {
+	fn next(&mut self) -> Result<&str, String> {
+		let token = self.tokens.get(self.pos).ok_or_else(|| "the condition ends too early".to_string())?;
+		self.pos += 1;
+		Ok(token)
+	}
+
+	fn accept(&mut self, token: &str) -> bool {
+		if self.tokens.get(self.pos).map(String::as_str) == Some(token) {
+			self.pos += 1;
+			true
+		} else {
+			false
+		}
+	}
+
+	fn or(&mut self) -> Result<Expr, String> {
+		let mut expr = self.and()?;
+		while self.accept("||") {
+			expr = Expr::Or(Box::new(expr), Box::new(self.and()?));
+		}
+		Ok(expr)
+	}
+
+	fn and(&mut self) -> Result<Expr, String> {
+		let mut expr = self.comparison()?;
+		while self.accept("&&") {
+			expr = Expr::And(Box::new(expr), Box::new(self.comparison()?));
+		}
+		Ok(expr)
+	}
+
+	fn comparison(&mut self) -> Result<Expr, String> {
+		if self.accept("(") {
+			let expr = self.or()?;
+			return if self.accept(")") { Ok(expr) } else { Err("missing )".to_string()) };
+		}
+
+		let a = self.operand()?;
+		let comparison = match self.next()? {
+			"==" => Comparison::Equal,
+			"!=" => Comparison::NotEqual,
+			"<" => Comparison::Less,
+			"<=" => Comparison::LessOrEqual,
+			">" => Comparison::Greater,
+			">=" => Comparison::GreaterOrEqual,
+			other => return Err(format!("expected a comparison, found {}", other))
+		};
+		let b = self.operand()?;
+		Ok(Expr::Compare(a, comparison, b))
+	}
+
+	fn operand(&mut self) -> Result<Operand, String> {
+		let token = self.next()?;
+		Operand::parse(token).ok_or_else(|| format!("unknown register or number {}", token))
+	}
+}
@@ -0,0 +1,285 @@
+use crate::{ Instruction, decode };
+
+use std::{
+	collections::{ BTreeMap, BTreeSet },
+	fmt::Write
+};
+
+#[cfg(test)]
+mod tests;
+
+// Where programs are loaded and start running
+const PROGRAM_START: u16 = 0x200;
+
+// The most bytes of data put on one line of a listing
+const DATA_PER_LINE: usize = 8;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Syntax {
+	Octo, // the syntax of the Octo assembler: v0 := 0x12, if v1 != v2 then, sprite v0 v1 5...
+	Classic // the mnemonics from Cowgod's reference: LD V0, 0x12, SNE V1, V2, DRW V0, V1, 5...
+}
+
+// What tracing a program's control flow found
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Trace {
+	pub code: BTreeSet<u16>, // the addresses instructions were found at
+	pub jump_targets: BTreeSet<u16>,
+	pub call_targets: BTreeSet<u16>
+}
+
+// Follows every path the program could take from 0x200, to tell its code from its data. Jumps
+// through BNNN can't be followed since their target depends on V0
+pub fn trace(rom: &[u8]) -> Trace {
+	let mut trace = Trace::default();
+	let mut pending = vec![PROGRAM_START];
+
+	while let Some(addr) = pending.pop() {
+		if trace.code.contains(&addr) { continue; }
+
+		let instruction = match read_word(rom, addr) {
+			Some(opcode) => decode(opcode),
+			None => continue // ran off the end of the program
+		};
+		if let Instruction::Unknown(_) = instruction { continue; } // not code after all
+		trace.code.insert(addr);
+
+		let next = addr.wrapping_add(instruction.size());
+		match instruction {
+			Instruction::Jump { addr: target } => {
+				trace.jump_targets.insert(target);
+				pending.push(target);
+			},
+			Instruction::Call { addr: target } => {
+				trace.call_targets.insert(target);
+				pending.push(target);
+			},
+			_ => ()
+		}
+
+		if instruction.is_skip() {
+			// the skipped instruction might be XO-CHIP's 4 byte F000 NNNN
+			let skipped = read_word(rom, next).map_or(2, |opcode| decode(opcode).size());
+			pending.push(next.wrapping_add(skipped));
+		}
+		if instruction.falls_through() {
+			pending.push(next);
+		}
+	}
+
+	trace
+}
+
+// Disassembles a whole program loaded at 0x200 into a listing with addresses, raw bytes and labels for
+// the targets of jumps and calls. Bytes that aren't reachable code are listed as data
+pub fn disassemble(rom: &[u8], syntax: Syntax) -> String {
+	let trace = trace(rom);
+
+	// split the program into lines first, a label can only go where a line starts
+	let end = PROGRAM_START as usize + rom.len();
+	let mut lines = Vec::new(); // the address and length of each line, and whether it's code
+	let mut addr = PROGRAM_START as usize;
+	while addr < end {
+		if trace.code.contains(&(addr as u16)) {
+			let len = decode(read_word(rom, addr as u16).unwrap()).size() as usize;
+			lines.push((addr, len.min(end - addr), true));
+			addr += len;
+		} else {
+			let next_code = trace.code.range(addr as u16 ..).next().map_or(end, |&a| a as usize);
+			let len = (next_code - addr).min(DATA_PER_LINE);
+			lines.push((addr, len, false));
+			addr += len;
+		}
+	}
+
+	let mut labels = BTreeMap::new();
+	for &(addr, _, code) in lines.iter() {
+		let addr = addr as u16;
+		if !code { continue; }
+
+		if addr == PROGRAM_START && syntax == Syntax::Octo {
+			labels.insert(addr, "main".to_string()); // Octo programs start at main
+		} else if trace.call_targets.contains(&addr) {
+			labels.insert(addr, format!("sub_{:03X}", addr));
+		} else if trace.jump_targets.contains(&addr) {
+			labels.insert(addr, format!("label_{:03X}", addr));
+		}
+	}
+
+	let mut listing = String::new();
+	for (addr, len, code) in lines {
+		let bytes = &rom[addr - PROGRAM_START as usize .. addr - PROGRAM_START as usize + len];
+		let hex: Vec<String> = bytes.iter().map(|b| format!("{:02X}", b)).collect();
+
+		let text = if code {
+			if let Some(label) = labels.get(&(addr as u16)) {
+				match syntax {
+					Syntax::Octo => writeln!(listing, ": {}", label).unwrap(),
+					Syntax::Classic => writeln!(listing, "{}:", label).unwrap()
+				}
+			}
+
+			let instruction = decode(read_word(rom, addr as u16).unwrap());
+			let long = read_word(rom, addr as u16 + 2).unwrap_or(0);
+			format_with(instruction, long, syntax, &|a| labels.get(&a).cloned())
+		} else {
+			format_data(bytes, syntax)
+		};
+
+		match syntax {
+			Syntax::Octo => writeln!(listing, "\t{:<28}# {:04X}  {}", text, addr, hex.join(" ")).unwrap(),
+			Syntax::Classic => writeln!(listing, "{:04X}  {:<24}{}", addr, hex.join(" "), text).unwrap()
+		}
+	}
+
+	listing
+}
+
+// Formats one instruction on its own, with addresses as numbers. long is the word after it, which is
+// the address of F000 NNNN
+pub fn format_instruction(instruction: Instruction, long: u16, syntax: Syntax) -> String {
+	format_with(instruction, long, syntax, &|_| None)
+}
+
+fn format_with(instruction: Instruction, long: u16, syntax: Syntax, label: &dyn Fn(u16) -> Option<String>) -> String {
+	use Instruction::*;
+
+	let address = |addr: u16| label(addr).unwrap_or_else(|| format!("0x{:03X}", addr));
+
+	match syntax {
+		Syntax::Octo => {
+			let v = |r: u8| format!("v{:x}", r);
+
+			match instruction {
+				Clear => "clear".to_string(),
+				Return => "return".to_string(),
+				ScrollDown { n } => format!("scroll-down {}", n),
+				ScrollUp { n } => format!("scroll-up {}", n),
+				ScrollRight => "scroll-right".to_string(),
+				ScrollLeft => "scroll-left".to_string(),
+				Exit => "exit".to_string(),
+				Lores => "lores".to_string(),
+				Hires => "hires".to_string(),
+				Jump { addr } => format!("jump {}", address(addr)),
+				Call { addr } => label(addr).unwrap_or_else(|| format!(":call 0x{:03X}", addr)), // a label on its own calls it
+				// Octo's conditions say when the next instruction runs, the opposite of when it's skipped
+				SkipIfEqual { x, byte } => format!("if {} != 0x{:02X} then", v(x), byte),
+				SkipIfNotEqual { x, byte } => format!("if {} == 0x{:02X} then", v(x), byte),
+				SkipIfRegistersEqual { x, y } => format!("if {} != {} then", v(x), v(y)),
+				SaveRange { x, y } => format!("save {} - {}", v(x), v(y)),
+				LoadRange { x, y } => format!("load {} - {}", v(x), v(y)),
+				SetImmediate { x, byte } => format!("{} := 0x{:02X}", v(x), byte),
+				AddImmediate { x, byte } => format!("{} += 0x{:02X}", v(x), byte),
+				Move { x, y } => format!("{} := {}", v(x), v(y)),
+				Or { x, y } => format!("{} |= {}", v(x), v(y)),
+				And { x, y } => format!("{} &= {}", v(x), v(y)),
+				Xor { x, y } => format!("{} ^= {}", v(x), v(y)),
+				Add { x, y } => format!("{} += {}", v(x), v(y)),
+				Sub { x, y } => format!("{} -= {}", v(x), v(y)),
+				ShiftRight { x, y } => format!("{} >>= {}", v(x), v(y)),
+				SubReversed { x, y } => format!("{} =- {}", v(x), v(y)),
+				ShiftLeft { x, y } => format!("{} <<= {}", v(x), v(y)),
+				SkipIfRegistersNotEqual { x, y } => format!("if {} == {} then", v(x), v(y)),
+				SetIndex { addr } => format!("i := {}", address(addr)),
+				JumpOffset { addr } => format!("jump0 {}", address(addr)),
+				Random { x, byte } => format!("{} := random 0x{:02X}", v(x), byte),
+				Draw { x, y, n } => format!("sprite {} {} {}", v(x), v(y), n),
+				SkipIfKey { x } => format!("if {} -key then", v(x)),
+				SkipIfNotKey { x } => format!("if {} key then", v(x)),
+				LongIndex => format!("i := long {}", label(long).unwrap_or_else(|| format!("0x{:04X}", long))),
+				SelectPlanes { n } => format!("plane {}", n),
+				LoadAudio => "audio".to_string(),
+				GetDelay { x } => format!("{} := delay", v(x)),
+				WaitKey { x } => format!("{} := key", v(x)),
+				SetDelay { x } => format!("delay := {}", v(x)),
+				SetSound { x } => format!("buzzer := {}", v(x)),
+				AddIndex { x } => format!("i += {}", v(x)),
+				Font { x } => format!("i := hex {}", v(x)),
+				BigFont { x } => format!("i := bighex {}", v(x)),
+				Bcd { x } => format!("bcd {}", v(x)),
+				SetPitch { x } => format!("pitch := {}", v(x)),
+				Save { x } => format!("save {}", v(x)),
+				Load { x } => format!("load {}", v(x)),
+				SaveFlags { x } => format!("saveflags {}", v(x)),
+				LoadFlags { x } => format!("loadflags {}", v(x)),
+				Unknown(opcode) => format!("0x{:02X} 0x{:02X}", opcode >> 8, opcode & 0xFF)
+			}
+		},
+
+		Syntax::Classic => {
+			let v = |r: u8| format!("V{:X}", r);
+
+			match instruction {
+				Clear => "CLS".to_string(),
+				Return => "RET".to_string(),
+				ScrollDown { n } => format!("SCD {}", n),
+				ScrollUp { n } => format!("SCU {}", n),
+				ScrollRight => "SCR".to_string(),
+				ScrollLeft => "SCL".to_string(),
+				Exit => "EXIT".to_string(),
+				Lores => "LOW".to_string(),
+				Hires => "HIGH".to_string(),
+				Jump { addr } => format!("JP {}", address(addr)),
+				Call { addr } => format!("CALL {}", address(addr)),
+				SkipIfEqual { x, byte } => format!("SE {}, 0x{:02X}", v(x), byte),
+				SkipIfNotEqual { x, byte } => format!("SNE {}, 0x{:02X}", v(x), byte),
+				SkipIfRegistersEqual { x, y } => format!("SE {}, {}", v(x), v(y)),
+				SaveRange { x, y } => format!("SAVE {} - {}", v(x), v(y)),
+				LoadRange { x, y } => format!("LOAD {} - {}", v(x), v(y)),
+				SetImmediate { x, byte } => format!("LD {}, 0x{:02X}", v(x), byte),
+				AddImmediate { x, byte } => format!("ADD {}, 0x{:02X}", v(x), byte),
+				Move { x, y } => format!("LD {}, {}", v(x), v(y)),
+				Or { x, y } => format!("OR {}, {}", v(x), v(y)),
+				And { x, y } => format!("AND {}, {}", v(x), v(y)),
+				Xor { x, y } => format!("XOR {}, {}", v(x), v(y)),
+				Add { x, y } => format!("ADD {}, {}", v(x), v(y)),
+				Sub { x, y } => format!("SUB {}, {}", v(x), v(y)),
+				ShiftRight { x, y } => format!("SHR {}, {}", v(x), v(y)),
+				SubReversed { x, y } => format!("SUBN {}, {}", v(x), v(y)),
+				ShiftLeft { x, y } => format!("SHL {}, {}", v(x), v(y)),
+				SkipIfRegistersNotEqual { x, y } => format!("SNE {}, {}", v(x), v(y)),
+				SetIndex { addr } => format!("LD I, {}", address(addr)),
+				JumpOffset { addr } => format!("JP V0, {}", address(addr)),
+				Random { x, byte } => format!("RND {}, 0x{:02X}", v(x), byte),
+				Draw { x, y, n } => format!("DRW {}, {}, {}", v(x), v(y), n),
+				SkipIfKey { x } => format!("SKP {}", v(x)),
+				SkipIfNotKey { x } => format!("SKNP {}", v(x)),
+				LongIndex => format!("LD I, LONG {}", label(long).unwrap_or_else(|| format!("0x{:04X}", long))),
+				SelectPlanes { n } => format!("PLANE {}", n),
+				LoadAudio => "AUDIO".to_string(),
+				GetDelay { x } => format!("LD {}, DT", v(x)),
+				WaitKey { x } => format!("LD {}, K", v(x)),
+				SetDelay { x } => format!("LD DT, {}", v(x)),
+				SetSound { x } => format!("LD ST, {}", v(x)),
+				AddIndex { x } => format!("ADD I, {}", v(x)),
+				Font { x } => format!("LD F, {}", v(x)),
+				BigFont { x } => format!("LD HF, {}", v(x)),
+				Bcd { x } => format!("LD B, {}", v(x)),
+				SetPitch { x } => format!("PITCH {}", v(x)),
+				Save { x } => format!("LD [I], {}", v(x)),
+				Load { x } => format!("LD {}, [I]", v(x)),
+				SaveFlags { x } => format!("LD R, {}", v(x)),
+				LoadFlags { x } => format!("LD {}, R", v(x)),
+				Unknown(opcode) => format!("DW 0x{:04X}", opcode)
+			}
+		}
+	}
+}
+
+fn format_data(bytes: &[u8], syntax: Syntax) -> String {
+	let values: Vec<String> = bytes.iter().map(|b| format!("0x{:02X}", b)).collect();
+
+	match syntax {
+		Syntax::Octo => values.join(" "),
+		Syntax::Classic => format!("DB {}", values.join(", "))
+	}
+}
+
+// The word at an address of a program loaded at 0x200, if the program has both of its bytes
+fn read_word(rom: &[u8], addr: u16) -> Option<u16> {
+	let offset = (addr as usize).checked_sub(PROGRAM_START as usize)?;
+	match (rom.get(offset), rom.get(offset + 1)) {
+		(Some(&high), Some(&low)) => Some((high as u16) << 8 | low as u16),
+		_ => None
+	}
+}
@@ -0,0 +1,106 @@
+use super::{ Syntax, trace, disassemble, format_instruction };
+use crate::{ Instruction, decode };
+
+#[test]
+fn decode_every_form() {
+	use Instruction::*;
+
+	let cases = [
+		(0x00E0, Clear), (0x00EE, Return), (0x00C3, ScrollDown { n: 3 }), (0x00D4, ScrollUp { n: 4 }),
+		(0x00FB, ScrollRight), (0x00FC, ScrollLeft), (0x00FD, Exit), (0x00FE, Lores), (0x00FF, Hires),
+		(0x1234, Jump { addr: 0x234 }), (0x2345, Call { addr: 0x345 }),
+		(0x3A12, SkipIfEqual { x: 0xA, byte: 0x12 }), (0x4B34, SkipIfNotEqual { x: 0xB, byte: 0x34 }),
+		(0x5120, SkipIfRegistersEqual { x: 1, y: 2 }), (0x5122, SaveRange { x: 1, y: 2 }), (0x5123, LoadRange { x: 1, y: 2 }),
+		(0x6C56, SetImmediate { x: 0xC, byte: 0x56 }), (0x7D78, AddImmediate { x: 0xD, byte: 0x78 }),
+		(0x8120, Move { x: 1, y: 2 }), (0x8121, Or { x: 1, y: 2 }), (0x8122, And { x: 1, y: 2 }),
+		(0x8123, Xor { x: 1, y: 2 }), (0x8124, Add { x: 1, y: 2 }), (0x8125, Sub { x: 1, y: 2 }),
+		(0x8126, ShiftRight { x: 1, y: 2 }), (0x8127, SubReversed { x: 1, y: 2 }), (0x812E, ShiftLeft { x: 1, y: 2 }),
+		(0x9340, SkipIfRegistersNotEqual { x: 3, y: 4 }), (0xA456, SetIndex { addr: 0x456 }),
+		(0xB567, JumpOffset { addr: 0x567 }), (0xC6FF, Random { x: 6, byte: 0xFF }), (0xD785, Draw { x: 7, y: 8, n: 5 }),
+		(0xE99E, SkipIfKey { x: 9 }), (0xEAA1, SkipIfNotKey { x: 0xA }),
+		(0xF000, LongIndex), (0xF301, SelectPlanes { n: 3 }), (0xF002, LoadAudio),
+		(0xF107, GetDelay { x: 1 }), (0xF20A, WaitKey { x: 2 }), (0xF315, SetDelay { x: 3 }), (0xF418, SetSound { x: 4 }),
+		(0xF51E, AddIndex { x: 5 }), (0xF629, Font { x: 6 }), (0xF730, BigFont { x: 7 }), (0xF833, Bcd { x: 8 }),
+		(0xF93A, SetPitch { x: 9 }), (0xFA55, Save { x: 0xA }), (0xFB65, Load { x: 0xB }),
+		(0xFC75, SaveFlags { x: 0xC }), (0xFD85, LoadFlags { x: 0xD })
+	];
+
+	for &(opcode, instruction) in cases.iter() {
+		assert_eq!(decode(opcode), instruction, "{:04X}", opcode);
+	}
+
+	for &opcode in [0x0123, 0x5121, 0x8128, 0x9341, 0xE19F, 0xF1FF, 0xF100, 0xF202].iter() {
+		assert_eq!(decode(opcode), Unknown(opcode), "{:04X}", opcode);
+	}
+}
+
+#[test]
+fn octo_and_classic_syntax() {
+	let cases = [
+		(0x6A02, "va := 0x02", "LD VA, 0x02"),
+		(0x3A12, "if va != 0x12 then", "SE VA, 0x12"),
+		(0x9340, "if v3 == v4 then", "SNE V3, V4"),
+		(0xE99E, "if v9 -key then", "SKP V9"),
+		(0x8127, "v1 =- v2", "SUBN V1, V2"),
+		(0xD785, "sprite v7 v8 5", "DRW V7, V8, 5"),
+		(0xA456, "i := 0x456", "LD I, 0x456"),
+		(0x2345, ":call 0x345", "CALL 0x345"),
+		(0xF418, "buzzer := v4", "LD ST, V4"),
+		(0x5122, "save v1 - v2", "SAVE V1 - V2"),
+		(0xFF65, "load vf", "LD VF, [I]"),
+		(0x0123, "0x01 0x23", "DW 0x0123")
+	];
+
+	for &(opcode, octo, classic) in cases.iter() {
+		assert_eq!(format_instruction(decode(opcode), 0, Syntax::Octo), octo);
+		assert_eq!(format_instruction(decode(opcode), 0, Syntax::Classic), classic);
+	}
+
+	assert_eq!(format_instruction(Instruction::LongIndex, 0x1234, Syntax::Octo), "i := long 0x1234");
+}
+
+#[test]
+fn trace_follows_control_flow() {
+	let rom = [
+		0x22, 0x08, // 200: call 208
+		0x3A, 0x00, // 202: skip if va == 0
+		0x12, 0x0C, // 204: jump 20C
+		0x12, 0x02, // 206: jump 202
+		0x00, 0xEE, // 208: return
+		0xAB, 0xCD, // 20A: data
+		0x00, 0xFD, // 20C: exit
+		0x12, 0x34  // 20E: data
+	];
+
+	let trace = trace(&rom);
+
+	assert_eq!(trace.code.iter().copied().collect::<Vec<_>>(), vec![0x200, 0x202, 0x204, 0x206, 0x208, 0x20C]);
+	assert_eq!(trace.call_targets.iter().copied().collect::<Vec<_>>(), vec![0x208]);
+	assert_eq!(trace.jump_targets.iter().copied().collect::<Vec<_>>(), vec![0x202, 0x20C]);
+}
+
+#[test]
+fn listing_with_labels_and_data() {
+	let rom = [0x22, 0x06, 0x12, 0x02, 0x01, 0x02, 0x00, 0xEE];
+
+	let octo = disassemble(&rom, Syntax::Octo);
+	assert_eq!(octo, "\
+: main
+	sub_206                     # 0200  22 06
+: label_202
+	jump label_202              # 0202  12 02
+	0x01 0x02                   # 0204  01 02
+: sub_206
+	return                      # 0206  00 EE
+");
+
+	let classic = disassemble(&rom, Syntax::Classic);
+	assert_eq!(classic, "\
+0200  22 06                   CALL sub_206
+label_202:
+0202  12 02                   JP label_202
+0204  01 02                   DB 0x01, 0x02
+sub_206:
+0206  00 EE                   RET
+");
+}
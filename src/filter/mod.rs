@@ -0,0 +1,142 @@
+use crate::frontend::Screen;
+
+use std::{
+	collections::VecDeque,
+	fmt
+};
+
+#[cfg(test)]
+mod tests;
+
+// How much light a pixel keeps from one frame to the next with the phosphor filter, when it isn't given
+pub const DEFAULT_DECAY: f32 = 0.6;
+
+// How many frames the persist filter merges, when it isn't given
+pub const DEFAULT_PERSIST_FRAMES: usize = 2;
+
+// The longest the persist filter looks back, at 60 frames per second
+const MAX_PERSIST_FRAMES: usize = 8;
+
+// What's done to the screen before it's shown. CHIP-8 programs move sprites by erasing them with XOR and
+// drawing them again, often in different frames, so without a filter they flicker
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub enum Filter {
+	#[default]
+	Off, // each frame as the machine left it
+	Phosphor(f32), // pixels that go out fade, keeping this much of their light each frame, like a CRT's phosphor
+	Persist(usize) // a pixel is lit if it was in any of the last this many frames
+}
+
+impl Filter {
+	// off, phosphor[:<decay>] or persist[:<frames>]
+	pub fn parse(text: &str) -> Result<Filter, String> {
+		let text = text.trim().to_lowercase();
+		let (name, value) = match text.split_once(':') {
+			Some((name, value)) => (name, Some(value)),
+			None => (text.as_str(), None)
+		};
+
+		match (name, value) {
+			("off", None) => Ok(Filter::Off),
+			("phosphor", None) => Ok(Filter::Phosphor(DEFAULT_DECAY)),
+			("phosphor", Some(decay)) => decay.parse().ok().filter(|d| *d > 0.0 && *d < 1.0).map(Filter::Phosphor)
+										.ok_or_else(|| format!("the phosphor's decay is between 0 and 1, not {}", decay)),
+			("persist", None) => Ok(Filter::Persist(DEFAULT_PERSIST_FRAMES)),
+			("persist", Some(frames)) => frames.parse().ok().filter(|n| (1 ..= MAX_PERSIST_FRAMES).contains(n)).map(Filter::Persist)
+										.ok_or_else(|| format!("persist takes 1 to {} frames, not {}", MAX_PERSIST_FRAMES, frames)),
+			_ => Err(format!("unknown filter {}, try off, phosphor[:<decay>] or persist[:<frames>]", text))
+		}
+	}
+}
+
+impl fmt::Display for Filter {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			Filter::Off => write!(f, "off"),
+			Filter::Phosphor(decay) => write!(f, "phosphor:{}", decay),
+			Filter::Persist(frames) => write!(f, "persist:{}", frames)
+		}
+	}
+}
+
+// The filtered screen, made from the frames the machine has run so far
+pub struct ScreenFilter {
+	filter: Filter,
+	width: usize,
+	height: usize,
+	pixels: Vec<u8>, // the planes each pixel is lit in
+	levels: Vec<[u8; 2]>, // how brightly each pixel is lit in each plane, for the phosphor filter
+	history: VecDeque<Vec<u8>> // the last frames, newest last, for the persist filter
+}
+
+impl ScreenFilter {
+	pub fn new(filter: Filter) -> ScreenFilter {
+		ScreenFilter { filter, width: 0, height: 0, pixels: Vec::new(), levels: Vec::new(), history: VecDeque::new() }
+	}
+
+	// Adds the screen as it is at the end of a frame. Call this once for every frame the machine runs.
+	// Returns whether what's shown changed
+	pub fn push(&mut self, screen: &Screen) -> bool {
+		let resized = (screen.width(), screen.height()) != (self.width, self.height);
+		if resized {
+			// the old frames don't line up with the new ones
+			self.width = screen.width();
+			self.height = screen.height();
+			self.levels.clear();
+			self.history.clear();
+		}
+
+		let before = self.pixels.clone();
+		match self.filter {
+			Filter::Off => {
+				self.pixels.clear();
+				self.pixels.extend_from_slice(screen.pixels());
+			},
+
+			Filter::Phosphor(decay) => {
+				if resized {
+					self.levels = vec![[0, 0]; screen.pixels().len()];
+				}
+
+				let mut faded = false;
+				for (level, &pixel) in self.levels.iter_mut().zip(screen.pixels()) {
+					for (plane, value) in level.iter_mut().enumerate() {
+						let lit = pixel >> plane & 1 != 0;
+						let next = if lit { 255 } else { (*value as f32 * decay) as u8 };
+						faded |= next != *value;
+						*value = next;
+					}
+				}
+
+				// a pixel counts as lit in a plane until it's faded to half
+				self.pixels = self.levels.iter().map(|[first, second]| (*first >= 128) as u8 | ((*second >= 128) as u8) << 1).collect();
+				return resized || faded;
+			},
+
+			Filter::Persist(frames) => {
+				self.history.push_back(screen.pixels().to_vec());
+				while self.history.len() > frames {
+					self.history.pop_front();
+				}
+
+				self.pixels = vec![0; screen.pixels().len()];
+				for frame in &self.history {
+					for (pixel, planes) in self.pixels.iter_mut().zip(frame) {
+						*pixel |= planes & 0b11;
+					}
+				}
+			}
+		}
+
+		resized || before != self.pixels
+	}
+
+	// What's shown. Empty until the first frame is pushed
+	pub fn screen(&self) -> Screen<'_> {
+		let screen = Screen::new(self.width, self.height, &self.pixels);
+		match self.filter {
+			Filter::Phosphor(_) => screen.with_levels(&self.levels),
+			_ => screen
+		}
+	}
+}
@@ -0,0 +1,61 @@
+use super::{ Filter, ScreenFilter, DEFAULT_DECAY };
+use crate::{ Palette, frontend::Screen };
+
+#[test]
+fn filters_are_parsed() {
+	assert_eq!(Filter::parse("off"), Ok(Filter::Off));
+	assert_eq!(Filter::parse(" Phosphor "), Ok(Filter::Phosphor(DEFAULT_DECAY)));
+	assert_eq!(Filter::parse("phosphor:0.25"), Ok(Filter::Phosphor(0.25)));
+	assert_eq!(Filter::parse("persist:4"), Ok(Filter::Persist(4)));
+	assert_eq!(Filter::parse(&Filter::Persist(3).to_string()), Ok(Filter::Persist(3)));
+
+	assert!(Filter::parse("phosphor:1").is_err());
+	assert!(Filter::parse("persist:0").is_err());
+	assert!(Filter::parse("off:2").is_err());
+	assert!(Filter::parse("blur").is_err());
+}
+
+#[test]
+fn phosphor_fades_pixels_out() {
+	let mut lit = vec![0; 64 * 32];
+	lit[0] = 1;
+	let dark = vec![0; 64 * 32];
+	let mut filter = ScreenFilter::new(Filter::Phosphor(0.5));
+
+	assert!(filter.push(&Screen::new(64, 32, &lit)));
+	assert_eq!(filter.screen().colour(0, 0), Palette::CLASSIC.colour(1));
+
+	// half as bright after a frame, a quarter after two, and still shown as lit until it's under half
+	assert!(filter.push(&Screen::new(64, 32, &dark)));
+	assert_eq!(filter.screen().colour(0, 0), [127, 127, 127, 255]);
+	assert_eq!(filter.screen().pixel(0, 0), 0);
+	assert!(filter.push(&Screen::new(64, 32, &dark)));
+	assert_eq!(filter.screen().colour(0, 0), [63, 63, 63, 255]);
+
+	// until it's gone and nothing changes any more
+	while filter.push(&Screen::new(64, 32, &dark)) {}
+	assert_eq!(filter.screen().colour(0, 0), Palette::CLASSIC.colour(0));
+}
+
+#[test]
+fn persist_shows_pixels_lit_in_the_last_frames() {
+	let mut first = vec![0; 64 * 32];
+	first[0] = 1;
+	let mut second = vec![0; 64 * 32];
+	second[1] = 2;
+	let mut filter = ScreenFilter::new(Filter::Persist(2));
+
+	filter.push(&Screen::new(64, 32, &first));
+	assert!(filter.push(&Screen::new(64, 32, &second)));
+	assert_eq!((filter.screen().pixel(0, 0), filter.screen().pixel(1, 0)), (1, 2));
+
+	// the first frame is too old now
+	assert!(filter.push(&Screen::new(64, 32, &second)));
+	assert_eq!((filter.screen().pixel(0, 0), filter.screen().pixel(1, 0)), (0, 2));
+	assert!(!filter.push(&Screen::new(64, 32, &second)));
+
+	// switching resolution forgets the frames from before
+	assert!(filter.push(&Screen::new(128, 64, &vec![0; 128 * 64])));
+	assert_eq!(filter.screen().width(), 128);
+	assert_eq!(filter.screen().pixel(1, 0), 0);
+}
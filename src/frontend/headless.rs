@@ -0,0 +1,93 @@
+use crate::{
+	CHIP8, ExecError,
+	frontend::{ Screen, SoundState, HostEvent, VideoSink, AudioSink, InputSource, Clock }
+};
+
+// A frontend without a window, sound or keyboard. Runs a set number of frames as fast as it can and
+// keeps the last one presented, for tests and batch runs
+pub struct HeadlessHost {
+	frames_left: u64,
+	finished: bool, // all the frames have been run
+	halted: bool,
+	frames_presented: u64,
+	frames_with_sound: u64,
+	width: usize,
+	height: usize,
+	pixels: Vec<u8>
+}
+
+impl HeadlessHost {
+	pub fn new(frames: u64) -> HeadlessHost {
+		HeadlessHost {
+			frames_left: frames,
+			finished: false,
+			halted: false,
+			frames_presented: 0,
+			frames_with_sound: 0,
+			width: 0,
+			height: 0,
+			pixels: Vec::new()
+		}
+	}
+
+	// The last frame presented. Empty if the screen was never drawn
+	pub fn screen(&self) -> Screen<'_> {
+		Screen::new(self.width, self.height, &self.pixels)
+	}
+
+	// How many times the screen changed
+	pub fn frames_presented(&self) -> u64 {
+		self.frames_presented
+	}
+
+	// How many passes of the main loop the buzzer was sounding for
+	pub fn frames_with_sound(&self) -> u64 {
+		self.frames_with_sound
+	}
+}
+
+impl VideoSink for HeadlessHost {
+	fn present(&mut self, screen: &Screen) {
+		self.width = screen.width();
+		self.height = screen.height();
+		self.pixels.clear();
+		self.pixels.extend_from_slice(screen.pixels());
+		self.frames_presented += 1;
+	}
+
+	// nobody is there to rewind or load a state, so the run ends
+	fn set_halted(&mut self, error: Option<&ExecError>) {
+		self.halted = error.is_some();
+	}
+}
+
+impl AudioSink for HeadlessHost {
+	fn update_sound(&mut self, sound: SoundState) {
+		if sound.playing {
+			self.frames_with_sound += 1;
+		}
+	}
+}
+
+impl InputSource for HeadlessHost {
+	fn poll_input(&mut self, _machine: &mut CHIP8) -> Vec<HostEvent> {
+		if self.finished || self.halted {
+			vec![HostEvent::Quit]
+		} else {
+			Vec::new()
+		}
+	}
+}
+
+impl Clock for HeadlessHost {
+	// one frame at a time, so every frame that changes the screen gets presented
+	fn wait_for_frames(&mut self) -> u32 {
+		if self.frames_left == 0 {
+			self.finished = true;
+			return 0;
+		}
+
+		self.frames_left -= 1;
+		1
+	}
+}
@@ -0,0 +1,445 @@
+// Everything between the emulated machine and the outside world. A frontend shows the screen, plays the
+// sound, reads the keyboard and keeps time, and run_host drives a machine with it
+#[cfg(feature = "window")]
+pub mod window;
+pub mod headless;
+pub mod terminal;
+
+#[cfg(test)]
+mod tests;
+
+use crate::{ CHIP8, Config, ExecError, ErrorPolicy, RewindBuffer, Recorder, Palette, Filter, ScreenFilter, debugger::{ Debugger, Stop }, gdb::{ GdbStub, GdbEvent } };
+
+use std::{
+	fs,
+	path::PathBuf
+};
+
+// A view of the machine's display in its current resolution
+#[derive(Clone, Copy)]
+pub struct Screen<'a> {
+	width: usize,
+	height: usize,
+	pixels: &'a [u8], // one byte per pixel, holding one bit per plane
+	levels: Option<&'a [[u8; 2]]> // how brightly each pixel is lit in each plane, when a filter blends frames
+}
+
+impl<'a> Screen<'a> {
+	pub fn new(width: usize, height: usize, pixels: &'a [u8]) -> Screen<'a> {
+		Screen { width, height, pixels: &pixels[.. width * height], levels: None }
+	}
+
+	// The same screen with pixels lit partway, 255 being fully lit
+	pub fn with_levels(self, levels: &'a [[u8; 2]]) -> Screen<'a> {
+		Screen { levels: Some(&levels[.. self.width * self.height]), .. self }
+	}
+
+	pub fn width(&self) -> usize {
+		self.width
+	}
+
+	pub fn height(&self) -> usize {
+		self.height
+	}
+
+	// The planes a pixel is set in, 0 being off in all of them
+	pub fn pixel(&self, x: usize, y: usize) -> u8 {
+		self.pixels[y * self.width + x] & 0b11
+	}
+
+	pub fn pixels(&self) -> &'a [u8] {
+		self.pixels
+	}
+
+	// The pixel's colour in the classic theme
+	pub fn colour(&self, x: usize, y: usize) -> [u8; 4] {
+		self.colour_in(&Palette::CLASSIC, x, y)
+	}
+
+	// The pixel's colour in a palette, mixed between the palette's colours if it's lit partway
+	pub fn colour_in(&self, palette: &Palette, x: usize, y: usize) -> [u8; 4] {
+		match self.levels {
+			Some(levels) => palette.mix(levels[y * self.width + x]),
+			None => palette.colour(self.pixel(x, y))
+		}
+	}
+
+	#[cfg(feature = "image")]
+	pub fn to_image(&self) -> image::RgbaImage {
+		self.to_image_with(&Palette::CLASSIC)
+	}
+
+	// The screen in other colours
+	#[cfg(feature = "image")]
+	pub fn to_image_with(&self, palette: &Palette) -> image::RgbaImage {
+		image::RgbaImage::from_fn(self.width as u32, self.height as u32, |x, y| image::Rgba(self.colour_in(palette, x as usize, y as usize)))
+	}
+}
+
+// What the machine's speaker is doing
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct SoundState {
+	pub playing: bool, // the sound timer is running
+	pub pattern: [u8; 16], // XO-CHIP's audio pattern
+	pub rate: f32 // bits of the pattern played per second
+}
+
+// Something the user asked the frontend for, besides pressing CHIP-8 keys
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum HostEvent {
+	Quit,
+	SaveState(u8), // save to a quick-save slot
+	LoadState(u8),
+	Rewind(bool), // start or stop stepping back through the last frames
+	Pause, // pause or resume, when running under the debugger
+	Step,
+	StepOver,
+	StepOut,
+	Screenshot, // save the screen as a PNG next to the ROM
+	Record, // start or stop recording a GIF next to the ROM
+	NextPalette // switch to the next of the built-in palettes
+}
+
+pub trait VideoSink {
+	// Shows a new frame. Only called when the screen has changed
+	fn present(&mut self, screen: &Screen);
+
+	// Called with the error that halted the machine, and with None once it's running again
+	fn set_halted(&mut self, _error: Option<&ExecError>) {}
+
+	// Called when the debugger pauses or resumes the machine
+	fn set_paused(&mut self, _paused: bool) {}
+
+	// Changes the colours the screen is drawn in. present is called straight after
+	fn set_palette(&mut self, _palette: &Palette) {}
+}
+
+pub trait AudioSink {
+	// Called once per pass of the main loop with what the speaker should be playing
+	fn update_sound(&mut self, sound: SoundState);
+}
+
+pub trait InputSource {
+	// Collects the input since the last call. CHIP-8 key presses are passed straight on to the machine
+	fn poll_input(&mut self, machine: &mut CHIP8) -> Vec<HostEvent>;
+}
+
+pub trait Clock {
+	// Waits until at least one frame is due and returns how many should be run
+	fn wait_for_frames(&mut self) -> u32;
+}
+
+// A whole frontend
+pub trait Host: VideoSink + AudioSink + InputSource + Clock {}
+
+impl<T: VideoSink + AudioSink + InputSource + Clock> Host for T {}
+
+// Runs the machine with a frontend until the user quits or the program exits. Returns the error the
+// machine was halted on, if it was still halted when the run ended. program_path is where the ROM was
+// loaded from, the quick-save slots are kept next to it
+pub fn run_host<H: Host>(machine: &mut CHIP8, host: &mut H, config: &Config, program_path: Option<&str>) -> Result<(), ExecError> {
+	let mut halted = None; // an error stopped the program, the frontend keeps showing its last screen
+	let mut rewind = RewindBuffer::new(config.rewind_frames);
+	let mut rewinding = false;
+	rewind.capture(machine);
+
+	let mut gdb = config.gdb_port.and_then(|port| match GdbStub::listen(("127.0.0.1", port)) {
+		Ok(stub) => {
+			println!("Waiting for GDB on port {}", port);
+			Some(stub)
+		},
+		Err(e) => {
+			eprintln!("Couldn't listen for GDB on port {}: {}", port, e);
+			None
+		}
+	});
+	let mut debugger = if config.debug || gdb.is_some() { Some(Debugger::for_program(config, program_path)) } else { None };
+	let mut recording = config.record.as_ref().and_then(|path| start_recording(path.clone(), &config.palette, config));
+
+	// the palette key goes from the palette the program started with through the built-in ones
+	let mut palettes = vec![config.palette];
+	palettes.extend(Palette::THEMES.iter().map(|&(_, palette)| palette).filter(|&palette| palette != config.palette));
+	let mut palette = 0;
+
+	// frames are always shown once at the end of each pass, never partway through one, but programs that
+	// erase a sprite in one frame and draw it in the next still flicker unless a filter blends them
+	let mut filter = if config.filter == Filter::Off { None } else { Some(ScreenFilter::new(config.filter)) };
+
+	while !machine.has_exited() {
+		let frames = host.wait_for_frames();
+
+		for event in host.poll_input(machine) {
+			match event {
+				HostEvent::Quit => {
+					stop_recording(&mut recording);
+					return halted.map_or(Ok(()), Err);
+				},
+				HostEvent::Rewind(on) => rewinding = on,
+				HostEvent::SaveState(slot) => match program_path {
+					Some(p) => save_slot(machine, p, slot),
+					None => eprintln!("Can't save state, the program wasn't loaded from a file")
+				},
+				HostEvent::LoadState(slot) => match program_path {
+					Some(p) => if load_slot(machine, p, slot) {
+						halted = None; // the state might be from before the error
+						host.set_halted(None);
+					},
+					None => eprintln!("Can't load state, the program wasn't loaded from a file")
+				},
+				HostEvent::Screenshot => match program_path {
+					Some(p) => save_screenshot(machine, p, &palettes[palette], config),
+					None => eprintln!("Can't save a screenshot, the program wasn't loaded from a file")
+				},
+				HostEvent::Record if recording.is_some() => stop_recording(&mut recording),
+				HostEvent::Record => match program_path {
+					Some(p) => recording = gif_path(p).and_then(|path| start_recording(path, &palettes[palette], config)),
+					None => eprintln!("Can't record, the program wasn't loaded from a file")
+				},
+				HostEvent::NextPalette => {
+					palette = (palette + 1) % palettes.len();
+					println!("Palette {}", palettes[palette]);
+					host.set_palette(&palettes[palette]);
+					host.present(&shown(machine, &filter));
+				},
+				HostEvent::Pause | HostEvent::Step | HostEvent::StepOver | HostEvent::StepOut => {
+					if let (Some(d), None) = (debugger.as_mut(), halted) {
+						if let Err(e) = debug_event(machine, d, host, event, &config.errors) {
+							eprintln!("Halted: {}", e);
+							host.set_halted(Some(&e));
+							halted = Some(e);
+						}
+					}
+				}
+			}
+		}
+
+		if let (Some(stub), Some(d)) = (gdb.as_mut(), debugger.as_mut()) {
+			match stub.poll(machine, d, &config.errors) {
+				Ok(GdbEvent::Killed) => {
+					stop_recording(&mut recording);
+					return halted.map_or(Ok(()), Err);
+				},
+				Ok(_) => (),
+				Err(e) => eprintln!("Lost the connection to GDB: {}", e)
+			}
+			if halted.is_some() && !d.is_paused() {
+				halted = None; // GDB carries on from the error, maybe after fixing it
+				host.set_halted(None);
+			}
+		}
+
+		let mut filtered = false; // the filter changed what's shown
+		for _ in 0 .. frames {
+			if rewinding {
+				if rewind.rewind(machine) && halted.is_some() {
+					halted = None; // back to before the error
+					host.set_halted(None);
+				}
+				record_frame(&mut recording, machine);
+				filtered |= filter_frame(&mut filter, machine);
+				continue;
+			}
+
+			if halted.is_some() { break; }
+
+			let result = match debugger.as_mut() {
+				Some(d) if d.is_paused() => break,
+				Some(d) => d.run_frame(machine, &config.errors).map(|stop| {
+					if let Some(stop) = stop {
+						print_stop(&stop);
+						show_pause(machine, d, host);
+						if let Some(stub) = gdb.as_mut() {
+							report_to_gdb(stub.stopped(&stop));
+						}
+					}
+				}),
+				None => machine.run_frame(&config.errors)
+			};
+
+			if let Err(e) = result {
+				eprintln!("Halted: {}", e);
+				if let Some(d) = debugger.as_mut() {
+					println!("{}", d.status(machine));
+				}
+				if let (Some(stub), Some(d)) = (gdb.as_mut(), debugger.as_mut()) {
+					d.pause(); // until GDB continues
+					report_to_gdb(stub.halted(&e));
+				}
+				host.set_halted(Some(&e));
+				halted = Some(e);
+			}
+
+			// frames the debugger stopped partway through are captured once they're finished
+			if !debugger.as_ref().is_some_and(|d| d.is_paused()) {
+				rewind.capture(machine);
+				record_frame(&mut recording, machine);
+				filtered |= filter_frame(&mut filter, machine);
+			}
+		}
+
+		if machine.draw_flag || filtered {
+			machine.draw_flag = false;
+			host.present(&shown(machine, &filter));
+		}
+		host.update_sound(machine.sound_state());
+	}
+
+	if let Some(stub) = gdb.as_mut() {
+		report_to_gdb(stub.exited());
+	}
+	stop_recording(&mut recording);
+	halted.map_or(Ok(()), Err)
+}
+
+// The screen as it's shown, through the filter if there is one
+fn shown<'a>(machine: &'a CHIP8, filter: &'a Option<ScreenFilter>) -> Screen<'a> {
+	match filter {
+		Some(f) if f.screen().width() > 0 => f.screen(),
+		_ => machine.screen()
+	}
+}
+
+// Returns whether what the filter shows changed
+fn filter_frame(filter: &mut Option<ScreenFilter>, machine: &CHIP8) -> bool {
+	filter.as_mut().is_some_and(|f| f.push(&machine.screen()))
+}
+
+fn report_to_gdb(result: std::io::Result<()>) {
+	if let Err(e) = result {
+		eprintln!("Lost the connection to GDB: {}", e);
+	}
+}
+
+// Pauses, resumes or steps the machine for one of the debugger's events
+fn debug_event<H: Host>(machine: &mut CHIP8, debugger: &mut Debugger, host: &mut H, event: HostEvent,
+						policy: &ErrorPolicy) -> Result<(), ExecError> {
+	match event {
+		HostEvent::Pause if debugger.is_paused() => {
+			debugger.resume();
+			host.set_paused(false);
+		},
+		HostEvent::Pause => {
+			debugger.pause();
+			show_pause(machine, debugger, host);
+		},
+		HostEvent::Step => {
+			print_stop(&debugger.step(machine, policy)?);
+			show_pause(machine, debugger, host);
+		},
+		HostEvent::StepOver => match debugger.step_over(machine, policy)? {
+			Some(stop) => {
+				print_stop(&stop);
+				show_pause(machine, debugger, host);
+			},
+			None => host.set_paused(false) // running until the call returns
+		},
+		HostEvent::StepOut => {
+			debugger.step_out(machine);
+			host.set_paused(false);
+		},
+		_ => ()
+	}
+	Ok(())
+}
+
+// Says why the machine stopped, unless it's just the end of a step
+fn print_stop(stop: &Stop) {
+	if *stop != Stop::Stepped {
+		println!("{}", stop);
+	}
+}
+
+// The registers are printed whenever the machine pauses, so they can be followed from the terminal
+fn show_pause<H: Host>(machine: &CHIP8, debugger: &Debugger, host: &mut H) {
+	println!("{}", debugger.status(machine));
+	host.set_paused(true);
+}
+
+fn save_slot(machine: &CHIP8, program_path: &str, slot: u8) {
+	let path = state_slot_path(program_path, slot);
+	match fs::write(&path, machine.save_state()) {
+		Ok(()) => println!("Saved state to slot {}", slot),
+		Err(e) => eprintln!("Couldn't save state to {}: {}", path.display(), e)
+	}
+}
+
+// Returns whether the state was loaded
+fn load_slot(machine: &mut CHIP8, program_path: &str, slot: u8) -> bool {
+	let path = state_slot_path(program_path, slot);
+	let loaded = fs::read(&path).map_err(|e| e.to_string())
+					.and_then(|data| machine.load_state(&data).map_err(|e| e.to_string()));
+
+	match loaded {
+		Ok(()) => {
+			println!("Loaded state from slot {}", slot);
+			true
+		},
+		Err(e) => {
+			eprintln!("Couldn't load state from {}: {}", path.display(), e);
+			false
+		}
+	}
+}
+
+#[cfg(feature = "image")]
+fn save_screenshot(machine: &CHIP8, program_path: &str, palette: &Palette, config: &Config) {
+	let path = crate::screenshot::screenshot_path(program_path, std::time::SystemTime::now());
+	match machine.save_screenshot(&path, config.screenshot_scale, palette) {
+		Ok(()) => println!("Saved a screenshot to {}", path.display()),
+		Err(e) => eprintln!("Couldn't save a screenshot to {}: {}", path.display(), e)
+	}
+}
+
+#[cfg(not(feature = "image"))]
+fn save_screenshot(_machine: &CHIP8, _program_path: &str, _palette: &Palette, _config: &Config) {
+	eprintln!("Can't save a screenshot, the emulator was built without the image feature");
+}
+
+fn start_recording(path: PathBuf, palette: &Palette, config: &Config) -> Option<(Recorder, PathBuf)> {
+	match Recorder::create(&path, config.screenshot_scale, palette) {
+		Ok(recorder) => {
+			println!("Recording to {}", path.display());
+			Some((recorder, path))
+		},
+		Err(e) => {
+			eprintln!("Couldn't record to {}: {}", path.display(), e);
+			None
+		}
+	}
+}
+
+fn record_frame(recording: &mut Option<(Recorder, PathBuf)>, machine: &CHIP8) {
+	if let Some((recorder, path)) = recording.as_mut() {
+		if let Err(e) = recorder.capture(&machine.screen()) {
+			eprintln!("Stopped recording to {}: {}", path.display(), e);
+			*recording = None;
+		}
+	}
+}
+
+fn stop_recording(recording: &mut Option<(Recorder, PathBuf)>) {
+	if let Some((recorder, path)) = recording.take() {
+		let frames = recorder.frames();
+		match recorder.finish() {
+			Ok(()) => println!("Saved {} frames to {}", frames, path.display()),
+			Err(e) => eprintln!("Couldn't finish the recording {}: {}", path.display(), e)
+		}
+	}
+}
+
+// Recordings started from the keyboard are GIFs, named like screenshots
+#[cfg(feature = "image")]
+fn gif_path(program_path: &str) -> Option<PathBuf> {
+	Some(crate::screenshot::capture_path(program_path, std::time::SystemTime::now(), "gif"))
+}
+
+#[cfg(not(feature = "image"))]
+fn gif_path(_program_path: &str) -> Option<PathBuf> {
+	eprintln!("Can't record a GIF, the emulator was built without the image feature");
+	None
+}
+
+// Quick-save slots are kept next to the ROM, e.g. pong.ch8.state1
+fn state_slot_path(program_path: &str, slot: u8) -> PathBuf {
+	PathBuf::from(format!("{}.state{}", program_path, slot))
+}
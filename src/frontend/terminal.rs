@@ -0,0 +1,455 @@
+use crate::{
+	CHIP8, Chip8Key, ExecError, Config, Keymap, Palette,
+	frontend::{ Screen, SoundState, HostEvent, VideoSink, AudioSink, InputSource, Clock },
+	timing::FrameClock
+};
+
+use std::{
+	fmt::Write as _,
+	io::{ self, IsTerminal, Read, Write },
+	path::Path,
+	process::{ Command, Stdio },
+	sync::mpsc::{ self, Receiver },
+	thread,
+	time::{ Duration, Instant }
+};
+
+// Terminals only say when a key is typed, not when it's let go. A key stays down for this long after it's
+// pressed, long enough for auto-repeat to start, which usually waits a quarter to half a second
+pub const FIRST_HOLD: Duration = Duration::from_millis(500);
+
+// Once auto-repeat has started, a key stays down for this long after it was last typed. Repeats come every
+// 30 to 50 milliseconds, so it's let go soon after they stop
+pub const REPEAT_HOLD: Duration = Duration::from_millis(150);
+
+// Escape sequences can arrive split over several reads, especially over SSH. What's left of one at the end
+// of a read waits this long for the rest before a lone escape counts as the escape key
+pub const ESCAPE_WAIT: Duration = Duration::from_millis(100);
+
+// Keymap bindings by scancode can't be used, terminals don't send them
+const NO_SCANCODE: u32 = u32::MAX;
+
+// How the screen is drawn with text
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum TextMode {
+	HalfBlocks, // a ▀ for every 1x2 pixels, coloured with the top pixel and backed with the bottom one
+	Braille // a Braille pattern for every 2x4 pixels, smaller but in one colour a cell
+}
+
+impl TextMode {
+	// half-blocks or braille
+	pub fn parse(name: &str) -> Option<TextMode> {
+		match name.to_lowercase().as_str() {
+			"half-blocks" | "halfblocks" | "blocks" => Some(TextMode::HalfBlocks),
+			"braille" => Some(TextMode::Braille),
+			_ => None
+		}
+	}
+
+	// How many pixels wide and high a character is
+	fn cell_size(self) -> (usize, usize) {
+		match self {
+			TextMode::HalfBlocks => (1, 2),
+			TextMode::Braille => (2, 4)
+		}
+	}
+}
+
+// A character on the terminal and its colours
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Cell {
+	pub ch: char,
+	pub fg: [u8; 4],
+	pub bg: [u8; 4]
+}
+
+// The screen as rows of characters, returning how many there are to a row
+pub(crate) fn render(screen: &Screen, mode: TextMode, palette: &Palette) -> (usize, Vec<Cell>) {
+	let (cell_w, cell_h) = mode.cell_size();
+	let columns = screen.width() / cell_w;
+	let rows = screen.height() / cell_h;
+
+	let cells = (0 .. rows).flat_map(|row| (0 .. columns).map(move |column| (column, row))).map(|(column, row)| {
+		let (x, y) = (column * cell_w, row * cell_h);
+		match mode {
+			TextMode::HalfBlocks => Cell {
+				ch: '▀',
+				fg: screen.colour_in(palette, x, y),
+				bg: screen.colour_in(palette, x, y + 1)
+			},
+			TextMode::Braille => {
+				// the dots are numbered down the left column, then down the right, then along the bottom row
+				const DOTS: [[u32; 4]; 2] = [ [0x01, 0x02, 0x04, 0x40], [0x08, 0x10, 0x20, 0x80] ];
+				let mut dots = 0;
+				let mut planes = [0; 4]; // how many of the dots are set in each combination of planes
+				for (dx, column_dots) in DOTS.iter().enumerate() {
+					for (dy, dot) in column_dots.iter().enumerate() {
+						let pixel = screen.pixel(x + dx, y + dy) as usize;
+						if pixel != 0 {
+							dots |= dot;
+							planes[pixel] += 1;
+						}
+					}
+				}
+
+				// the dots take the colour most of them have
+				let plane = (1 .. 4).rev().max_by_key(|&p| planes[p]).unwrap();
+				Cell {
+					ch: char::from_u32(0x2800 + dots).unwrap(),
+					fg: palette.colour(plane as u8),
+					bg: palette.colour(0)
+				}
+			}
+		}
+	}).collect();
+
+	(columns, cells)
+}
+
+// Adds the escape codes that turn the previous frame into the next to out, moving the cursor only to the
+// cells that changed. An empty previous frame draws everything
+pub(crate) fn redraw(previous: &[Cell], next: &[Cell], columns: usize, out: &mut String) {
+	let mut cursor = None; // where the last character was written, the cursor is right after it
+	let mut colours = None;
+
+	for (i, cell) in next.iter().enumerate() {
+		if previous.get(i) == Some(cell) { continue; }
+
+		let (row, column) = (i / columns, i % columns);
+		if column == 0 || cursor != Some((row, column - 1)) {
+			write!(out, "\x1b[{};{}H", row + 1, column + 1).unwrap();
+		}
+		if colours != Some((cell.fg, cell.bg)) {
+			let ([fr, fg, fb, _], [br, bg, bb, _]) = (cell.fg, cell.bg);
+			write!(out, "\x1b[38;2;{};{};{};48;2;{};{};{}m", fr, fg, fb, br, bg, bb).unwrap();
+			colours = Some((cell.fg, cell.bg));
+		}
+		out.push(cell.ch);
+		cursor = Some((row, column));
+	}
+}
+
+// The names of the keys typed, in the keymap's names for them, and how many of the bytes they took up.
+// Escape sequences for the arrows and the function keys are understood, the other keys without a character
+// are left out. Unless the input is complete, an escape sequence that's cut off at the end is left for the
+// next read to finish
+pub(crate) fn parse_keys(bytes: &[u8], complete: bool) -> (Vec<String>, usize) {
+	let mut keys = Vec::new();
+	let mut i = 0;
+
+	while i < bytes.len() {
+		let byte = bytes[i];
+		i += 1;
+
+		let key = match byte {
+			b'0' ..= b'9' => format!("Key{}", byte as char),
+			b'a' ..= b'z' | b'A' ..= b'Z' => (byte as char).to_ascii_uppercase().to_string(),
+			b' ' => "Space".to_string(),
+			b'\r' | b'\n' => "Return".to_string(),
+			b'\t' => "Tab".to_string(),
+			0x7F | 0x08 => "Back".to_string(),
+			0x03 => "Escape".to_string(), // ctrl-C doesn't interrupt in raw mode, so it quits like escape
+			0x10 => "Snapshot".to_string(), // terminals don't pass print screen on, ctrl-P stands in for it
+			0x12 => "Scroll".to_string(), // and ctrl-R for scroll lock
+			0x1B => {
+				// an escape sequence runs up to a letter or ~, a lone escape is the escape key
+				let sequence = match bytes.get(i) {
+					Some(b'[') | Some(b'O') => {
+						let end = bytes[i + 1 ..].iter().position(|b| b.is_ascii_alphabetic() || *b == b'~');
+						end.map(|end| &bytes[i .. i + end + 2])
+					},
+					_ => None
+				};
+
+				match sequence {
+					Some(sequence) => {
+						i += sequence.len();
+						match escape_key(sequence) {
+							Some(key) => key.to_string(),
+							None => continue
+						}
+					},
+					None if !complete && matches!(bytes.get(i), None | Some(b'[') | Some(b'O')) => return (keys, i - 1),
+					None => "Escape".to_string()
+				}
+			},
+			_ => continue
+		};
+		keys.push(key);
+	}
+
+	(keys, bytes.len())
+}
+
+fn escape_key(sequence: &[u8]) -> Option<&'static str> {
+	Some(match sequence {
+		b"[A" | b"OA" => "Up",
+		b"[B" | b"OB" => "Down",
+		b"[C" | b"OC" => "Right",
+		b"[D" | b"OD" => "Left",
+		b"OP" | b"[11~" => "F1",
+		b"OQ" | b"[12~" => "F2",
+		b"OR" | b"[13~" => "F3",
+		b"OS" | b"[14~" => "F4",
+		b"[15~" => "F5",
+		b"[17~" => "F6",
+		b"[18~" => "F7",
+		b"[19~" => "F8",
+		b"[20~" => "F9",
+		b"[21~" => "F10",
+		b"[23~" => "F11",
+		b"[24~" => "F12",
+		_ => return None
+	})
+}
+
+// The same keys as the window: F1 to F4 save, F5 to F8 load, F9 to F12 pause and step
+fn function_key(name: &str) -> Option<HostEvent> {
+	match name {
+		"F1" => Some(HostEvent::SaveState(1)),
+		"F2" => Some(HostEvent::SaveState(2)),
+		"F3" => Some(HostEvent::SaveState(3)),
+		"F4" => Some(HostEvent::SaveState(4)),
+		"F5" => Some(HostEvent::LoadState(1)),
+		"F6" => Some(HostEvent::LoadState(2)),
+		"F7" => Some(HostEvent::LoadState(3)),
+		"F8" => Some(HostEvent::LoadState(4)),
+		"F9" => Some(HostEvent::Pause),
+		"F10" => Some(HostEvent::StepOver),
+		"F11" => Some(HostEvent::Step),
+		"F12" => Some(HostEvent::StepOut),
+		_ => None
+	}
+}
+
+// A frontend for terminals, e.g. over SSH: the screen drawn in text with 24-bit colour, the keyboard read in
+// raw mode and the terminal's bell for the buzzer
+pub struct TerminalHost {
+	mode: TextMode,
+	palette: Palette,
+	title: String,
+	keymap: Keymap,
+	input: Receiver<Vec<u8>>, // what's typed, read on another thread so polling doesn't block
+	pending: Vec<u8>, // the start of an escape sequence whose end hasn't been read yet
+	pending_since: Instant,
+	output: Box<dyn Write>,
+	cells: Vec<Cell>, // what's on the terminal now
+	columns: usize,
+	held: Vec<(String, Instant)>, // the keys that count as down, and when they'll be let go
+	keys_down: [bool; 16],
+	bell: bool, // ring the bell when the buzzer starts. Off when muted
+	sounding: bool,
+	clock: FrameClock,
+	saved_mode: Option<String> // the terminal's settings before raw mode, to put back at the end
+}
+
+impl TerminalHost {
+	// Takes over the terminal the emulator was started from
+	pub fn new(mode: TextMode, program_path: &str, config: &Config) -> io::Result<TerminalHost> {
+		if !io::stdin().is_terminal() || !io::stdout().is_terminal() {
+			return Err(io::Error::other("the input and output have to be a terminal"));
+		}
+
+		// no line buffering, echo or signals, and reads return as soon as there's a byte
+		let saved_mode = stty(&["-g"])?;
+		stty(&["-icanon", "-echo", "-isig", "-ixon", "min", "1"])?;
+
+		let (sender, input) = mpsc::channel();
+		thread::spawn(move || {
+			let mut buffer = [0; 64];
+			let mut stdin = io::stdin();
+			while let Ok(n @ 1 ..) = stdin.read(&mut buffer) {
+				if sender.send(buffer[.. n].to_vec()).is_err() { break; }
+			}
+		});
+
+		let mut host = TerminalHost::with_io(mode, program_path, config, input, Box::new(io::stdout()));
+		host.saved_mode = Some(saved_mode.trim().to_string());
+		write!(host.output, "\x1b[?25l\x1b[2J")?; // hide the cursor and clear the terminal
+		Ok(host)
+	}
+
+	// A terminal frontend reading keys from input and drawing to output, without touching the terminal's modes
+	pub fn with_io(mode: TextMode, program_path: &str, config: &Config, input: Receiver<Vec<u8>>, output: Box<dyn Write>) -> TerminalHost {
+		let program_name = Path::new(program_path).file_name()
+							.map_or(program_path.to_string(), |n| n.to_string_lossy().into_owned());
+
+		TerminalHost {
+			mode,
+			palette: config.palette,
+			title: config.title.clone().unwrap_or_else(|| program_name.clone()),
+			keymap: config.keymap.for_rom(&program_name),
+			input,
+			pending: Vec::new(),
+			pending_since: Instant::now(),
+			output,
+			cells: Vec::new(),
+			columns: 0,
+			held: Vec::new(),
+			keys_down: [false; 16],
+			bell: config.audio.volume > 0.0,
+			sounding: false,
+			clock: FrameClock::new(Instant::now()),
+			saved_mode: None
+		}
+	}
+
+	// The line under the screen says what's running, and whether it's halted or paused
+	fn show_status(&mut self, status: &str) {
+		let rows = self.cells.len() / self.columns.max(1);
+		let line = format!("\x1b[{};1H\x1b[0m\x1b[KCHIP-8 {}{}\n", rows + 2, self.title, status);
+		self.write(&line);
+	}
+
+	fn write(&mut self, text: &str) {
+		if let Err(e) = self.output.write_all(text.as_bytes()).and_then(|_| self.output.flush()) {
+			eprintln!("Couldn't draw to the terminal: {}", e);
+		}
+	}
+
+	// Handles the keys typed since the last poll, and lets go of the ones that haven't been typed for a while
+	pub(crate) fn poll_at(&mut self, machine: &mut CHIP8, now: Instant) -> Vec<HostEvent> {
+		let mut events = Vec::new();
+		if self.pending.is_empty() {
+			self.pending_since = now;
+		}
+		self.pending.extend(self.input.try_iter().flatten());
+
+		let (keys, read) = parse_keys(&self.pending, now >= self.pending_since + ESCAPE_WAIT);
+		self.pending.drain(.. read);
+
+		for name in keys {
+			match name.as_str() {
+				"Escape" => events.push(HostEvent::Quit),
+				"Snapshot" => events.push(HostEvent::Screenshot),
+				"Scroll" => events.push(HostEvent::Record),
+				"Tab" => events.push(HostEvent::NextPalette),
+				_ if function_key(&name).is_some() => events.extend(function_key(&name)),
+				_ => {
+					let was_held = self.held.iter().any(|(n, _)| *n == name);
+					self.held.retain(|(n, _)| *n != name);
+					if name == "Back" && !was_held {
+						events.push(HostEvent::Rewind(true)); // backspace rewinds for as long as it's held
+					}
+					let hold = if was_held { REPEAT_HOLD } else { FIRST_HOLD };
+					self.held.push((name, now + hold));
+				}
+			}
+		}
+
+		if self.held.iter().any(|(name, until)| name == "Back" && *until <= now) {
+			events.push(HostEvent::Rewind(false));
+		}
+		self.held.retain(|&(_, until)| until > now);
+
+		// a CHIP-8 key stays down for as long as any of the host keys bound to it are
+		let mut down = [false; 16];
+		for (name, _) in self.held.iter() {
+			for key in self.keymap.chip8_keys(Some(name), NO_SCANCODE) {
+				down[key.index() as usize] = true;
+			}
+		}
+		for key in Chip8Key::all() {
+			let i = key.index() as usize;
+			if down[i] != self.keys_down[i] {
+				machine.set_key(key, down[i]);
+			}
+		}
+		self.keys_down = down;
+
+		events
+	}
+}
+
+impl Drop for TerminalHost {
+	// puts the cursor under the screen and the terminal back the way it was
+	fn drop(&mut self) {
+		if let Some(mode) = self.saved_mode.take() {
+			self.write("\x1b[0m\x1b[?25h");
+			if let Err(e) = stty(&[&mode]) {
+				eprintln!("Couldn't restore the terminal, `stty sane` will: {}", e);
+			}
+		}
+	}
+}
+
+impl VideoSink for TerminalHost {
+	fn present(&mut self, screen: &Screen) {
+		let (columns, cells) = render(screen, self.mode, &self.palette);
+
+		let mut out = String::new();
+		if columns != self.columns || cells.len() != self.cells.len() {
+			// the resolution changed, start again on a clear terminal
+			out.push_str("\x1b[0m\x1b[2J");
+			self.cells.clear();
+		}
+		redraw(&self.cells, &cells, columns, &mut out);
+		let resized = self.cells.is_empty();
+		self.cells = cells;
+		self.columns = columns;
+
+		// the cursor is left under the screen, for anything printed while running
+		let rows = self.cells.len() / columns.max(1);
+		write!(out, "\x1b[0m\x1b[{};1H", rows + 3).unwrap();
+		self.write(&out);
+
+		if resized {
+			self.show_status("");
+		}
+	}
+
+	fn set_halted(&mut self, error: Option<&ExecError>) {
+		match error {
+			Some(e) => self.show_status(&format!(" (halted: {})", e)),
+			None => self.show_status("")
+		}
+	}
+
+	fn set_paused(&mut self, paused: bool) {
+		self.show_status(if paused { " (paused)" } else { "" });
+	}
+
+	fn set_palette(&mut self, palette: &Palette) {
+		self.palette = *palette;
+	}
+}
+
+impl AudioSink for TerminalHost {
+	// the bell can't be held, so it rings once each time the buzzer starts
+	fn update_sound(&mut self, sound: SoundState) {
+		if sound.playing && !self.sounding && self.bell {
+			self.write("\x07");
+		}
+		self.sounding = sound.playing;
+	}
+}
+
+impl InputSource for TerminalHost {
+	fn poll_input(&mut self, machine: &mut CHIP8) -> Vec<HostEvent> {
+		self.poll_at(machine, Instant::now())
+	}
+}
+
+impl Clock for TerminalHost {
+	// sleeps until the next frame is due
+	fn wait_for_frames(&mut self) -> u32 {
+		loop {
+			let now = Instant::now();
+			let frames = self.clock.frames_due(now);
+			if frames > 0 {
+				return frames;
+			}
+
+			thread::sleep(self.clock.next_frame().saturating_duration_since(now));
+		}
+	}
+}
+
+// Runs stty on the terminal, returning what it printed
+fn stty(args: &[&str]) -> io::Result<String> {
+	let output = Command::new("stty").args(args).stdin(Stdio::inherit()).output()?;
+	if !output.status.success() {
+		return Err(io::Error::other(String::from_utf8_lossy(&output.stderr).trim().to_string()));
+	}
+	Ok(String::from_utf8_lossy(&output.stdout).into_owned())
+}
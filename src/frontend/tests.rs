@@ -0,0 +1,181 @@
+use crate::{
+	CHIP8, Config, ExecError, HeadlessHost, Chip8Key, HostKey, TerminalHost, TextMode, Palette,
+	frontend::{ run_host, Screen, HostEvent, terminal::{ render, redraw, parse_keys, FIRST_HOLD, REPEAT_HOLD, ESCAPE_WAIT } }
+};
+
+use std::{ io, sync::mpsc, time::{ Duration, Instant } };
+
+fn get_machine(program: &[u8]) -> CHIP8 {
+	let mut machine = CHIP8::new(&[]).unwrap();
+	machine.memory[0x200 .. 0x200 + program.len()].copy_from_slice(program);
+
+	machine
+}
+
+#[test]
+fn headless_runs_the_given_frames() {
+	let mut machine = get_machine(&[0x12, 0x00]); // jump to itself
+	let mut host = HeadlessHost::new(90);
+
+	assert_eq!(run_host(&mut machine, &mut host, &Config::default(), None), Ok(()));
+	assert_eq!(machine.frame_count(), 90);
+}
+
+#[test]
+fn headless_presents_the_screen() {
+	// draw the font's 0 in the top left corner, then loop
+	let mut machine = get_machine(&[0xA0, 0x00, 0xD0, 0x05, 0x12, 0x04]);
+	let mut host = HeadlessHost::new(2);
+
+	run_host(&mut machine, &mut host, &Config::default(), None).unwrap();
+
+	let screen = host.screen();
+	assert_eq!((screen.width(), screen.height()), (64, 32));
+	assert_eq!(screen.pixel(0, 0), 1);
+	assert_eq!(screen.pixel(4, 0), 0);
+	assert_eq!(screen.pixel(1, 1), 0);
+	assert_eq!(host.frames_presented(), 1);
+}
+
+#[test]
+fn headless_stops_when_halted() {
+	let mut machine = get_machine(&[0x00, 0xEE]); // return with nothing on the stack
+	let mut host = HeadlessHost::new(60);
+
+	let result = run_host(&mut machine, &mut host, &Config::default(), None);
+
+	assert_eq!(result, Err(ExecError::StackUnderflow { pc: 0x200, opcode: 0x00EE }));
+	assert_eq!(machine.frame_count(), 0);
+}
+
+#[test]
+fn headless_stops_when_the_program_exits() {
+	let mut machine = get_machine(&[0x00, 0xFD]);
+	let mut host = HeadlessHost::new(60);
+
+	assert_eq!(run_host(&mut machine, &mut host, &Config::default(), None), Ok(()));
+	assert_eq!(machine.frame_count(), 1);
+}
+
+#[test]
+fn terminal_draws_half_blocks_and_braille() {
+	let mut pixels = vec![0; 64 * 32];
+	pixels[0] = 1; // top left
+	pixels[64 + 1] = 2; // one down and one along
+	let screen = Screen::new(64, 32, &pixels);
+
+	let (columns, cells) = render(&screen, TextMode::HalfBlocks, &Palette::CLASSIC);
+	assert_eq!((columns, cells.len()), (64, 64 * 16));
+	assert_eq!((cells[0].fg, cells[0].bg), (Palette::CLASSIC.colour(1), Palette::CLASSIC.colour(0)));
+	assert_eq!((cells[1].fg, cells[1].bg), (Palette::CLASSIC.colour(0), Palette::CLASSIC.colour(2)));
+
+	let (columns, cells) = render(&screen, TextMode::Braille, &Palette::CLASSIC);
+	assert_eq!((columns, cells.len()), (32, 32 * 8));
+	assert_eq!(cells[0].ch, '⠑'); // dots 1 and 5
+	assert_eq!(cells[0].fg, Palette::CLASSIC.colour(1)); // a tie goes to the first plane
+	assert_eq!(cells[1].ch, '⠀');
+}
+
+#[test]
+fn terminal_redraws_only_changed_cells() {
+	let mut pixels = vec![0; 64 * 32];
+	let (columns, before) = render(&Screen::new(64, 32, &pixels), TextMode::HalfBlocks, &Palette::CLASSIC);
+	pixels[3] = 1;
+	pixels[4] = 1;
+	let (_, after) = render(&Screen::new(64, 32, &pixels), TextMode::HalfBlocks, &Palette::CLASSIC);
+
+	let mut out = String::new();
+	redraw(&before, &after, columns, &mut out);
+	assert_eq!(out, "\x1b[1;4H\x1b[38;2;255;255;255;48;2;0;0;0m▀▀");
+
+	out.clear();
+	redraw(&after, &after, columns, &mut out);
+	assert!(out.is_empty());
+}
+
+#[test]
+fn terminal_key_names() {
+	assert_eq!(parse_keys(b"1q\x1b[A\x1bOP\x1b[21~ \x7f", true).0, vec!["Key1", "Q", "Up", "F1", "F10", "Space", "Back"]);
+	assert_eq!(parse_keys(b"\x1b", true), (vec!["Escape".to_string()], 1));
+	assert_eq!(parse_keys(b"\x1b[99~x", true).0, vec!["X"]); // unknown sequences are skipped
+	assert_eq!(parse_keys(b"q\x1b[2", false), (vec!["Q".to_string()], 1)); // the rest of the sequence is still to come
+}
+
+#[test]
+fn terminal_keys_are_let_go_after_a_while() {
+	let (sender, input) = mpsc::channel();
+	let mut host = TerminalHost::with_io(TextMode::HalfBlocks, "game.ch8", &Config::default(), input, Box::new(io::sink()));
+	let mut machine = get_machine(&[]);
+	let start = Instant::now();
+
+	sender.send(b"w\x1b[19~".to_vec()).unwrap();
+	assert_eq!(host.poll_at(&mut machine, start), vec![HostEvent::LoadState(4)]);
+	let five = Chip8Key::from_digit('5').unwrap();
+	assert!(machine.is_key_pressed(five));
+
+	// it stays down through the pause before auto-repeat starts
+	host.poll_at(&mut machine, start + Duration::from_millis(400));
+	assert!(machine.is_key_pressed(five));
+
+	// then each repeat keeps it down a little longer
+	let repeat = start + Duration::from_millis(450);
+	sender.send(b"w".to_vec()).unwrap();
+	host.poll_at(&mut machine, repeat);
+	host.poll_at(&mut machine, repeat + REPEAT_HOLD / 2);
+	sender.send(b"w".to_vec()).unwrap();
+	host.poll_at(&mut machine, repeat + REPEAT_HOLD / 2);
+	host.poll_at(&mut machine, repeat + REPEAT_HOLD);
+	assert!(machine.is_key_pressed(five));
+
+	// and it's let go soon after the repeats stop
+	host.poll_at(&mut machine, repeat + REPEAT_HOLD * 2);
+	assert!(!machine.is_key_pressed(five));
+
+	// a single tap is held until auto-repeat would have started
+	let tap = start + Duration::from_secs(1);
+	sender.send(b"w".to_vec()).unwrap();
+	host.poll_at(&mut machine, tap);
+	host.poll_at(&mut machine, tap + REPEAT_HOLD * 2);
+	assert!(machine.is_key_pressed(five));
+	host.poll_at(&mut machine, tap + FIRST_HOLD);
+	assert!(!machine.is_key_pressed(five));
+
+	// a lone escape waits a moment in case it's the start of a sequence
+	sender.send(b"\t\x1b".to_vec()).unwrap();
+	assert_eq!(host.poll_at(&mut machine, start), vec![HostEvent::NextPalette]);
+	assert_eq!(host.poll_at(&mut machine, start + ESCAPE_WAIT), vec![HostEvent::Quit]);
+}
+
+#[test]
+fn terminal_escape_sequences_can_be_split() {
+	let (sender, input) = mpsc::channel();
+	let mut host = TerminalHost::with_io(TextMode::HalfBlocks, "game.ch8", &Config::default(), input, Box::new(io::sink()));
+	let mut machine = get_machine(&[]);
+	let start = Instant::now();
+
+	sender.send(b"\x1b".to_vec()).unwrap();
+	assert_eq!(host.poll_at(&mut machine, start), vec![]);
+	sender.send(b"[19".to_vec()).unwrap();
+	assert_eq!(host.poll_at(&mut machine, start + ESCAPE_WAIT / 2), vec![]);
+	sender.send(b"~".to_vec()).unwrap();
+	assert_eq!(host.poll_at(&mut machine, start + ESCAPE_WAIT / 2), vec![HostEvent::LoadState(4)]);
+
+}
+
+#[test]
+fn terminal_arrows_can_be_split() {
+	let mut config = Config::default();
+	let up = Chip8Key::from_digit('2').unwrap();
+	config.keymap.bind(up, HostKey::Named("Up".to_string()));
+
+	let (sender, input) = mpsc::channel();
+	let mut host = TerminalHost::with_io(TextMode::HalfBlocks, "game.ch8", &config, input, Box::new(io::sink()));
+	let mut machine = get_machine(&[]);
+	let start = Instant::now();
+
+	sender.send(b"\x1b".to_vec()).unwrap();
+	assert_eq!(host.poll_at(&mut machine, start), vec![]);
+	sender.send(b"[A".to_vec()).unwrap();
+	assert_eq!(host.poll_at(&mut machine, start), vec![]);
+	assert!(machine.is_key_pressed(up));
+}
@@ -0,0 +1,257 @@
+use crow::{
+	glutin::{
+		event::{ Event, WindowEvent, VirtualKeyCode, ElementState },
+		event_loop::{ EventLoop, ControlFlow },
+		platform::desktop::EventLoopExtDesktop,
+		window::WindowBuilder,
+		dpi::LogicalSize
+	},
+	Context, DrawConfig, Texture
+};
+
+use rodio::{ OutputStream, Sink };
+
+use crate::{
+	CHIP8, Chip8Key, Platform, ExecError, Config, Keymap, Palette, WINDOW_W, WINDOW_H,
+	frontend::{ Screen, SoundState, HostEvent, VideoSink, AudioSink, InputSource, Clock },
+	graphics::GraphicalContext,
+	sound::sources::{ Gate, ToneSource, SampleSource, PatternPlayer },
+	timing::FrameClock
+};
+
+use std::{
+	error::Error,
+	path::Path,
+	thread,
+	time::Instant
+};
+
+// The desktop frontend: a crow window, rodio audio and the keyboard
+pub struct WindowHost {
+	el: EventLoop<()>,
+	context: Context,
+	screen_texture: Option<Texture>,
+	title: String, // the program's name from the ROM database, or its file name
+	palette: Palette,
+	scale: u32, // window pixels per CHIP-8 pixel in the 64x32 mode
+	keymap: Keymap, // with the overrides for the running ROM applied
+	held: Vec<(u32, Chip8Key)>, // the host keys that are down, by scancode, and the CHIP-8 keys they hold down
+	clock: FrameClock,
+	_stream: OutputStream, // has to be kept alive for the sink to play
+	_sink: Sink,
+	buzzer: Gate,
+	pattern_player: Option<PatternPlayer> // XO-CHIP programs play their own audio pattern instead of the buzzer
+}
+
+impl WindowHost {
+	pub fn new(platform: Platform, program_path: &str, config: &Config) -> Result<WindowHost, Box<dyn Error>> {
+		let audio = &config.audio;
+
+		// set up the buzzer. it plays for as long as the sound timer is running
+		let (stream, stream_handle) = OutputStream::try_default()?;
+		let sink = Sink::try_new(&stream_handle)?;
+		let buzzer = Gate::default();
+
+		let pattern_player = if platform == Platform::XoChip {
+			let (player, source) = PatternPlayer::new(audio.volume);
+			sink.append(source);
+			Some(player)
+		} else {
+			let sample = audio.sample.as_ref().and_then(|path| {
+				SampleSource::load(path, audio.volume, buzzer.clone())
+					.map_err(|e| eprintln!("Couldn't load sound {}, using a tone instead: {}", path.display(), e))
+					.ok()
+			});
+
+			match sample {
+				Some(s) => sink.append(s),
+				None => sink.append(ToneSource::new(audio, buzzer.clone()))
+			}
+			None
+		};
+
+		let program_name = Path::new(program_path).file_name()
+							.map_or(program_path.to_string(), |n| n.to_string_lossy().into_owned());
+
+		let title = config.title.clone().unwrap_or_else(|| program_name.clone());
+
+		let window_bld = WindowBuilder::new()
+				.with_title(format!("CHIP-8 {}", title))
+				.with_inner_size(LogicalSize::new(WINDOW_W as u32 * config.scale, WINDOW_H as u32 * config.scale))
+				.with_resizable(false);
+
+		// create a graphical context and take the texture and context out of it through destructuring. TODO: this won't be required with rust version 2021
+		let GraphicalContext { el, ctx: context, txt: screen_texture } = GraphicalContext::new(window_bld)?;
+
+		Ok(WindowHost {
+			el,
+			context,
+			screen_texture,
+			keymap: config.keymap.for_rom(&program_name),
+			title,
+			palette: config.palette,
+			scale: config.scale,
+			held: Vec::new(),
+			clock: FrameClock::new(Instant::now()),
+			_stream: stream,
+			_sink: sink,
+			buzzer,
+			pattern_player
+		})
+	}
+
+	fn draw(&mut self) {
+		if let Some(txt) = &self.screen_texture {
+			let mut surface = self.context.surface();
+
+			// scale the screen to fill the window in either resolution
+			let scale = (WINDOW_W as u32 * self.scale / txt.width()).max(1);
+			let drw_cfg = DrawConfig {
+				scale: (scale, scale),
+				.. Default::default()
+			};
+
+			self.context.draw(&mut surface, txt, (0, 0), &drw_cfg);
+
+			if let Err(e) = self.context.present(surface) { // swap back-buffer
+				eprintln!("Couldn't present the screen: {}", e);
+			}
+		}
+	}
+}
+
+impl VideoSink for WindowHost {
+	fn present(&mut self, screen: &Screen) {
+		match Texture::from_image(&mut self.context, screen.to_image_with(&self.palette)) {
+			Ok(txt) => self.screen_texture = Some(txt),
+			Err(e) => eprintln!("Couldn't create the screen texture: {}", e)
+		}
+		self.draw();
+	}
+
+	fn set_halted(&mut self, error: Option<&ExecError>) {
+		let title = match error {
+			Some(e) => format!("CHIP-8 {} (halted: {})", self.title, e),
+			None => format!("CHIP-8 {}", self.title)
+		};
+		self.context.window().set_title(&title);
+	}
+
+	fn set_paused(&mut self, paused: bool) {
+		let title = if paused {
+			format!("CHIP-8 {} (paused)", self.title)
+		} else {
+			format!("CHIP-8 {}", self.title)
+		};
+		self.context.window().set_title(&title);
+	}
+
+	fn set_palette(&mut self, palette: &Palette) {
+		self.palette = *palette;
+	}
+}
+
+impl AudioSink for WindowHost {
+	fn update_sound(&mut self, sound: SoundState) {
+		match &self.pattern_player {
+			Some(player) => player.update(sound.playing, sound.pattern, sound.rate),
+			None => self.buzzer.set(sound.playing)
+		}
+	}
+}
+
+impl InputSource for WindowHost {
+	// handles the events glutin has queued up, then hands control back
+	fn poll_input(&mut self, machine: &mut CHIP8) -> Vec<HostEvent> {
+		let mut events = Vec::new();
+		let mut redraw = false;
+		let keymap = &self.keymap;
+		let held = &mut self.held;
+
+		self.el.run_return(|event, _, control_flow| {
+			match event {
+				Event::WindowEvent { event, .. } => match event {
+					WindowEvent::CloseRequested => events.push(HostEvent::Quit),
+					WindowEvent::KeyboardInput { input, .. } => {
+						let pressed = input.state == ElementState::Pressed;
+
+						match input.virtual_keycode {
+							Some(VirtualKeyCode::Escape) => events.push(HostEvent::Quit), // quit when pressing escape
+							Some(VirtualKeyCode::Back) => events.push(HostEvent::Rewind(pressed)), // holding backspace steps back through the last few seconds
+							Some(VirtualKeyCode::Snapshot) if pressed => events.push(HostEvent::Screenshot), // print screen saves a PNG
+							Some(VirtualKeyCode::Scroll) if pressed => events.push(HostEvent::Record), // scroll lock starts and stops recording a GIF
+							Some(VirtualKeyCode::Tab) if pressed => events.push(HostEvent::NextPalette),
+							Some(key) if pressed => events.extend(quick_save_key(key).or_else(|| debug_key(key))),
+							_ => ()
+						}
+
+						// a CHIP-8 key stays down for as long as any of the host keys bound to it are
+						let name = input.virtual_keycode.map(|key| format!("{:?}", key));
+						let keys = keymap.chip8_keys(name.as_deref(), input.scancode);
+						held.retain(|&(scancode, _)| scancode != input.scancode);
+						if pressed {
+							held.extend(keys.iter().map(|&key| (input.scancode, key)));
+						}
+
+						for key in keys {
+							machine.set_key(key, held.iter().any(|&(_, k)| k == key));
+						}
+					},
+
+					_ => ()
+				},
+
+				Event::RedrawRequested(..) => redraw = true,
+				Event::MainEventsCleared => *control_flow = ControlFlow::Exit,
+				_ => ()
+			}
+		});
+
+		if redraw {
+			self.draw();
+		}
+		events
+	}
+}
+
+impl Clock for WindowHost {
+	// sleeps until the next frame is due
+	fn wait_for_frames(&mut self) -> u32 {
+		loop {
+			let now = Instant::now();
+			let frames = self.clock.frames_due(now);
+			if frames > 0 {
+				return frames;
+			}
+
+			thread::sleep(self.clock.next_frame().saturating_duration_since(now));
+		}
+	}
+}
+
+// F1 to F4 save to the quick-save slots 1 to 4, F5 to F8 load from them
+fn quick_save_key(key: VirtualKeyCode) -> Option<HostEvent> {
+	match key {
+		VirtualKeyCode::F1 => Some(HostEvent::SaveState(1)),
+		VirtualKeyCode::F2 => Some(HostEvent::SaveState(2)),
+		VirtualKeyCode::F3 => Some(HostEvent::SaveState(3)),
+		VirtualKeyCode::F4 => Some(HostEvent::SaveState(4)),
+		VirtualKeyCode::F5 => Some(HostEvent::LoadState(1)),
+		VirtualKeyCode::F6 => Some(HostEvent::LoadState(2)),
+		VirtualKeyCode::F7 => Some(HostEvent::LoadState(3)),
+		VirtualKeyCode::F8 => Some(HostEvent::LoadState(4)),
+		_ => None
+	}
+}
+
+// F9 pauses and resumes, F10 steps over calls, F11 steps a single instruction and F12 steps out of a subroutine.
+// They only do anything when running under the debugger
+fn debug_key(key: VirtualKeyCode) -> Option<HostEvent> {
+	match key {
+		VirtualKeyCode::F9 => Some(HostEvent::Pause),
+		VirtualKeyCode::F10 => Some(HostEvent::StepOver),
+		VirtualKeyCode::F11 => Some(HostEvent::Step),
+		VirtualKeyCode::F12 => Some(HostEvent::StepOut),
+		_ => None
+	}
+}
@@ -0,0 +1,472 @@
+// A stub for GDB's remote serial protocol, so programs can be debugged from GDB or anything else that
+// speaks it. The stub drives the machine through a Debugger: breakpoints and watchpoints set from GDB
+// are the debugger's, and the machine is paused whenever GDB isn't letting it run
+use crate::{ CHIP8, ExecError, ErrorPolicy, AccessKind, debugger::{ Debugger, Stop, Watchpoint, WatchKind } };
+
+use std::{
+	io::{ self, Read, Write },
+	net::{ SocketAddr, TcpListener, TcpStream, ToSocketAddrs },
+	thread,
+	time::Duration
+};
+
+#[cfg(test)]
+mod tests;
+
+// The registers, in the order GDB numbers them: V0 to VF, I, pc, sp, the delay timer and the sound timer.
+// I, pc and sp are 2 bytes, little-endian, the others 1 byte
+const REG_I: usize = 16;
+const REG_PC: usize = 17;
+const REG_SP: usize = 18;
+const REG_DELAY: usize = 19;
+const REG_SOUND: usize = 20;
+
+// The registers described for GDB, so it knows their names and sizes without an architecture of its own
+const TARGET_XML: &str = r#"<?xml version="1.0"?>
+<!DOCTYPE target SYSTEM "gdb-target.dtd">
+<target version="1.0">
+<feature name="org.chip8.core">
+<reg name="v0" bitsize="8" regnum="0"/><reg name="v1" bitsize="8"/><reg name="v2" bitsize="8"/><reg name="v3" bitsize="8"/>
+<reg name="v4" bitsize="8"/><reg name="v5" bitsize="8"/><reg name="v6" bitsize="8"/><reg name="v7" bitsize="8"/>
+<reg name="v8" bitsize="8"/><reg name="v9" bitsize="8"/><reg name="va" bitsize="8"/><reg name="vb" bitsize="8"/>
+<reg name="vc" bitsize="8"/><reg name="vd" bitsize="8"/><reg name="ve" bitsize="8"/><reg name="vf" bitsize="8"/>
+<reg name="i" bitsize="16" type="data_ptr"/>
+<reg name="pc" bitsize="16" type="code_ptr"/>
+<reg name="sp" bitsize="16"/>
+<reg name="delay" bitsize="8"/>
+<reg name="sound" bitsize="8"/>
+</feature>
+</target>
+"#;
+
+// What happened to the connection while polling
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum GdbEvent {
+	None,
+	Detached, // GDB let go of the machine, it carries on running without it
+	Killed // GDB asked for the program to be stopped
+}
+
+struct Client {
+	stream: TcpStream,
+	buffer: Vec<u8>, // bytes received that don't make up a whole packet yet
+	no_ack: bool, // GDB turned off the + and - acknowledgements
+	running: bool // GDB is waiting to be told the machine stopped
+}
+
+pub struct GdbStub {
+	listener: TcpListener,
+	client: Option<Client>,
+	detached: bool // GDB let go of the machine, it runs freely until GDB connects again
+}
+
+impl GdbStub {
+	// Listens for GDB on addr. Connections are accepted while polling, the machine is paused until one is made
+	pub fn listen<A: ToSocketAddrs>(addr: A) -> io::Result<GdbStub> {
+		let listener = TcpListener::bind(addr)?;
+		listener.set_nonblocking(true)?;
+		Ok(GdbStub { listener, client: None, detached: false })
+	}
+
+	pub fn local_addr(&self) -> io::Result<SocketAddr> {
+		self.listener.local_addr()
+	}
+
+	pub fn is_connected(&self) -> bool {
+		self.client.is_some()
+	}
+
+	// Accepts GDB if it's waiting, then handles the packets it sent. Doesn't block
+	pub fn poll(&mut self, machine: &mut CHIP8, debugger: &mut Debugger, policy: &ErrorPolicy) -> io::Result<GdbEvent> {
+		if self.client.is_none() {
+			match self.listener.accept() {
+				Ok((stream, _)) => {
+					stream.set_nonblocking(true)?;
+					stream.set_nodelay(true)?;
+					debugger.pause(); // GDB expects to find the machine stopped
+					self.client = Some(Client { stream, buffer: Vec::new(), no_ack: false, running: false });
+				},
+				Err(e) if e.kind() == io::ErrorKind::WouldBlock => {
+					if !self.detached {
+						debugger.pause();
+					}
+					return Ok(GdbEvent::None);
+				},
+				Err(e) => return Err(e)
+			}
+		}
+
+		let event = match self.client.as_mut() {
+			Some(client) => client.poll(machine, debugger, policy),
+			None => Ok(GdbEvent::None)
+		};
+
+		match event {
+			Ok(GdbEvent::None) => Ok(GdbEvent::None),
+			Ok(event) => {
+				self.detach(debugger);
+				Ok(event)
+			},
+			Err(e) => {
+				self.detach(debugger); // the connection broke, so the machine is left running
+				Err(e)
+			}
+		}
+	}
+
+	fn detach(&mut self, debugger: &mut Debugger) {
+		self.client = None;
+		self.detached = true;
+		debugger.resume();
+	}
+
+	// Tells GDB why the machine stopped, if it was waiting for it to
+	pub fn stopped(&mut self, stop: &Stop) -> io::Result<()> {
+		self.report(&stop_reply(stop))
+	}
+
+	// Tells GDB the machine halted on an error
+	pub fn halted(&mut self, error: &ExecError) -> io::Result<()> {
+		self.report(&halt_reply(error))
+	}
+
+	pub fn exited(&mut self) -> io::Result<()> {
+		self.report("W00")
+	}
+
+	fn report(&mut self, reply: &str) -> io::Result<()> {
+		match self.client.as_mut() {
+			Some(client) if client.running => {
+				client.running = false;
+				client.send(reply)
+			},
+			_ => Ok(())
+		}
+	}
+}
+
+// Runs the machine for GDB without a frontend, until GDB detaches or kills it
+pub fn serve(machine: &mut CHIP8, stub: &mut GdbStub, debugger: &mut Debugger, policy: &ErrorPolicy) -> io::Result<()> {
+	loop {
+		match stub.poll(machine, debugger, policy)? {
+			GdbEvent::None => (),
+			GdbEvent::Detached | GdbEvent::Killed => return Ok(())
+		}
+
+		if debugger.is_paused() || machine.has_exited() {
+			thread::sleep(Duration::from_millis(1)); // waiting on GDB
+			continue;
+		}
+
+		match debugger.run_frame(machine, policy) {
+			Ok(Some(stop)) => stub.stopped(&stop)?,
+			Ok(None) => (),
+			Err(e) => {
+				debugger.pause();
+				stub.halted(&e)?;
+			}
+		}
+		if machine.has_exited() {
+			debugger.pause();
+			stub.exited()?;
+		}
+	}
+}
+
+impl Client {
+	fn poll(&mut self, machine: &mut CHIP8, debugger: &mut Debugger, policy: &ErrorPolicy) -> io::Result<GdbEvent> {
+		let mut chunk = [0; 1024];
+		loop {
+			match self.stream.read(&mut chunk) {
+				Ok(0) => return Ok(GdbEvent::Detached), // GDB went away
+				Ok(n) => self.buffer.extend_from_slice(&chunk[.. n]),
+				Err(e) if e.kind() == io::ErrorKind::WouldBlock => break,
+				Err(e) if e.kind() == io::ErrorKind::Interrupted => (),
+				Err(e) => return Err(e)
+			}
+		}
+
+		while let Some(packet) = self.next_packet()? {
+			let event = match packet {
+				Packet::Interrupt => {
+					debugger.pause();
+					if self.running {
+						self.running = false;
+						self.send("S02")?; // SIGINT
+					}
+					GdbEvent::None
+				},
+				Packet::Command(command) => self.handle(&command, machine, debugger, policy)?
+			};
+			if event != GdbEvent::None {
+				return Ok(event);
+			}
+		}
+		Ok(GdbEvent::None)
+	}
+
+	// Takes the next whole packet out of the buffer, acknowledging it. Acknowledgements from GDB are skipped,
+	// and so are packets with a bad checksum once they've been asked for again
+	fn next_packet(&mut self) -> io::Result<Option<Packet>> {
+		loop {
+			loop {
+				match self.buffer.first() {
+					None => return Ok(None),
+					Some(0x03) => {
+						self.buffer.remove(0);
+						return Ok(Some(Packet::Interrupt));
+					},
+					Some(b'$') => break,
+					Some(_) => { self.buffer.remove(0); } // + and -, or noise between packets
+				}
+			}
+
+			let end = match self.buffer.iter().position(|&b| b == b'#') {
+				Some(end) if self.buffer.len() >= end + 3 => end,
+				_ => return Ok(None) // the rest hasn't arrived yet
+			};
+
+			let packet: Vec<u8> = self.buffer.drain(.. end + 3).collect();
+			let data = &packet[1 .. end];
+			let checksum = std::str::from_utf8(&packet[end + 1 ..]).ok().and_then(|c| u8::from_str_radix(c, 16).ok());
+
+			if checksum == Some(checksum_of(data)) {
+				if !self.no_ack {
+					self.stream.write_all(b"+")?;
+				}
+				return Ok(Some(Packet::Command(String::from_utf8_lossy(data).into_owned())));
+			}
+			if !self.no_ack {
+				self.stream.write_all(b"-")?; // asks for it again
+			}
+		}
+	}
+
+	fn send(&mut self, data: &str) -> io::Result<()> {
+		let packet = format!("${}#{:02x}", data, checksum_of(data.as_bytes()));
+		self.stream.write_all(packet.as_bytes())
+	}
+
+	fn handle(&mut self, command: &str, machine: &mut CHIP8, debugger: &mut Debugger, policy: &ErrorPolicy) -> io::Result<GdbEvent> {
+		let (kind, args) = command.split_at(command.chars().next().map_or(0, char::len_utf8));
+
+		let reply = match kind {
+			"?" => "S05".to_string(),
+			"g" => registers(machine).iter().map(|r| hex(r)).collect(),
+			"G" => ok_or_error(set_registers(machine, args)),
+			"p" => match usize::from_str_radix(args, 16).ok().and_then(|n| registers(machine).get(n).cloned()) {
+				Some(register) => hex(&register),
+				None => "E01".to_string()
+			},
+			"P" => ok_or_error(args.split_once('=').and_then(|(n, value)| {
+				set_register(machine, usize::from_str_radix(n, 16).ok()?, &from_hex(value)?)
+			})),
+			"m" => match parse_memory_range(args).and_then(|(addr, len)| read_memory(machine, addr, len)) {
+				Some(bytes) => hex(&bytes),
+				None => "E01".to_string()
+			},
+			"M" => ok_or_error(args.split_once(':').and_then(|(range, data)| {
+				let (addr, len) = parse_memory_range(range)?;
+				let bytes = from_hex(data).filter(|b| b.len() == len)?;
+				write_memory(machine, addr, &bytes)
+			})),
+			"Z" | "z" => ok_or_error(set_breakpoint(debugger, kind == "Z", args)),
+			"s" => {
+				if !args.is_empty() { // resuming somewhere else
+					machine.pc = u16::from_str_radix(args, 16).unwrap_or(machine.pc);
+				}
+				match debugger.step(machine, policy) {
+					Ok(stop) => stop_reply(&stop),
+					Err(e) => halt_reply(&e)
+				}
+			},
+			"c" => {
+				if !args.is_empty() {
+					machine.pc = u16::from_str_radix(args, 16).unwrap_or(machine.pc);
+				}
+				debugger.resume();
+				self.running = true; // the reply is sent once the machine stops
+				return Ok(GdbEvent::None);
+			},
+			"D" => {
+				self.send("OK")?;
+				return Ok(GdbEvent::Detached);
+			},
+			"k" => return Ok(GdbEvent::Killed),
+			"H" => "OK".to_string(), // there's only one thread to pick
+			"q" | "Q" => query(command, self),
+			_ => String::new() // not supported
+		};
+
+		self.send(&reply)?;
+		Ok(GdbEvent::None)
+	}
+}
+
+enum Packet {
+	Interrupt, // GDB sent ^C
+	Command(String)
+}
+
+fn query(command: &str, client: &mut Client) -> String {
+	if command.starts_with("qSupported") {
+		return "PacketSize=1000;qXfer:features:read+;QStartNoAckMode+".to_string();
+	}
+	if command == "QStartNoAckMode" {
+		client.no_ack = true;
+		return "OK".to_string();
+	}
+	if let Some(range) = command.strip_prefix("qXfer:features:read:target.xml:") {
+		return match parse_memory_range(range) {
+			Some((offset, len)) => {
+				let offset = (offset as usize).min(TARGET_XML.len());
+				let end = match offset.checked_add(len) {
+					Some(end) => end.min(TARGET_XML.len()),
+					None => return "E01".to_string()
+				};
+				// m means there's more to read, l that this is the last of it
+				format!("{}{}", if end < TARGET_XML.len() { "m" } else { "l" }, &TARGET_XML[offset .. end])
+			},
+			None => "E01".to_string()
+		};
+	}
+
+	match command {
+		"qAttached" => "1".to_string(), // the machine was already running, detaching leaves it running
+		"qC" => "QC1".to_string(),
+		"qfThreadInfo" => "m1".to_string(),
+		"qsThreadInfo" => "l".to_string(),
+		_ => String::new()
+	}
+}
+
+// T05 with the kind of watchpoint and the address for watchpoints, S05 for everything else that stops
+fn stop_reply(stop: &Stop) -> String {
+	match stop {
+		Stop::Watchpoint(access) => {
+			let kind = match access.kind { AccessKind::Read => "rwatch", AccessKind::Write => "watch" };
+			format!("T05{}:{:x};", kind, access.start)
+		},
+		_ => "S05".to_string()
+	}
+}
+
+// The signal a real CPU would raise for the error: SIGILL for unknown opcodes, SIGSEGV for the rest
+fn halt_reply(error: &ExecError) -> String {
+	match error {
+		ExecError::UnknownOpcode { .. } => "S04".to_string(),
+		_ => "S0b".to_string()
+	}
+}
+
+fn ok_or_error(result: Option<()>) -> String {
+	match result {
+		Some(()) => "OK".to_string(),
+		None => "E01".to_string()
+	}
+}
+
+fn registers(machine: &CHIP8) -> Vec<Vec<u8>> {
+	let mut registers: Vec<Vec<u8>> = machine.V.iter().map(|&v| vec![v]).collect();
+	registers.push(machine.I.to_le_bytes().to_vec());
+	registers.push(machine.pc.to_le_bytes().to_vec());
+	registers.push(machine.sp.to_le_bytes().to_vec());
+	registers.push(vec![machine.delay_timer]);
+	registers.push(vec![machine.sound_timer]);
+	registers
+}
+
+fn set_registers(machine: &mut CHIP8, data: &str) -> Option<()> {
+	let bytes = from_hex(data)?;
+	let sizes = registers(machine).iter().map(Vec::len).collect::<Vec<_>>();
+	if bytes.len() != sizes.iter().sum::<usize>() {
+		return None;
+	}
+
+	let mut offset = 0;
+	for (n, size) in sizes.into_iter().enumerate() {
+		set_register(machine, n, &bytes[offset .. offset + size])?;
+		offset += size;
+	}
+	Some(())
+}
+
+fn set_register(machine: &mut CHIP8, n: usize, value: &[u8]) -> Option<()> {
+	let word = || if value.len() == 2 { Some(u16::from_le_bytes([value[0], value[1]])) } else { None };
+	let byte = || if value.len() == 1 { Some(value[0]) } else { None };
+
+	match n {
+		0 ..= 15 => machine.V[n] = byte()?,
+		REG_I => machine.I = word()?,
+		REG_PC => machine.pc = word()?,
+		REG_SP => machine.sp = word().filter(|&sp| (sp as usize) <= machine.stack.len())?,
+		REG_DELAY => machine.delay_timer = byte()?,
+		REG_SOUND => machine.sound_timer = byte()?,
+		_ => return None
+	}
+	Some(())
+}
+
+fn read_memory(machine: &CHIP8, addr: u16, len: usize) -> Option<Vec<u8>> {
+	let start = addr as usize;
+	machine.memory.get(start ..)?.get(.. len).map(<[u8]>::to_vec)
+}
+
+fn write_memory(machine: &mut CHIP8, addr: u16, bytes: &[u8]) -> Option<()> {
+	let start = addr as usize;
+	machine.memory.get_mut(start ..)?.get_mut(.. bytes.len())?.copy_from_slice(bytes);
+	machine.invalidate_decoded(start, bytes.len());
+	Some(())
+}
+
+// Z0 and Z1 are breakpoints, Z2, Z3 and Z4 watch writes, reads and both. The kind at the end is ignored
+fn set_breakpoint(debugger: &mut Debugger, insert: bool, args: &str) -> Option<()> {
+	let mut parts = args.split(',');
+	let kind = parts.next()?;
+	let addr = u16::from_str_radix(parts.next()?, 16).ok()?;
+	let len = parts.next().and_then(|l| u16::from_str_radix(l, 16).ok()).unwrap_or(1).max(1);
+
+	let watch = match kind {
+		"0" | "1" => {
+			if insert {
+				debugger.add_breakpoint(addr);
+			} else {
+				debugger.remove_breakpoint(addr);
+			}
+			return Some(());
+		},
+		"2" => WatchKind::Write,
+		"3" => WatchKind::Read,
+		"4" => WatchKind::ReadWrite,
+		_ => return None
+	};
+
+	let watchpoint = Watchpoint::new(addr, addr.saturating_add(len - 1), watch);
+	if insert {
+		debugger.add_watchpoint(watchpoint);
+	} else {
+		debugger.remove_watchpoint(&watchpoint);
+	}
+	Some(())
+}
+
+// addr,len in hex, as m and M packets have them
+fn parse_memory_range(text: &str) -> Option<(u16, usize)> {
+	let (addr, len) = text.split_once(',')?;
+	Some((u16::from_str_radix(addr, 16).ok()?, usize::from_str_radix(len, 16).ok()?))
+}
+
+fn checksum_of(data: &[u8]) -> u8 {
+	data.iter().fold(0u8, |sum, &b| sum.wrapping_add(b))
+}
+
+fn hex(bytes: &[u8]) -> String {
+	bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn from_hex(text: &str) -> Option<Vec<u8>> {
+	if !text.len().is_multiple_of(2) {
+		return None;
+	}
+	(0 .. text.len()).step_by(2).map(|i| u8::from_str_radix(text.get(i .. i + 2)?, 16).ok()).collect()
+}
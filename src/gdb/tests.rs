@@ -0,0 +1,156 @@
+use super::{ GdbStub, serve, checksum_of, from_hex, registers, set_register, stop_reply, halt_reply, REG_PC, REG_SP };
+use crate::{ CHIP8, ErrorPolicy, ExecError, MemoryAccess, AccessKind, asm::assemble, debugger::{ Debugger, Stop } };
+
+use std::{
+	io::{ Read, Write },
+	net::TcpStream,
+	thread,
+	time::Duration
+};
+
+// GDB's side of the connection
+struct Gdb {
+	stream: TcpStream
+}
+
+impl Gdb {
+	fn connect(port: u16) -> Gdb {
+		let stream = TcpStream::connect(("127.0.0.1", port)).unwrap();
+		stream.set_read_timeout(Some(Duration::from_secs(5))).unwrap();
+		Gdb { stream }
+	}
+
+	fn send(&mut self, data: &str) {
+		let packet = format!("${}#{:02x}", data, checksum_of(data.as_bytes()));
+		self.stream.write_all(packet.as_bytes()).unwrap();
+	}
+
+	// The next packet's data, skipping acknowledgements
+	fn reply(&mut self) -> String {
+		let mut packet = Vec::new();
+		let mut byte = [0];
+		loop {
+			self.stream.read_exact(&mut byte).unwrap();
+			match byte[0] {
+				b'+' if packet.is_empty() => continue,
+				b'#' => break,
+				b => packet.push(b)
+			}
+		}
+		let mut checksum = [0; 2];
+		self.stream.read_exact(&mut checksum).unwrap();
+
+		let data = String::from_utf8(packet).unwrap().trim_start_matches('$').to_string();
+		assert_eq!(u8::from_str_radix(std::str::from_utf8(&checksum).unwrap(), 16), Ok(checksum_of(data.as_bytes())));
+		data
+	}
+
+	fn command(&mut self, data: &str) -> String {
+		self.send(data);
+		self.reply()
+	}
+}
+
+#[test]
+fn registers_are_little_endian_and_settable() {
+	let mut machine = CHIP8::new(&[]).unwrap();
+	machine.V[0xF] = 0xAB;
+	machine.pc = 0x2F4;
+	machine.sound_timer = 7;
+
+	let registers = registers(&machine);
+	assert_eq!(registers.len(), 21);
+	assert_eq!(registers[0xF], vec![0xAB]);
+	assert_eq!(registers[REG_PC], vec![0xF4, 0x02]);
+	assert_eq!(registers[20], vec![7]);
+
+	assert_eq!(set_register(&mut machine, REG_PC, &[0x10, 0x03]), Some(()));
+	assert_eq!(machine.pc, 0x310);
+	assert_eq!(set_register(&mut machine, 0, &[1, 2]), None);
+	assert_eq!(set_register(&mut machine, REG_SP, &[0xFF, 0]), None); // deeper than the stack
+	assert_eq!(set_register(&mut machine, 21, &[0]), None);
+}
+
+#[test]
+fn packet_helpers() {
+	assert_eq!(checksum_of(b"OK"), 0x9A);
+	assert_eq!(from_hex("00ff1A"), Some(vec![0x00, 0xFF, 0x1A]));
+	assert_eq!(from_hex("abc"), None);
+	assert_eq!(from_hex("zz"), None);
+
+	let write = MemoryAccess { kind: AccessKind::Write, start: 0x300, len: 3 };
+	assert_eq!(stop_reply(&Stop::Watchpoint(write)), "T05watch:300;");
+	assert_eq!(stop_reply(&Stop::Breakpoint(0x200)), "S05");
+	assert_eq!(halt_reply(&ExecError::UnknownOpcode { opcode: 0xFFFF, pc: 0x200 }), "S04");
+}
+
+#[test]
+fn loopback_session() {
+	// 200: v0 += 1, 202: v1 := 5, 204: i := 0x300, 206: save v1, 208: jump 208
+	let rom = assemble("
+	: main
+		v0 += 1
+		v1 := 5
+		i := 0x300
+		save v1
+	: stop
+		jump stop
+	").unwrap().rom;
+
+	let mut stub = GdbStub::listen("127.0.0.1:0").unwrap();
+	let port = stub.local_addr().unwrap().port();
+
+	let server = thread::spawn(move || {
+		let mut machine = CHIP8::new(&[]).unwrap();
+		let mut debugger = Debugger::new();
+		serve(&mut machine, &mut stub, &mut debugger, &ErrorPolicy::default()).unwrap();
+		machine
+	});
+
+	let mut gdb = Gdb::connect(port);
+	assert!(gdb.command("qSupported:multiprocess+").contains("qXfer:features:read+"));
+	assert!(gdb.command("qXfer:features:read:target.xml:0,1000").starts_with("l<?xml"));
+	assert_eq!(gdb.command("?"), "S05");
+
+	// load the program through memory writes, then read it back
+	let data: String = rom.iter().map(|b| format!("{:02x}", b)).collect();
+	assert_eq!(gdb.command(&format!("M200,{:x}:{}", rom.len(), data)), "OK");
+	assert_eq!(gdb.command("m200,4"), data[.. 8]);
+	assert_eq!(gdb.command("mfff,2"), "E01");
+	assert_eq!(gdb.command("m1,ffffffffffffffff"), "E01"); // lengths that don't fit are errors, not crashes
+	assert_eq!(gdb.command("M1,ffffffffffffffff:00"), "E01");
+	assert_eq!(gdb.command("qXfer:features:read:target.xml:1,ffffffffffffffff"), "E01");
+
+	// a breakpoint, then continue until it's hit
+	assert_eq!(gdb.command("Z0,204,2"), "OK");
+	gdb.send("c");
+	assert_eq!(gdb.reply(), "S05");
+	assert_eq!(gdb.command(&format!("p{:x}", REG_PC)), "0402");
+	assert_eq!(gdb.command("p1"), "05");
+	assert_eq!(&gdb.command("g")[.. 4], "0105");
+
+	// step, then write a register
+	assert_eq!(gdb.command("s"), "S05");
+	assert_eq!(gdb.command("p10"), "0003");
+	assert_eq!(gdb.command("P1=2a"), "OK");
+
+	// a write watchpoint stops right after the save
+	assert_eq!(gdb.command("Z2,301,1"), "OK");
+	gdb.send("c");
+	assert_eq!(gdb.reply(), "T05watch:300;");
+	assert_eq!(gdb.command("m300,2"), "012a");
+
+	// packets with bad checksums are asked for again, however many arrive at once
+	gdb.stream.write_all("$m300,2#00".repeat(10_000).as_bytes()).unwrap();
+	let mut naks = vec![0; 10_000];
+	gdb.stream.read_exact(&mut naks).unwrap();
+	assert!(naks.iter().all(|&b| b == b'-'));
+	assert_eq!(gdb.command("m300,2"), "012a");
+
+	assert_eq!(gdb.command("z0,204,2"), "OK");
+	assert_eq!(gdb.command("z2,301,1"), "OK");
+	assert_eq!(gdb.command("D"), "OK");
+
+	let machine = server.join().unwrap();
+	assert_eq!((machine.V[0], machine.V[1], machine.pc), (1, 0x2A, 0x208));
+}
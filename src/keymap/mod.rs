@@ -0,0 +1,213 @@
+use crate::Chip8Key;
+
+use std::{
+	collections::HashMap,
+	error::Error,
+	fmt, fs, io,
+	path::Path
+};
+
+#[cfg(test)]
+mod tests;
+
+// Keymap files bind each CHIP-8 key to one or more keys of the host's keyboard, one CHIP-8 key per line:
+//
+//     # the COSMAC VIP's keypad on the left of a QWERTY keyboard
+//     1 = Key1
+//     4 = Q, Up
+//     6 = E, scancode:0x12
+//
+// Host keys are named like glutin's virtual keycodes (Key1, Q, Up, Space, Numpad4...) or given as
+// scancodes. CHIP-8 keys the file doesn't mention keep their default bindings. Lines after a
+// [name.ch8] header only apply while that ROM is running, and replace the bindings above them for
+// the CHIP-8 keys they mention
+pub const DEFAULT_KEYMAP: &str = "\
+1 = Key1
+2 = Key2
+3 = Key3
+C = Key4
+4 = Q
+5 = W
+6 = E
+D = R
+7 = A
+8 = S
+9 = D
+E = F
+A = Z
+0 = X
+B = C
+F = V
+";
+
+// A key on the host's keyboard
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub enum HostKey {
+	Named(String), // a virtual keycode, the key with this label in the current layout
+	Scancode(u32) // a physical key, wherever the layout puts its label. The numbers differ between platforms
+}
+
+impl fmt::Display for HostKey {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			HostKey::Named(name) => write!(f, "{}", name),
+			HostKey::Scancode(code) => write!(f, "scancode:0x{:02X}", code)
+		}
+	}
+}
+
+#[derive(Debug)]
+pub enum KeymapError {
+	Io(io::Error),
+	Syntax { line: usize, reason: String } // line numbers start at 1
+}
+
+impl fmt::Display for KeymapError {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self {
+			KeymapError::Io(e) => write!(f, "couldn't read the keymap: {}", e),
+			KeymapError::Syntax { line, reason } => write!(f, "keymap line {}: {}", line, reason)
+		}
+	}
+}
+
+impl Error for KeymapError {}
+
+impl From<io::Error> for KeymapError {
+	fn from(e: io::Error) -> KeymapError {
+		KeymapError::Io(e)
+	}
+}
+
+// The host keys bound to each CHIP-8 key. None leaves a key's binding to the level below
+type Bindings = [Option<Vec<HostKey>>; 16];
+
+#[derive(Clone, Debug)]
+pub struct Keymap {
+	bindings: [Vec<HostKey>; 16], // indexed by the CHIP-8 key
+	roms: HashMap<String, Bindings> // overrides for programs, by their file name
+}
+
+impl Default for Keymap {
+	fn default() -> Keymap {
+		Keymap::empty().parse_onto(DEFAULT_KEYMAP).expect("the default keymap is valid")
+	}
+}
+
+impl Keymap {
+	// A keymap with no keys bound at all
+	pub fn empty() -> Keymap {
+		Keymap { bindings: Default::default(), roms: HashMap::new() }
+	}
+
+	// Reads a keymap file, on top of the default bindings
+	pub fn load(path: &Path) -> Result<Keymap, KeymapError> {
+		Keymap::parse(&fs::read_to_string(path)?)
+	}
+
+	// Parses a keymap file's contents, on top of the default bindings
+	pub fn parse(text: &str) -> Result<Keymap, KeymapError> {
+		Keymap::default().parse_onto(text)
+	}
+
+	fn parse_onto(mut self, text: &str) -> Result<Keymap, KeymapError> {
+		let mut section: Option<String> = None; // the ROM the lines are for, None for every ROM
+
+		for (number, line) in text.lines().enumerate() {
+			let line = line.split('#').next().unwrap().trim();
+			if line.is_empty() { continue; }
+
+			let syntax_error = |reason: String| KeymapError::Syntax { line: number + 1, reason };
+
+			if line.starts_with('[') {
+				let name = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')).map(str::trim)
+							.filter(|n| !n.is_empty())
+							.ok_or_else(|| syntax_error(format!("bad ROM header {}", line)))?;
+				section = Some(name.to_string());
+				self.roms.entry(name.to_string()).or_default();
+				continue;
+			}
+
+			let (key, host_keys) = parse_binding(line).map_err(syntax_error)?;
+			match &section {
+				Some(rom) => self.roms.get_mut(rom).unwrap()[key.index() as usize] = Some(host_keys),
+				None => self.bindings[key.index() as usize] = host_keys
+			}
+		}
+
+		Ok(self)
+	}
+
+	// The keymap with the overrides for a ROM applied
+	pub fn for_rom(&self, rom_name: &str) -> Keymap {
+		let mut keymap = Keymap { bindings: self.bindings.clone(), roms: HashMap::new() };
+
+		if let Some(overrides) = self.roms.get(rom_name) {
+			for (binding, host_keys) in keymap.bindings.iter_mut().zip(overrides.iter()) {
+				if let Some(keys) = host_keys {
+					*binding = keys.clone();
+				}
+			}
+		}
+
+		keymap
+	}
+
+	// Adds a host key to the ones bound to a CHIP-8 key
+	pub fn bind(&mut self, key: Chip8Key, host_key: HostKey) {
+		let keys = &mut self.bindings[key.index() as usize];
+		if !keys.contains(&host_key) {
+			keys.push(host_key);
+		}
+	}
+
+	pub fn host_keys(&self, key: Chip8Key) -> &[HostKey] {
+		&self.bindings[key.index() as usize]
+	}
+
+	// The CHIP-8 keys bound to a host key, found by its virtual keycode's name or its scancode
+	pub fn chip8_keys(&self, name: Option<&str>, scancode: u32) -> Vec<Chip8Key> {
+		Chip8Key::all().iter().copied()
+			.filter(|&key| self.host_keys(key).iter().any(|host_key| match host_key {
+				HostKey::Named(n) => Some(n.as_str()) == name,
+				HostKey::Scancode(s) => *s == scancode
+			}))
+			.collect()
+	}
+}
+
+// Parses "<hex digit> = <host key>, <host key>..."
+fn parse_binding(line: &str) -> Result<(Chip8Key, Vec<HostKey>), String> {
+	let mut parts = line.splitn(2, '=');
+	let key_part = parts.next().unwrap().trim();
+	let keys_part = parts.next().ok_or_else(|| format!("expected <CHIP-8 key> = <host keys>, found {}", line))?;
+
+	let mut digits = key_part.chars();
+	let key = match (digits.next(), digits.next()) {
+		(Some(digit), None) => Chip8Key::from_digit(digit),
+		_ => None
+	}.ok_or_else(|| format!("{} isn't a CHIP-8 key, they go from 0 to F", key_part))?;
+
+	let host_keys = keys_part.split(',')
+						.map(str::trim)
+						.filter(|k| !k.is_empty())
+						.map(parse_host_key)
+						.collect::<Result<Vec<_>, _>>()?;
+
+	Ok((key, host_keys))
+}
+
+fn parse_host_key(text: &str) -> Result<HostKey, String> {
+	match text.strip_prefix("scancode:") {
+		Some(code) => {
+			let code = code.trim();
+			let parsed = match code.strip_prefix("0x").or_else(|| code.strip_prefix("0X")) {
+				Some(hex) => u32::from_str_radix(hex, 16),
+				None => code.parse()
+			};
+			parsed.map(HostKey::Scancode).map_err(|_| format!("bad scancode {}", code))
+		},
+		None if text.chars().all(|c| c.is_ascii_alphanumeric()) => Ok(HostKey::Named(text.to_string())),
+		None => Err(format!("bad key name {}", text))
+	}
+}
@@ -0,0 +1,58 @@
+use super::{ Keymap, KeymapError, HostKey };
+use crate::Chip8Key;
+
+fn named(name: &str) -> HostKey {
+	HostKey::Named(name.to_string())
+}
+
+#[test]
+fn default_layout() {
+	let keymap = Keymap::default();
+
+	assert_eq!(keymap.host_keys(Chip8Key::K1), &[named("Key1")]);
+	assert_eq!(keymap.host_keys(Chip8Key::KB), &[named("C")]);
+	assert_eq!(keymap.host_keys(Chip8Key::KC), &[named("Key4")]);
+	assert_eq!(keymap.chip8_keys(Some("X"), 0), vec![Chip8Key::K0]);
+	assert_eq!(keymap.chip8_keys(Some("Escape"), 0), vec![]);
+}
+
+#[test]
+fn parse_multiple_keys_and_scancodes() {
+	let keymap = Keymap::parse("# arrows as well\n5 = W, Up   # up\n8 = S, scancode:0x50\n7 = scancode:75\n").unwrap();
+
+	assert_eq!(keymap.host_keys(Chip8Key::K5), &[named("W"), named("Up")]);
+	assert_eq!(keymap.host_keys(Chip8Key::K8), &[named("S"), HostKey::Scancode(0x50)]);
+	assert_eq!(keymap.host_keys(Chip8Key::K7), &[HostKey::Scancode(75)]);
+	assert_eq!(keymap.host_keys(Chip8Key::K1), &[named("Key1")]); // not in the file, so still the default
+
+	assert_eq!(keymap.chip8_keys(Some("Up"), 0x48), vec![Chip8Key::K5]);
+	assert_eq!(keymap.chip8_keys(None, 0x50), vec![Chip8Key::K8]);
+}
+
+#[test]
+fn rom_overrides() {
+	let keymap = Keymap::parse("5 = W, Up\n\n[pong.ch8]\n1 = Q\n5 = Space\n").unwrap();
+
+	let pong = keymap.for_rom("pong.ch8");
+	assert_eq!(pong.host_keys(Chip8Key::K1), &[named("Q")]);
+	assert_eq!(pong.host_keys(Chip8Key::K5), &[named("Space")]);
+	assert_eq!(pong.host_keys(Chip8Key::K2), &[named("Key2")]);
+	assert_eq!(pong.chip8_keys(Some("Q"), 0), vec![Chip8Key::K1, Chip8Key::K4]);
+
+	let other = keymap.for_rom("tetris.ch8");
+	assert_eq!(other.host_keys(Chip8Key::K5), &[named("W"), named("Up")]);
+}
+
+#[test]
+fn syntax_errors() {
+	let line_of = |text: &str| match Keymap::parse(text) {
+		Err(KeymapError::Syntax { line, .. }) => line,
+		other => panic!("expected a syntax error, got {:?}", other)
+	};
+
+	assert_eq!(line_of("1 = Key1\nG = Q\n"), 2);
+	assert_eq!(line_of("\n\n1 Key1\n"), 3);
+	assert_eq!(line_of("1 = Key1\n[pong.ch8\n"), 2);
+	assert_eq!(line_of("1 = scancode:0xZZ\n"), 1);
+	assert_eq!(line_of("1 = Left Shift\n"), 1);
+}
@@ -1,7 +1,49 @@
 #[allow(non_snake_case)]
 pub mod CPU;
 
+use std::path::PathBuf;
+
+pub use CPU::{
+	quirks::{ Quirks, IndexIncrement },
+	platform::Platform,
+	error::{ ExecError, StepOutcome, ErrorPolicy, ErrorAction },
+	state::StateError,
+	keypad::Chip8Key,
+	instruction::{ Instruction, decode },
+	access::{ MemoryAccess, AccessKind }
+};
+pub use sound::{ AudioConfig, Waveform };
+pub use rewind::RewindBuffer;
+pub use trace::Tracer;
+pub use record::{ Recorder, RecordFormat };
+pub use palette::Palette;
+pub use filter::{ Filter, ScreenFilter };
+pub use database::{ RomDatabase, RomInfo, DatabaseError };
+pub use keymap::{ Keymap, KeymapError, HostKey };
+pub use frontend::{ Host, Screen, SoundState, HostEvent, headless::HeadlessHost, terminal::{ TerminalHost, TextMode } };
+pub use debugger::{ Debugger, Registers, Watchpoint, WatchKind, Condition };
+#[cfg(feature = "window")]
+pub use frontend::window::WindowHost;
+
+#[cfg(feature = "window")]
 mod graphics;
+mod sound;
+mod timing;
+pub mod rewind;
+pub mod frontend;
+pub mod keymap;
+pub mod disasm;
+pub mod asm;
+pub mod debugger;
+pub mod gdb;
+pub mod trace;
+pub mod database;
+pub mod cli;
+pub mod record;
+pub mod palette;
+pub mod filter;
+#[cfg(feature = "image")]
+pub mod screenshot;
 
 static FONTSET: [u8; 80] = [ 0xF0, 0x90, 0x90, 0x90, 0xF0,	 // 0
 							 0x20, 0x60, 0x20, 0x20, 0x70,	 // 1
@@ -20,165 +62,122 @@ static FONTSET: [u8; 80] = [ 0xF0, 0x90, 0x90, 0x90, 0xF0,	 // 0
 							 0xF0, 0x80, 0xF0, 0x80, 0xF0,	 // E
 							 0xF0, 0x80, 0xF0, 0x80, 0x80 ]; // F
 
+// SUPER-CHIP's 8x10 font for the high resolution mode, loaded right after FONTSET
+static BIG_FONTSET: [u8; 160] = [ 0xFF, 0xFF, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF,	 // 0
+								  0x18, 0x78, 0x78, 0x18, 0x18, 0x18, 0x18, 0x18, 0xFF, 0xFF,	 // 1
+								  0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF,	 // 2
+								  0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF,	 // 3
+								  0xC3, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0x03, 0x03,	 // 4
+								  0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF,	 // 5
+								  0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF,	 // 6
+								  0xFF, 0xFF, 0x03, 0x03, 0x06, 0x0C, 0x18, 0x18, 0x18, 0x18,	 // 7
+								  0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF,	 // 8
+								  0xFF, 0xFF, 0xC3, 0xC3, 0xFF, 0xFF, 0x03, 0x03, 0xFF, 0xFF,	 // 9
+								  0x7E, 0xFF, 0xC3, 0xC3, 0xC3, 0xFF, 0xFF, 0xC3, 0xC3, 0xC3,	 // A
+								  0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC, 0xC3, 0xC3, 0xFC, 0xFC,	 // B
+								  0x3C, 0xFF, 0xC3, 0xC0, 0xC0, 0xC0, 0xC0, 0xC3, 0xFF, 0x3C,	 // C
+								  0xFC, 0xFE, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xC3, 0xFE, 0xFC,	 // D
+								  0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF,	 // E
+								  0xFF, 0xFF, 0xC0, 0xC0, 0xFF, 0xFF, 0xC0, 0xC0, 0xC0, 0xC0 ]; // F
+const BIG_FONT_START: u16 = 0x50;
+
 const WINDOW_W: u8 = 64;
 const WINDOW_H: u8 = 32;
-const VIRTUAL_WW: u16 = 512;
-const VIRTUAL_WH: u16 = 256;
+const HIRES_W: u8 = 128; // SUPER-CHIP's high resolution mode
+const HIRES_H: u8 = 64;
+const DEFAULT_SCALE: u32 = 8; // a 512x256 window
 
 // define the pieces of the cpu
 #[allow(non_snake_case)]
 pub struct CHIP8 {
 	opcode: u16, // current opcode
-	memory: [u8; 4096], // ram
+	memory: Vec<u8>, // ram, 4 KiB or 64 KiB depending on the platform
 	V: [u8; 16], // cpu registers
 	I: u16, // index register
 	pc: u16, // program counter
-	gfx: [u8; WINDOW_W as usize * WINDOW_H as usize], // visual ram
+	gfx: [u8; HIRES_W as usize * HIRES_H as usize], // visual ram, big enough for the high resolution mode
+	hires: bool, // whether the screen is in the 128x64 mode. only the first width * height pixels of gfx are used
+	plane: u8, // XO-CHIP's selected bitplanes. every pixel in gfx holds one bit per plane
+	exited: bool, // set by 00FD, the program has finished
 	draw_flag: bool, // since the cpu doesn't draw each frame we set a flag for when it should
-	sound_flag: bool, // the same as above but for sound
 	delay_timer: u8,
 	sound_timer: u8,
+	pattern: [u8; 16], // XO-CHIP's audio pattern, 128 1-bit samples played while the sound timer is active
+	pitch: u8, // XO-CHIP's pitch register, sets the rate the pattern is played at
 	stack: [u16; 16],
 	sp: u16, // stack pointer
-	key: [u8; 16]
+	key: [u8; 16],
+	rpl: [u8; 16], // SUPER-CHIP's RPL user flags
+	quirks: Quirks, // which interpretation of the ambiguous instructions to use
+	platform: Platform,
+	instructions_per_frame: u32, // how many instructions run between each tick of the timers
+	frame_count: u64,
+	rom_hash: u64, // identifies the loaded program in save states
+	rom_sha1: [u8; 20], // identifies the loaded program in the ROM database
+	decoded: Vec<Option<Instruction>>, // the instruction decoded at each address, cleared when the memory under it is written
+	last_access: Option<MemoryAccess>, // the memory the last instruction read or wrote, for watchpoints
+	tracer: Option<Tracer> // logs each instruction as it runs
 }
 
-use crow::{
-	glutin::{
-		event::{ Event, WindowEvent, VirtualKeyCode },
-		event_loop::ControlFlow,
-		window::WindowBuilder,
-		dpi::LogicalSize
-	},
-	DrawConfig, Texture
-};
-
-use crate::graphics::GraphicalContext;
-
-use rodio::{ OutputStream, Sink };
-
-use std::{
-	time::{Duration, Instant},
-	path::Path
-};
-
-pub fn run(mut machine: CHIP8, program_path: &String) {
-	// load sound data
-	let (_stream, stream_handle) = OutputStream::try_default().unwrap();
-	let sink = Sink::try_new(&stream_handle).unwrap();
-
-	let beep_sound = load_sound_file("beep.ogg").unwrap();
-
-	let program_name = Path::new(&program_path).file_name().unwrap()
-						.to_str().unwrap();
-
-	let window_bld = WindowBuilder::new()
-			.with_title(format!("CHIP-8 {}", program_name))
-			.with_inner_size(LogicalSize::new(VIRTUAL_WW, VIRTUAL_WH))
-			.with_resizable(false);
-
-	// create a graphical context and take the texture and context out of it through destructuring. TODO: this won't be required with rust version 2021
-	let gc = GraphicalContext::new(window_bld).unwrap();
-	let GraphicalContext { ctx: mut context, txt: mut screen_texture, .. } = gc;
-	
-	// create a draw config for setting window scale
-	let drw_cfg = DrawConfig {
-		scale: (8, 8),
-		.. Default::default()
-	};
-
-	gc.el.run(move |event, _, control_flow| {
-		let start_time = Instant::now();
-
-		match event {
-			Event::WindowEvent { event, .. } => match event {
-				WindowEvent::CloseRequested => { *control_flow = ControlFlow::Exit; },
-				WindowEvent::KeyboardInput { input, .. } => {
-					match input.virtual_keycode {
-						Some(kc) => match kc {
-							VirtualKeyCode::Escape => *control_flow = ControlFlow::Exit, // quit when pressing escape
-							_ => ()
-						},
-						_ => ()
-					}
-					machine.handle_input(input)
-				},
-
-				_ => ()
-			},
-
-			Event::MainEventsCleared => {
-				machine.emulate_cycle();
-				if machine.draw_flag {
-					let screen_image = machine.create_screen_image();
-					screen_texture = Some(Texture::from_image(&mut context, screen_image).unwrap());
-					context.window().request_redraw();
-				}
-				if machine.sound_flag {
-					sink.append(beep_sound.clone());
-					machine.sound_flag = false;
-				}
-			},
-
-			Event::RedrawRequested(..) => {
-				match &screen_texture {
-					Some(txt) => {
-						let mut surface = context.surface();
-
-						context.draw(&mut surface, &txt, (0, 0), &drw_cfg);
-
-						context.present(surface).unwrap(); // swap back-buffer
-					},
-					None => ()
-				}
-
-			},
+// Settings for the window, sound and everything else around the emulated machine
+#[derive(Clone, Debug)]
+pub struct Config {
+	pub audio: AudioConfig,
+	pub errors: ErrorPolicy, // what to do when the program runs an instruction that can't be executed
+	pub rewind_frames: usize, // how many frames back the rewind key can go. 0 turns rewinding off
+	pub keymap: Keymap,
+	pub debug: bool, // run under the debugger, so the program can be paused, stepped and stopped at breakpoints
+	pub breakpoints: Vec<u16>,
+	pub watchpoints: Vec<Watchpoint>,
+	pub conditions: Vec<Condition>,
+	pub gdb_port: Option<u16>, // wait for GDB on this local port and let it drive the machine
+	pub title: Option<String>, // the program's name, shown instead of its file name
+	pub palette: Palette, // the colours the screen is drawn in
+	pub filter: Filter, // blends the last frames together so sprites redrawn with XOR don't flicker
+	pub scale: u32, // how many pixels of the window each CHIP-8 pixel takes up, in the 64x32 mode
+	pub screenshot_scale: u32, // how many pixels of a screenshot or recording each CHIP-8 pixel takes up
+	pub record: Option<PathBuf>, // record from the start to a .gif, .y4m or .ppm file
+	pub terminal: Option<TextMode> // draw in the terminal instead of opening a window
+}
 
-			_ => ()
+impl Default for Config {
+	fn default() -> Config {
+		Config {
+			audio: AudioConfig::default(),
+			errors: ErrorPolicy::default(),
+			rewind_frames: rewind::DEFAULT_REWIND_FRAMES,
+			keymap: Keymap::default(),
+			debug: false,
+			breakpoints: Vec::new(),
+			watchpoints: Vec::new(),
+			conditions: Vec::new(),
+			gdb_port: None,
+			title: None,
+			palette: Palette::CLASSIC,
+			filter: Filter::Off,
+			scale: DEFAULT_SCALE,
+			screenshot_scale: DEFAULT_SCALE,
+			record: None,
+			terminal: None
 		}
-		
-		// ugly solution but it works
-		let end_time = Instant::now();
-		let time_elapsed = end_time - start_time;
-		if time_elapsed < Duration::from_millis(1) && *control_flow != ControlFlow::Exit {
-			let next_frame_time = Duration::from_millis(1) - time_elapsed;
-			*control_flow = ControlFlow::WaitUntil(end_time + next_frame_time);
-		}
-	});
+	}
 }
 
-use rodio::{
-	Decoder, Source,
-	source::Buffered
-};
-use std::{
-	fs::File,
-	io::{ self, Read },
-	error::Error
-};
-
-// Read a file in binary mode and store it in a vector buffer
-fn load_binary_file(path: &str) -> io::Result<Vec<u8>> {
-	let mut p = File::open(path)?;
-	let mut buf = Vec::new();
+// Opens a window and runs the machine in it until it's closed
+#[cfg(feature = "window")]
+pub fn run(mut machine: CHIP8, program_path: &str, config: &Config) -> Result<(), Box<dyn std::error::Error>> {
+	let mut host = WindowHost::new(machine.platform(), program_path, config)?;
 
-	p.read_to_end(&mut buf)?;
-	Ok(buf)
+	// an error that halted the machine was already reported when it happened
+	frontend::run_host(&mut machine, &mut host, config, Some(program_path)).ok();
+	Ok(())
 }
 
-// Loads a sound file as a Buffered struct. Thanks to https://stackoverflow.com/a/61547339/9353072
-fn load_sound_file(path: &str) -> Result<Buffered<Decoder<File>> , Box<dyn Error>> {
-	// load a sound from a file
-	let file = File::open(path)?;
-
-	// decode that file into a source
-	let source = Decoder::new(file)?;
-
-	// store the decoded audio in a buffer
-	let result = source.buffered();
-
-	// as the buffer is lazily initiated we force the decoding early
-	result.clone().for_each(|_| {});
+// Runs the machine in the terminal it was started from until escape is pressed
+pub fn run_in_terminal(mut machine: CHIP8, program_path: &str, config: &Config) -> std::io::Result<()> {
+	let mode = config.terminal.unwrap_or(TextMode::HalfBlocks);
+	let mut host = TerminalHost::new(mode, program_path, config)?;
 
-	Ok(result)
+	frontend::run_host(&mut machine, &mut host, config, Some(program_path)).ok();
+	Ok(())
 }
@@ -1,19 +1,297 @@
-use chip8::CHIP8;
+use chip8::{
+	CHIP8, Config, Platform, Keymap, Tracer, RomDatabase, HeadlessHost, asm,
+	cli::{ self, Command, RunOptions, Settings },
+	disasm::{ self, Syntax }
+};
 
 use std::env;
+use std::fs;
+use std::path::Path;
 use std::process;
 
+const KEYMAP_FILE: &str = "chip8.keymap";
+const DATABASE_FILE: &str = "chip8-database.json";
+
 fn main() {
-	let args: Vec<_> = env::args().collect();
+	let args: Vec<String> = env::args().skip(1).collect();
+
+	let command = cli::parse(&args).unwrap_or_else(|e| {
+		eprintln!("Error: {}\n\n{}", e, cli::USAGE);
+		process::exit(2);
+	});
+
+	match command {
+		Command::Run(options) => run(&options),
+		Command::Disasm { rom, syntax } => disassemble(&rom, syntax),
+		Command::Asm { source, output } => assemble(&source, output),
+		Command::Info { rom } => info(&rom),
+		Command::Test { rom, frames, screenshot, scale } => test(&rom, frames, screenshot.as_deref(), scale),
+		Command::Help => print!("{}", cli::USAGE)
+	}
+}
+
+// Creates the machine for a ROM, on the platform its extension says, or plain CHIP-8
+fn open(rom_path: &str) -> CHIP8 {
+	let platform = Path::new(rom_path).extension().and_then(|e| Platform::from_extension(&e.to_string_lossy()));
+
+	let mut machine = CHIP8::open(rom_path, platform.unwrap_or(Platform::Chip8)).unwrap_or_else(|e| {
+		eprintln!("Error loading {}: {}", rom_path, e);
+		process::exit(1);
+	});
+
+	// the extended platforms are newer and their programs agree on the quirks they expect
+	if let Some(p) = platform.filter(|&p| p != Platform::Chip8) {
+		machine.set_quirks(p.quirks());
+	}
+	machine
+}
+
+// Programs the ROM database knows get the platform, quirks, speed, colours and keys they were made for.
+// chip8-database.json, in the directory the emulator is started from, adds to the bundled database and
+// takes priority over it
+fn database() -> RomDatabase {
+	RomDatabase::with_overrides(Path::new(DATABASE_FILE)).unwrap_or_else(|e| {
+		eprintln!("Error loading {}: {}", DATABASE_FILE, e);
+		process::exit(1);
+	})
+}
+
+fn apply_database(machine: &mut CHIP8, config: &mut Config) {
+	if let Some(info) = database().lookup(&machine.rom_sha1()) {
+		info.apply(machine, config);
+	}
+}
+
+// Settings come from chip8.toml, or the file given with --config, then the ROM database, then the command line
+fn run(options: &RunOptions) {
+	let config_path = options.config_file.clone().unwrap_or_else(|| cli::CONFIG_FILE.into());
+	let defaults = if options.config_file.is_some() || config_path.exists() {
+		Settings::load(&config_path).unwrap_or_else(|e| {
+			eprintln!("Error loading {}: {}", config_path.display(), e);
+			process::exit(1);
+		})
+	} else {
+		Settings::default()
+	};
 
-	let machine = CHIP8::new(Some(&args))
-						.unwrap_or_else(
-							|e| {
-								eprintln!("Error creating emulator object: {}", e);
-								process::exit(1);
-							}
-						);
+	// key bindings can be changed in chip8.keymap, in the directory the emulator is started from, or the
+	// file given with --keymap. The database's bindings are added to them
+	let mut config = Config::default();
+	let keymap_path = options.settings.keymap.clone().or_else(|| defaults.keymap.clone()).unwrap_or_else(|| KEYMAP_FILE.into());
+	if keymap_path.exists() || keymap_path != Path::new(KEYMAP_FILE) {
+		config.keymap = Keymap::load(&keymap_path).unwrap_or_else(|e| {
+			eprintln!("Error loading {}: {}", keymap_path.display(), e);
+			process::exit(1);
+		});
+	}
 
-	chip8::run(machine, &args[1]);
+	let mut machine = open(&options.rom);
+	defaults.apply(&mut machine, &mut config);
+	apply_database(&mut machine, &mut config);
+	options.settings.apply(&mut machine, &mut config);
+	options.configure_debugging(&mut config);
+	config.record = options.record.clone();
+
+	match tracer(options) {
+		Ok(tracer) => machine.set_tracer(tracer),
+		Err(e) => {
+			eprintln!("Error setting up the trace: {}", e);
+			process::exit(1);
+		}
+	}
+
+	if options.headless {
+		run_headless(machine, options, &config);
+	} else if config.terminal.is_some() {
+		if let Err(e) = chip8::run_in_terminal(machine, &options.rom, &config) {
+			eprintln!("Error taking over the terminal: {}", e);
+			process::exit(1);
+		}
+	} else if cfg!(not(feature = "window")) {
+		run_headless(machine, options, &config);
+	} else {
+		run_window(machine, &options.rom, &config);
+	}
+}
+
+fn tracer(options: &RunOptions) -> Result<Option<Tracer>, String> {
+	if options.trace.is_none() && options.trace_dump.is_none() {
+		return Ok(None);
+	}
+
+	let mut tracer = match options.trace.as_ref() {
+		Some(path) => Tracer::to_file(path).map_err(|e| format!("couldn't create {}: {}", path, e))?,
+		None => Tracer::new()
+	};
+	for &(start, end) in options.trace_ranges.iter() {
+		tracer.add_range(start, end);
+	}
+	tracer.set_dump_path(options.trace_dump.as_ref());
+	if let Some(count) = options.trace_history {
+		tracer.set_history_len(count);
+	}
+	Ok(Some(tracer))
+}
+
+#[cfg(feature = "window")]
+fn run_window(machine: CHIP8, program_path: &str, config: &Config) {
+	if let Err(e) = chip8::run(machine, program_path, config) {
+		eprintln!("Error opening the window: {}", e);
+		process::exit(1);
+	}
 }
 
+#[cfg(not(feature = "window"))]
+fn run_window(_machine: CHIP8, _program_path: &str, _config: &Config) {
+	unreachable!("built without a window, programs are always run headless");
+}
+
+// Without a window the program runs for the frames given with --frames, with the debugger's prompt on the
+// terminal, or for GDB
+fn run_headless(mut machine: CHIP8, options: &RunOptions, config: &Config) {
+	let program_path = options.rom.as_str();
+
+	if let Some(port) = config.gdb_port {
+		let mut debugger = chip8::Debugger::for_program(config, Some(program_path));
+		let served = chip8::gdb::GdbStub::listen(("127.0.0.1", port)).and_then(|mut stub| {
+			println!("Waiting for GDB on port {}", port);
+			chip8::gdb::serve(&mut machine, &mut stub, &mut debugger, &config.errors)
+		});
+
+		if let Err(e) = served {
+			eprintln!("Error talking to GDB: {}", e);
+			process::exit(1);
+		}
+		return;
+	}
+
+	if config.debug {
+		let mut debugger = chip8::Debugger::for_program(config, Some(program_path));
+
+		let stdin = std::io::stdin();
+		if let Err(e) = chip8::debugger::repl(&mut machine, &mut debugger, &config.errors, stdin.lock(), std::io::stdout()) {
+			eprintln!("Error reading commands: {}", e);
+			process::exit(1);
+		}
+		return;
+	}
+
+	let mut host = HeadlessHost::new(options.frames.unwrap_or(cli::DEFAULT_FRAMES));
+
+	match chip8::frontend::run_host(&mut machine, &mut host, config, Some(program_path)) {
+		Ok(()) => println!("Ran {} frames", machine.frame_count()),
+		Err(e) => {
+			eprintln!("Halted after {} frames: {}", machine.frame_count(), e);
+			process::exit(1);
+		}
+	}
+}
+
+#[cfg(feature = "image")]
+fn save_screenshot(machine: &CHIP8, path: &str, scale: u32, config: &Config) {
+	if let Err(e) = machine.save_screenshot(path, scale, &config.palette) {
+		eprintln!("Error saving {}: {}", path, e);
+		process::exit(1);
+	}
+}
+
+#[cfg(not(feature = "image"))]
+fn save_screenshot(_machine: &CHIP8, path: &str, _scale: u32, _config: &Config) {
+	eprintln!("Error saving {}: the emulator was built without the image feature", path);
+	process::exit(1);
+}
+
+// disasm [--classic] <rom>: prints a listing of the program, in Octo's syntax unless --classic is given
+fn disassemble(path: &str, syntax: Syntax) {
+	match fs::read(path) {
+		Ok(rom) => print!("{}", disasm::disassemble(&rom, syntax)),
+		Err(e) => {
+			eprintln!("Error reading {}: {}", path, e);
+			process::exit(1);
+		}
+	}
+}
+
+// asm <source> [-o <rom>]: assembles Octo source into <source>.ch8 unless -o is given, with its source map next to it
+fn assemble(source_path: &str, rom_path: Option<String>) {
+	let rom_path = rom_path.unwrap_or_else(|| Path::new(source_path).with_extension("ch8").to_string_lossy().into_owned());
+
+	let source = fs::read_to_string(source_path).unwrap_or_else(|e| {
+		eprintln!("Error reading {}: {}", source_path, e);
+		process::exit(1);
+	});
+
+	let assembly = asm::assemble(&source).unwrap_or_else(|e| {
+		eprintln!("Error in {}, {}", source_path, e);
+		process::exit(1);
+	});
+
+	let written = fs::write(&rom_path, &assembly.rom)
+					.and_then(|_| assembly.source_map.save(&asm::source_map_path(&rom_path)));
+	if let Err(e) = written {
+		eprintln!("Error writing {}: {}", rom_path, e);
+		process::exit(1);
+	}
+
+	println!("Assembled {} bytes into {}", assembly.rom.len(), rom_path);
+}
+
+// info <rom>: what the emulator knows about a ROM, from its extension and the ROM database
+fn info(path: &str) {
+	let rom = fs::read(path).unwrap_or_else(|e| {
+		eprintln!("Error reading {}: {}", path, e);
+		process::exit(1);
+	});
+	let sha1 = chip8::database::sha1_hex(&rom);
+	let platform = Path::new(path).extension().and_then(|e| Platform::from_extension(&e.to_string_lossy()));
+
+	println!("File      {}", path);
+	println!("Size      {} bytes", rom.len());
+	println!("SHA-1     {}", sha1);
+	match platform {
+		Some(p) => println!("Platform  {:?}, from the extension", p),
+		None => println!("Platform  Chip8, the extension doesn't say")
+	}
+
+	match database().lookup(&sha1) {
+		Some(info) => {
+			println!("Title     {}", info.title);
+			if let Some(platform) = info.platform {
+				println!("Platform  {:?}, from the ROM database", platform);
+			}
+			if let Some(quirks) = info.quirks {
+				println!("Quirks    {:?}", quirks);
+			}
+			if let Some(ipf) = info.instructions_per_frame {
+				println!("Speed     {} instructions per frame", ipf);
+			}
+		},
+		None => println!("Not in the ROM database")
+	}
+}
+
+// test <rom> [--frames <count>] [--screenshot <png>]: runs a program headless, the way test suites are, and
+// prints the screen it ends on, saving it as a PNG too if asked to. Fails if the program halts on an error
+fn test(path: &str, frames: u64, screenshot: Option<&str>, scale: u32) {
+	let mut config = Config::default();
+	let mut machine = open(path);
+	apply_database(&mut machine, &mut config);
+	let mut host = HeadlessHost::new(frames);
+
+	let result = chip8::frontend::run_host(&mut machine, &mut host, &config, Some(path));
+
+	let screen = host.screen();
+	for y in 0 .. screen.height() {
+		let row: String = (0 .. screen.width()).map(|x| if screen.pixel(x, y) != 0 { '#' } else { '.' }).collect();
+		println!("{}", row);
+	}
+
+	if let Some(png) = screenshot {
+		save_screenshot(&machine, png, scale, &config);
+	}
+
+	if let Err(e) = result {
+		eprintln!("Halted after {} frames: {}", machine.frame_count(), e);
+		process::exit(1);
+	}
+}
@@ -0,0 +1,124 @@
+use std::fmt;
+
+#[cfg(test)]
+mod tests;
+
+// The colours the screen is drawn in: for pixels set in no plane, the first, the second and both. Programs
+// that only draw to the first plane just use the first two
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Palette {
+	colours: [[u8; 4]; 4]
+}
+
+impl Palette {
+	// white on black, with greys for XO-CHIP's second plane
+	pub const CLASSIC: Palette = Palette::new([ [0, 0, 0, 255], [255, 255, 255, 255], [170, 170, 170, 255], [85, 85, 85, 255] ]);
+
+	// an amber monochrome monitor
+	pub const AMBER: Palette = Palette::new([ [20, 12, 0, 255], [255, 176, 0, 255], [128, 88, 0, 255], [255, 212, 120, 255] ]);
+
+	// a green phosphor terminal
+	pub const GREEN: Palette = Palette::new([ [4, 16, 4, 255], [51, 255, 51, 255], [26, 128, 26, 255], [160, 255, 160, 255] ]);
+
+	// a handheld's greenish LCD, dark pixels on a light screen
+	pub const LCD: Palette = Palette::new([ [155, 188, 15, 255], [15, 56, 15, 255], [139, 172, 15, 255], [48, 98, 48, 255] ]);
+
+	// Octo's defaults
+	pub const OCTO: Palette = Palette::new([ [153, 102, 0, 255], [255, 204, 0, 255], [255, 102, 0, 255], [102, 34, 0, 255] ]);
+
+	// The built-in themes, in the order the palette key cycles through them
+	pub const THEMES: [(&'static str, Palette); 5] = [
+		("classic", Palette::CLASSIC),
+		("amber", Palette::AMBER),
+		("green", Palette::GREEN),
+		("lcd", Palette::LCD),
+		("octo", Palette::OCTO)
+	];
+
+	pub const fn new(colours: [[u8; 4]; 4]) -> Palette {
+		Palette { colours }
+	}
+
+	pub fn theme(name: &str) -> Option<Palette> {
+		let name = name.to_lowercase();
+		Palette::THEMES.iter().find(|(theme, _)| *theme == name).map(|&(_, palette)| palette)
+	}
+
+	// The theme's name, if it's one of the built-in ones
+	pub fn name(&self) -> Option<&'static str> {
+		Palette::THEMES.iter().find(|(_, palette)| palette == self).map(|&(name, _)| name)
+	}
+
+	// A theme's name, or up to 4 colours like #1a2b3c separated by commas. The ones left out are the classic
+	// theme's
+	pub fn parse(text: &str) -> Result<Palette, String> {
+		if let Some(palette) = Palette::theme(text.trim()) {
+			return Ok(palette);
+		}
+
+		let colours = text.split(',').map(|colour| parse_colour(colour.trim())).collect::<Result<Vec<_>, _>>()?;
+		if colours.len() > 4 {
+			return Err(format!("a palette has at most 4 colours, not {}", colours.len()));
+		}
+		Ok(Palette::CLASSIC.with(&colours))
+	}
+
+	// This palette with its first colours replaced
+	pub fn with(&self, colours: &[[u8; 4]]) -> Palette {
+		let mut palette = *self;
+		for (slot, &colour) in palette.colours.iter_mut().zip(colours) {
+			*slot = colour;
+		}
+		palette
+	}
+
+	// The colour for a pixel, given the planes it's set in
+	pub fn colour(&self, planes: u8) -> [u8; 4] {
+		self.colours[planes as usize & 0b11]
+	}
+
+	// The colour for a pixel lit partway in each plane, 255 being fully lit, mixed from the colours for the
+	// planes it's in
+	pub fn mix(&self, [first, second]: [u8; 2]) -> [u8; 4] {
+		let (a, b) = (first as u32, second as u32);
+		let weights = [(255 - a) * (255 - b), a * (255 - b), (255 - a) * b, a * b];
+
+		let mut colour = [0; 4];
+		for (i, channel) in colour.iter_mut().enumerate() {
+			let sum: u32 = weights.iter().zip(&self.colours).map(|(w, c)| w * c[i] as u32).sum();
+			*channel = ((sum + 255 * 255 / 2) / (255 * 255)) as u8;
+		}
+		colour
+	}
+
+	pub fn colours(&self) -> &[[u8; 4]; 4] {
+		&self.colours
+	}
+}
+
+impl Default for Palette {
+	fn default() -> Palette {
+		Palette::CLASSIC
+	}
+}
+
+// A theme's name, or the colours
+impl fmt::Display for Palette {
+	fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+		match self.name() {
+			Some(name) => write!(f, "{}", name),
+			None => {
+				let colours: Vec<String> = self.colours.iter().map(|[r, g, b, _]| format!("#{:02x}{:02x}{:02x}", r, g, b)).collect();
+				write!(f, "{}", colours.join(","))
+			}
+		}
+	}
+}
+
+// #1a2b3c
+fn parse_colour(text: &str) -> Result<[u8; 4], String> {
+	let hex = text.strip_prefix('#').filter(|h| h.len() == 6 && h.chars().all(|c| c.is_ascii_hexdigit()))
+				.ok_or_else(|| format!("bad colour {}, they're written like #1a2b3c or a theme's name: classic, amber, green, lcd or octo", text))?;
+	let channel = |i: usize| u8::from_str_radix(&hex[i .. i + 2], 16).unwrap();
+	Ok([channel(0), channel(2), channel(4), 255])
+}
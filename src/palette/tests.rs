@@ -0,0 +1,34 @@
+use super::Palette;
+
+#[test]
+fn themes_are_found_by_name() {
+	assert_eq!(Palette::theme("LCD"), Some(Palette::LCD));
+	assert_eq!(Palette::theme("sepia"), None);
+	assert_eq!(Palette::OCTO.name(), Some("octo"));
+	assert_eq!(Palette::default(), Palette::CLASSIC);
+
+	// every theme draws all four combinations of planes differently
+	for (name, palette) in Palette::THEMES.iter() {
+		let colours = palette.colours();
+		for i in 0 .. 4 {
+			assert!(colours[i + 1 ..].iter().all(|c| *c != colours[i]), "{}", name);
+		}
+	}
+}
+
+#[test]
+fn palettes_are_parsed_from_themes_or_colours() {
+	assert_eq!(Palette::parse(" amber "), Ok(Palette::AMBER));
+
+	let palette = Palette::parse("#102030, #FFFFFF").unwrap();
+	assert_eq!(palette.colour(0), [0x10, 0x20, 0x30, 255]);
+	assert_eq!(palette.colour(1), [255, 255, 255, 255]);
+	assert_eq!(palette.colour(2), Palette::CLASSIC.colour(2));
+	assert_eq!(palette.name(), None);
+	assert_eq!(palette.to_string(), "#102030,#ffffff,#aaaaaa,#555555");
+	assert_eq!(Palette::parse(&Palette::GREEN.with(&[[1, 2, 3, 255]]).to_string()), Ok(Palette::GREEN.with(&[[1, 2, 3, 255]])));
+
+	assert!(Palette::parse("#12345").is_err());
+	assert!(Palette::parse("sepia").is_err());
+	assert_eq!(Palette::parse("#000000,#000000,#000000,#000000,#000000"), Err("a palette has at most 4 colours, not 5".to_string()));
+}
@@ -0,0 +1,213 @@
+use crate::{ Palette, HIRES_W, HIRES_H, frontend::Screen };
+
+use std::{
+	fs::File,
+	io::{ self, BufWriter, Write },
+	path::Path
+};
+
+#[cfg(test)]
+mod tests;
+
+// The shortest delay GIF viewers respect, in hundredths of a second. Most show anything shorter as 1/10 s
+#[cfg(feature = "image")]
+const MIN_GIF_DELAY: u64 = 2;
+
+const FRAME_RATE: u64 = 60;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum RecordFormat {
+	Gif, // an animated GIF, with each distinct frame once and timed with its delay
+	Y4m, // uncompressed YUV 4:4:4 at 60 frames per second, which ffmpeg and most encoders read
+	Ppm // one P6 image after another, every frame, for ffmpeg's image2pipe
+}
+
+impl RecordFormat {
+	// Picks the format from a file's extension
+	pub fn from_path(path: &Path) -> Option<RecordFormat> {
+		match path.extension()?.to_string_lossy().to_lowercase().as_str() {
+			"gif" => Some(RecordFormat::Gif),
+			"y4m" => Some(RecordFormat::Y4m),
+			"ppm" => Some(RecordFormat::Ppm),
+			_ => None
+		}
+	}
+}
+
+// Records the screen as it is at every frame. Recordings stay the same size when programs switch between the
+// 64x32 and 128x64 modes, like the window. The scale is in pixels of the recording per pixel of the 64x32
+// mode, and rounded up to an even number so the high resolution mode's pixels are whole
+pub struct Recorder {
+	format: RecordFormat,
+	output: Output,
+	scale: u32, // pixels of the recording per pixel of the high resolution mode
+	palette: Palette,
+	frames: u64, // how many frames have been captured
+	last: Vec<u8> // the last frame's pixels, for GIFs to tell if the next one changed
+}
+
+enum Output {
+	Raw(Box<dyn Write>),
+	#[cfg(feature = "image")]
+	Gif {
+		encoder: image::gif::GifEncoder<Box<dyn Write>>,
+		pending: Option<image::RgbaImage>, // the frame waiting for the next change to know how long it's shown
+		written_until: u64 // when the last frame written ends, in hundredths of a second
+	}
+}
+
+impl Recorder {
+	// Records to a new file at path, in the format its extension says
+	pub fn create<P: AsRef<Path>>(path: P, scale: u32, palette: &Palette) -> io::Result<Recorder> {
+		let path = path.as_ref();
+		let format = RecordFormat::from_path(path).ok_or_else(|| {
+			io::Error::new(io::ErrorKind::InvalidInput, "recordings are .gif, .y4m or .ppm files")
+		})?;
+
+		let file = File::create(path)?;
+		Recorder::with_output(format, Box::new(BufWriter::new(file)), scale, palette)
+	}
+
+	pub fn with_output(format: RecordFormat, output: Box<dyn Write>, scale: u32, palette: &Palette) -> io::Result<Recorder> {
+		let scale = scale.max(1).div_ceil(2);
+		let output = match format {
+			#[cfg(feature = "image")]
+			RecordFormat::Gif => {
+				let mut encoder = image::gif::GifEncoder::new(output);
+				encoder.set_repeat(image::gif::Repeat::Infinite).map_err(io::Error::other)?;
+				Output::Gif { encoder, pending: None, written_until: 0 }
+			},
+			#[cfg(not(feature = "image"))]
+			RecordFormat::Gif => return Err(io::Error::other("GIFs can't be recorded without the image feature")),
+			RecordFormat::Y4m => {
+				let mut output = output;
+				let (width, height) = (HIRES_W as u32 * scale, HIRES_H as u32 * scale);
+				writeln!(output, "YUV4MPEG2 W{} H{} F{}:1 Ip A1:1 C444", width, height, FRAME_RATE)?;
+				Output::Raw(output)
+			},
+			RecordFormat::Ppm => Output::Raw(output)
+		};
+
+		Ok(Recorder { format, output, scale, palette: *palette, frames: 0, last: Vec::new() })
+	}
+
+	pub fn format(&self) -> RecordFormat {
+		self.format
+	}
+
+	// How many frames have been recorded. GIFs keep fewer, as they only store changes
+	pub fn frames(&self) -> u64 {
+		self.frames
+	}
+
+	pub fn width(&self) -> u32 {
+		HIRES_W as u32 * self.scale
+	}
+
+	pub fn height(&self) -> u32 {
+		HIRES_H as u32 * self.scale
+	}
+
+	// Adds a frame. Call this once for every frame the machine runs
+	pub fn capture(&mut self, screen: &Screen) -> io::Result<()> {
+		let changed = self.last.as_slice() != screen.pixels();
+		if changed {
+			self.last.clear();
+			self.last.extend_from_slice(screen.pixels());
+		}
+
+		let (width, height) = (self.width(), self.height());
+		let pixel_scale = self.scale * (HIRES_W as u32 / screen.width() as u32);
+		let palette = self.palette;
+		let colour = |x: u32, y: u32| palette.colour(screen.pixel((x / pixel_scale) as usize, (y / pixel_scale) as usize));
+
+		match &mut self.output {
+			Output::Raw(out) => {
+				let pixels: Vec<[u8; 4]> = (0 .. height).flat_map(|y| (0 .. width).map(move |x| (x, y))).map(|(x, y)| colour(x, y)).collect();
+				if self.format == RecordFormat::Y4m {
+					out.write_all(b"FRAME\n")?;
+					for channel in 0 .. 3 {
+						let plane: Vec<u8> = pixels.iter().map(|&p| to_ycbcr(p)[channel]).collect();
+						out.write_all(&plane)?;
+					}
+				} else {
+					write!(out, "P6\n{} {}\n255\n", width, height)?;
+					let rgb: Vec<u8> = pixels.iter().flat_map(|p| p[.. 3].iter().copied()).collect();
+					out.write_all(&rgb)?;
+				}
+			},
+
+			#[cfg(feature = "image")]
+			Output::Gif { encoder, pending, written_until } => {
+				if changed || pending.is_none() {
+					let image = image::RgbaImage::from_fn(width, height, |x, y| image::Rgba(colour(x, y)));
+					let now = centiseconds(self.frames);
+					match pending.take() {
+						// the frame before is shown until this one
+						Some(previous) if now - *written_until >= MIN_GIF_DELAY => {
+							write_gif_frame(encoder, previous, now - *written_until)?;
+							*written_until = now;
+						},
+						// or if that's too short for GIF viewers to show it, this one takes its place
+						_ => ()
+					}
+					*pending = Some(image);
+				}
+			}
+		}
+
+		self.frames += 1;
+		Ok(())
+	}
+
+	// Writes out what's left and closes the recording
+	pub fn finish(mut self) -> io::Result<()> {
+		self.flush()
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		match &mut self.output {
+			Output::Raw(out) => out.flush(),
+
+			#[cfg(feature = "image")]
+			Output::Gif { encoder, pending, written_until } => {
+				if let Some(image) = pending.take() {
+					let delay = centiseconds(self.frames).saturating_sub(*written_until).max(MIN_GIF_DELAY);
+					write_gif_frame(encoder, image, delay)?;
+				}
+				Ok(())
+			}
+		}
+	}
+}
+
+impl Drop for Recorder {
+	// a recording that isn't finished still gets its last frame
+	fn drop(&mut self) {
+		if let Err(e) = self.flush() {
+			eprintln!("Couldn't finish the recording: {}", e);
+		}
+	}
+}
+
+// When a frame starts, in hundredths of a second
+#[cfg(feature = "image")]
+fn centiseconds(frame: u64) -> u64 {
+	(frame * 100 + FRAME_RATE / 2) / FRAME_RATE
+}
+
+#[cfg(feature = "image")]
+fn write_gif_frame(encoder: &mut image::gif::GifEncoder<Box<dyn Write>>, image: image::RgbaImage, delay: u64) -> io::Result<()> {
+	let delay = image::Delay::from_numer_denom_ms(delay as u32 * 10, 1);
+	encoder.encode_frame(image::Frame::from_parts(image, 0, 0, delay)).map_err(io::Error::other)
+}
+
+// BT.601, in the 16 to 235 range video expects
+fn to_ycbcr([r, g, b, _]: [u8; 4]) -> [u8; 3] {
+	let (r, g, b) = (r as i32, g as i32, b as i32);
+	[
+		(((66 * r + 129 * g + 25 * b + 128) >> 8) + 16) as u8,
+		(((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128) as u8,
+		(((112 * r - 94 * g - 18 * b + 128) >> 8) + 128) as u8
+	]
+}
@@ -0,0 +1,101 @@
+use super::{ Recorder, RecordFormat, to_ycbcr };
+use crate::{ Palette, frontend::Screen };
+
+use std::{
+	io::{ self, Write },
+	path::Path,
+	sync::{ Arc, Mutex }
+};
+
+#[derive(Clone, Default)]
+struct Output(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Output {
+	fn write(&mut self, data: &[u8]) -> io::Result<usize> {
+		self.0.lock().unwrap().extend_from_slice(data);
+		Ok(data.len())
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		Ok(())
+	}
+}
+
+fn record(format: RecordFormat, scale: u32, screens: &[(usize, usize, Vec<u8>)]) -> Vec<u8> {
+	let output = Output::default();
+	let mut recorder = Recorder::with_output(format, Box::new(output.clone()), scale, &Palette::CLASSIC).unwrap();
+	for (width, height, pixels) in screens {
+		recorder.capture(&Screen::new(*width, *height, pixels)).unwrap();
+	}
+	assert_eq!(recorder.frames(), screens.len() as u64);
+	recorder.finish().unwrap();
+
+	let data = output.0.lock().unwrap().clone();
+	data
+}
+
+#[test]
+fn formats_come_from_the_extension() {
+	assert_eq!(RecordFormat::from_path(Path::new("clip.GIF")), Some(RecordFormat::Gif));
+	assert_eq!(RecordFormat::from_path(Path::new("clip.y4m")), Some(RecordFormat::Y4m));
+	assert_eq!(RecordFormat::from_path(Path::new("clip.ppm")), Some(RecordFormat::Ppm));
+	assert_eq!(RecordFormat::from_path(Path::new("clip")), None);
+	assert!(Recorder::create("clip.mp4", 1, &Palette::CLASSIC).is_err());
+}
+
+#[test]
+fn y4m_has_every_frame_at_the_high_resolution_size() {
+	let mut lores = vec![0; 64 * 32];
+	lores[0] = 1;
+	let data = record(RecordFormat::Y4m, 2, &[(64, 32, lores.clone()), (64, 32, lores), (128, 64, vec![0; 128 * 64])]);
+
+	let header = b"YUV4MPEG2 W128 H64 F60:1 Ip A1:1 C444\n";
+	let frame_len = 6 + 128 * 64 * 3;
+	assert!(data.starts_with(header));
+	assert_eq!(data.len(), header.len() + 3 * frame_len);
+
+	// the 64x32 mode's pixels are doubled
+	let frame = &data[header.len() + 6 ..];
+	let [white, _, _] = to_ycbcr(Palette::CLASSIC.colour(1));
+	let [black, _, _] = to_ycbcr(Palette::CLASSIC.colour(0));
+	assert_eq!((white, black), (235, 16));
+	assert_eq!(&frame[.. 3], &[white, white, black]);
+	assert_eq!(frame[128 + 1], white);
+	assert_eq!(frame[2 * 128], black);
+}
+
+#[test]
+fn ppm_is_a_stream_of_images() {
+	let data = record(RecordFormat::Ppm, 1, &[(128, 64, vec![3; 128 * 64]), (128, 64, vec![0; 128 * 64])]);
+
+	let header = b"P6\n128 64\n255\n";
+	let image_len = header.len() + 128 * 64 * 3;
+	assert_eq!(data.len(), 2 * image_len);
+	assert_eq!(&data[.. header.len()], header);
+	assert_eq!(&data[header.len() .. header.len() + 3], &Palette::CLASSIC.colour(3)[.. 3]);
+	assert_eq!(&data[image_len .. image_len + header.len()], header);
+}
+
+#[cfg(feature = "image")]
+#[test]
+fn gifs_only_keep_changes() {
+	use image::AnimationDecoder;
+
+	// 30 frames of one screen then 4 of another, then one frame too short to show and 29 of a fourth
+	let blank = (64, 32, vec![0; 64 * 32]);
+	let lit = (64, 32, vec![1; 64 * 32]);
+	let both = (64, 32, vec![3; 64 * 32]);
+	let mut screens = vec![blank; 30];
+	screens.extend(vec![lit; 4]);
+	screens.push((64, 32, vec![2; 64 * 32]));
+	screens.extend(vec![both; 29]);
+
+	let data = record(RecordFormat::Gif, 2, &screens);
+	let frames = image::gif::GifDecoder::new(data.as_slice()).unwrap().into_frames().collect_frames().unwrap();
+
+	let delays: Vec<u32> = frames.iter().map(|f| f.delay().numer_denom_ms().0 / f.delay().numer_denom_ms().1).collect();
+	assert_eq!(delays, vec![500, 70, 500]); // 4 frames end 0.57 s in, and the short frame's time goes to the next
+	assert_eq!(frames[0].buffer().dimensions(), (128, 64));
+	assert_eq!(frames[1].buffer().get_pixel(0, 0).0, Palette::CLASSIC.colour(1));
+	assert_eq!(frames[2].buffer().get_pixel(0, 0).0, Palette::CLASSIC.colour(3));
+}
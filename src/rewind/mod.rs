@@ -0,0 +1,152 @@
+use crate::CHIP8;
+
+use std::collections::VecDeque;
+
+#[cfg(test)]
+mod tests;
+
+// 20 seconds at 60 frames per second
+pub const DEFAULT_REWIND_FRAMES: usize = 20 * 60;
+
+// Keeps the machine's recent history so it can be stepped backwards one frame at a time. Only the
+// latest state is stored whole, every older one is kept as the difference with the state after it,
+// which is tiny as most of memory and the screen don't change from one frame to the next
+pub struct RewindBuffer {
+	capacity: usize, // how many frames can be rewound
+	latest: Option<Vec<u8>>, // the save state of the most recently captured frame
+	deltas: VecDeque<Vec<u8>> // each one turns a state into the one captured before it. newest last
+}
+
+impl RewindBuffer {
+	pub fn new(capacity: usize) -> RewindBuffer {
+		RewindBuffer {
+			capacity,
+			latest: None,
+			deltas: VecDeque::with_capacity(capacity)
+		}
+	}
+
+	// Records the machine's current state. Call this once after every frame
+	pub fn capture(&mut self, machine: &CHIP8) {
+		let state = machine.save_state();
+
+		if let Some(latest) = self.latest.take() {
+			self.deltas.push_back(encode_delta(&state, &latest));
+			if self.deltas.len() > self.capacity {
+				self.deltas.pop_front(); // forget the oldest frame
+			}
+		}
+
+		self.latest = Some(state);
+	}
+
+	// Puts the machine back to the frame captured before the latest one. Returns false, leaving the
+	// machine alone, when there's no history left
+	pub fn rewind(&mut self, machine: &mut CHIP8) -> bool {
+		let (latest, delta) = match (&self.latest, self.deltas.pop_back()) {
+			(Some(latest), Some(delta)) => (latest, delta),
+			_ => return false
+		};
+
+		let previous = apply_delta(latest, &delta);
+		if machine.load_state(&previous).is_err() {
+			// only happens if another program was loaded since, the history is useless now
+			self.clear();
+			return false;
+		}
+
+		self.latest = Some(previous);
+		true
+	}
+
+	// How many frames can be rewound right now
+	pub fn len(&self) -> usize {
+		self.deltas.len()
+	}
+
+	pub fn is_empty(&self) -> bool {
+		self.deltas.is_empty()
+	}
+
+	pub fn clear(&mut self) {
+		self.latest = None;
+		self.deltas.clear();
+	}
+
+	// The total size of the stored history in bytes
+	pub fn memory_usage(&self) -> usize {
+		self.latest.as_ref().map_or(0, Vec::len) + self.deltas.iter().map(Vec::len).sum::<usize>()
+	}
+}
+
+// Deltas start with a tag saying how the rest is encoded
+const DELTA_XOR: u8 = 0; // runs of unchanged bytes and the XOR of the changed ones
+const DELTA_FULL: u8 = 1; // the whole target state, for when the two states differ in size
+
+// Encodes how to get from one state to another. The XOR of the two states is stored as pairs of
+// the number of bytes that are the same and the bytes that differ
+fn encode_delta(from: &[u8], to: &[u8]) -> Vec<u8> {
+	if from.len() != to.len() {
+		let mut delta = vec![DELTA_FULL];
+		delta.extend_from_slice(to);
+		return delta;
+	}
+
+	let mut delta = vec![DELTA_XOR];
+	let mut i = 0;
+	while i < from.len() {
+		let same_start = i;
+		while i < from.len() && from[i] == to[i] { i += 1; }
+		let diff_start = i;
+		while i < from.len() && from[i] != to[i] { i += 1; }
+
+		if diff_start == i { break; } // nothing left that differs
+		write_varint(&mut delta, diff_start - same_start);
+		write_varint(&mut delta, i - diff_start);
+		delta.extend(from[diff_start .. i].iter().zip(&to[diff_start .. i]).map(|(a, b)| a ^ b));
+	}
+
+	delta
+}
+
+fn apply_delta(from: &[u8], delta: &[u8]) -> Vec<u8> {
+	if delta[0] == DELTA_FULL {
+		return delta[1 ..].to_vec();
+	}
+
+	let mut state = from.to_vec();
+	let mut pos = 0;
+	let mut i = 1;
+	while i < delta.len() {
+		pos += read_varint(delta, &mut i);
+		let len = read_varint(delta, &mut i);
+		for (byte, change) in state[pos .. pos + len].iter_mut().zip(&delta[i .. i + len]) {
+			*byte ^= change;
+		}
+		pos += len;
+		i += len;
+	}
+
+	state
+}
+
+// LEB128, 7 bits per byte with the top bit set on all but the last
+fn write_varint(out: &mut Vec<u8>, mut value: usize) {
+	while value >= 0x80 {
+		out.push((value as u8 & 0x7F) | 0x80);
+		value >>= 7;
+	}
+	out.push(value as u8);
+}
+
+fn read_varint(data: &[u8], i: &mut usize) -> usize {
+	let mut value = 0;
+	let mut shift = 0;
+	loop {
+		let byte = data[*i];
+		*i += 1;
+		value |= ((byte & 0x7F) as usize) << shift;
+		if byte & 0x80 == 0 { return value; }
+		shift += 7;
+	}
+}
@@ -0,0 +1,96 @@
+use super::{ RewindBuffer, encode_delta, apply_delta };
+use crate::{ CHIP8, Chip8Key, ErrorPolicy };
+
+fn get_counting_machine() -> CHIP8 {
+	let mut machine = CHIP8::new(&[]).unwrap();
+
+	// 7101 1200: add 1 to V1 forever
+	machine.memory[0x200 .. 0x204].copy_from_slice(&[0x71, 0x01, 0x12, 0x00]);
+	machine.set_instructions_per_frame(2);
+
+	machine
+}
+
+#[test]
+fn delta_round_trip() {
+	let from = vec![0, 1, 2, 3, 4, 5, 6, 7, 8, 9];
+	let to = vec![0, 1, 9, 3, 4, 5, 6, 0, 0, 9];
+
+	let delta = encode_delta(&from, &to);
+
+	assert_eq!(apply_delta(&from, &delta), to);
+	assert_eq!(apply_delta(&from, &encode_delta(&from, &from)), from);
+	assert_eq!(apply_delta(&from, &encode_delta(&from, &[1, 2])), vec![1, 2]);
+}
+
+#[test]
+fn delta_long_runs() {
+	let from = vec![0; 100_000];
+	let mut to = from.clone();
+	to[70_000] = 1;
+	to[99_999] = 2;
+
+	let delta = encode_delta(&from, &to);
+
+	assert!(delta.len() < 16);
+	assert_eq!(apply_delta(&from, &delta), to);
+}
+
+#[test]
+fn rewind_frames() {
+	let mut machine = get_counting_machine();
+	let mut rewind = RewindBuffer::new(100);
+
+	rewind.capture(&machine);
+	for _ in 0 .. 10 {
+		machine.run_frame(&ErrorPolicy::default()).unwrap();
+		rewind.capture(&machine);
+	}
+
+	assert_eq!(machine.V[0x1], 10);
+	assert_eq!(rewind.len(), 10);
+
+	for expected in (0 .. 10).rev() {
+		assert!(rewind.rewind(&mut machine));
+		assert_eq!(machine.V[0x1], expected);
+		assert_eq!(machine.frame_count(), expected as u64);
+	}
+
+	assert!(!rewind.rewind(&mut machine));
+	assert_eq!(machine.V[0x1], 0);
+}
+
+#[test]
+fn rewind_capacity() {
+	let mut machine = get_counting_machine();
+	let mut rewind = RewindBuffer::new(5);
+
+	for _ in 0 .. 20 {
+		machine.run_frame(&ErrorPolicy::default()).unwrap();
+		rewind.capture(&machine);
+	}
+
+	assert_eq!(rewind.len(), 5);
+	while rewind.rewind(&mut machine) {}
+	assert_eq!(machine.V[0x1], 15);
+
+	// deltas are much smaller than whole states
+	assert!(rewind.memory_usage() < machine.save_state().len() + 5 * 64);
+}
+
+#[test]
+fn rewinding_leaves_the_keys_alone() {
+	let mut machine = get_counting_machine();
+	let mut rewind = RewindBuffer::new(100);
+	let key = Chip8Key::from_index(5).unwrap();
+
+	// the key was down in the frames rewound to, but it's been let go since
+	machine.set_key(key, true);
+	rewind.capture(&machine);
+	machine.run_frame(&ErrorPolicy::default()).unwrap();
+	rewind.capture(&machine);
+	machine.set_key(key, false);
+
+	assert!(rewind.rewind(&mut machine));
+	assert!(!machine.is_key_pressed(key));
+}
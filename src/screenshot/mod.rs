@@ -0,0 +1,69 @@
+use crate::{ Palette, frontend::Screen };
+
+use image::{ ImageResult, RgbaImage };
+
+use std::{
+	path::{ Path, PathBuf },
+	time::{ SystemTime, UNIX_EPOCH }
+};
+
+#[cfg(test)]
+mod tests;
+
+// The screen with every pixel blown up into a scale x scale square
+pub fn scaled_image(screen: &Screen, scale: u32, palette: &Palette) -> RgbaImage {
+	let scale = scale.max(1);
+	let width = screen.width() as u32 * scale;
+	let height = screen.height() as u32 * scale;
+
+	RgbaImage::from_fn(width, height, |x, y| {
+		image::Rgba(palette.colour(screen.pixel((x / scale) as usize, (y / scale) as usize)))
+	})
+}
+
+// Writes the screen to a PNG file
+pub fn save_png<P: AsRef<Path>>(screen: &Screen, path: P, scale: u32, palette: &Palette) -> ImageResult<()> {
+	scaled_image(screen, scale, palette).save_with_format(path, image::ImageFormat::Png)
+}
+
+// Screenshots are saved next to the ROM and named after it and the time they were taken, in UTC, e.g.
+// pong-20261017-153012.png. A number is added if there's already a screenshot from that second
+pub fn screenshot_path(program_path: &str, time: SystemTime) -> PathBuf {
+	capture_path(program_path, time, "png")
+}
+
+// The same for other captures of the screen, like recordings
+pub fn capture_path(program_path: &str, time: SystemTime, extension: &str) -> PathBuf {
+	let program = Path::new(program_path);
+	let name = program.file_stem().map_or("screenshot".into(), |s| s.to_string_lossy());
+	let stem = format!("{}-{}", name, timestamp(time));
+
+	let mut path = program.with_file_name(format!("{}.{}", stem, extension));
+	let mut count = 2;
+	while path.exists() {
+		path = program.with_file_name(format!("{}-{}.{}", stem, count, extension));
+		count += 1;
+	}
+	path
+}
+
+// YYYYMMDD-HHMMSS
+fn timestamp(time: SystemTime) -> String {
+	let seconds = time.duration_since(UNIX_EPOCH).map_or(0, |d| d.as_secs());
+	let (days, second_of_day) = (seconds / 86400, seconds % 86400);
+
+	// the days since 1970 as a date in the Gregorian calendar, with years starting in March so leap days
+	// come last
+	let days = days as i64 + 719468;
+	let era = days / 146097;
+	let day_of_era = days - era * 146097;
+	let year_of_era = (day_of_era - day_of_era / 1460 + day_of_era / 36524 - day_of_era / 146096) / 365;
+	let day_of_year = day_of_era - (365 * year_of_era + year_of_era / 4 - year_of_era / 100);
+	let month = (5 * day_of_year + 2) / 153;
+	let day = day_of_year - (153 * month + 2) / 5 + 1;
+	let month = if month < 10 { month + 3 } else { month - 9 };
+	let year = year_of_era + era * 400 + if month <= 2 { 1 } else { 0 };
+
+	format!("{:04}{:02}{:02}-{:02}{:02}{:02}", year, month, day,
+			second_of_day / 3600, second_of_day / 60 % 60, second_of_day % 60)
+}
@@ -0,0 +1,50 @@
+use super::{ scaled_image, save_png, screenshot_path, timestamp };
+use crate::{ CHIP8, Palette, frontend::Screen };
+
+use std::{
+	fs,
+	time::{ Duration, UNIX_EPOCH }
+};
+
+#[test]
+fn images_are_scaled_up() {
+	let mut pixels = vec![0; 64 * 32];
+	pixels[1] = 1;
+	pixels[64] = 3;
+	let image = scaled_image(&Screen::new(64, 32, &pixels), 3, &Palette::CLASSIC);
+
+	assert_eq!(image.dimensions(), (192, 96));
+	assert_eq!(image.get_pixel(2, 2).0, Palette::CLASSIC.colour(0));
+	assert_eq!(image.get_pixel(3, 0).0, Palette::CLASSIC.colour(1));
+	assert_eq!(image.get_pixel(5, 2).0, Palette::CLASSIC.colour(1));
+	assert_eq!(image.get_pixel(0, 3).0, Palette::CLASSIC.colour(3));
+}
+
+#[test]
+fn timestamps_are_utc_dates() {
+	assert_eq!(timestamp(UNIX_EPOCH), "19700101-000000");
+	assert_eq!(timestamp(UNIX_EPOCH + Duration::from_secs(951_782_400)), "20000229-000000"); // a leap day
+	assert_eq!(timestamp(UNIX_EPOCH + Duration::from_secs(1_792_251_012)), "20261017-153012");
+}
+
+#[test]
+fn screenshots_are_named_after_the_rom() {
+	let dir = std::env::temp_dir().join(format!("chip8-screenshots-{}", std::process::id()));
+	fs::create_dir_all(&dir).unwrap();
+	let program = dir.join("pong.ch8");
+	let time = UNIX_EPOCH + Duration::from_secs(1_792_251_012);
+
+	let first = screenshot_path(program.to_str().unwrap(), time);
+	assert_eq!(first, dir.join("pong-20261017-153012.png"));
+
+	// saving one, the next from the same second gets a number
+	let mut machine = CHIP8::new(&[]).unwrap();
+	machine.gfx[0] = 1;
+	save_png(&machine.screen(), &first, 2, &Palette::CLASSIC).unwrap();
+	let saved = image::open(&first).unwrap().to_rgba8();
+	assert_eq!(saved.dimensions(), (128, 64));
+	assert_eq!(saved.get_pixel(1, 1).0, Palette::CLASSIC.colour(1));
+	assert_eq!(screenshot_path(program.to_str().unwrap(), time), dir.join("pong-20261017-153012-2.png"));
+
+	fs::remove_dir_all(&dir).unwrap();
+}
@@ -0,0 +1,42 @@
+// The buzzer's settings are always available, the rodio sources that play it need the window feature
+#[cfg(feature = "window")]
+pub mod sources;
+
+use std::path::PathBuf;
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Waveform {
+	Square,
+	Sine
+}
+
+impl Waveform {
+	// square or sine
+	pub fn parse(name: &str) -> Option<Waveform> {
+		match name.to_lowercase().as_str() {
+			"square" => Some(Waveform::Square),
+			"sine" => Some(Waveform::Sine),
+			_ => None
+		}
+	}
+}
+
+// How the buzzer sounds while the sound timer is running
+#[derive(Clone, Debug)]
+pub struct AudioConfig {
+	pub waveform: Waveform,
+	pub frequency: f32, // in Hz
+	pub volume: f32, // from 0 to 1
+	pub sample: Option<PathBuf> // a sound file looped instead of the generated tone
+}
+
+impl Default for AudioConfig {
+	fn default() -> AudioConfig {
+		AudioConfig {
+			waveform: Waveform::Square,
+			frequency: 440.0,
+			volume: 0.25,
+			sample: None
+		}
+	}
+}
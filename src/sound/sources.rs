@@ -0,0 +1,190 @@
+use rodio::{ Decoder, Source };
+
+use crate::sound::{ AudioConfig, Waveform };
+
+use std::{
+	f32::consts::PI,
+	fs::File,
+	error::Error,
+	io::BufReader,
+	path::Path,
+	sync::{
+		atomic::{ AtomicBool, Ordering },
+		Arc, Mutex
+	},
+	time::Duration
+};
+
+const SAMPLE_RATE: u32 = 44100;
+
+// Turns a source on and off from the emulator's thread. The source outputs silence while it's closed
+#[derive(Clone, Default)]
+pub struct Gate(Arc<AtomicBool>);
+
+impl Gate {
+	pub fn set(&self, open: bool) {
+		self.0.store(open, Ordering::Relaxed);
+	}
+
+	fn is_open(&self) -> bool {
+		self.0.load(Ordering::Relaxed)
+	}
+}
+
+// A never ending square or sine wave, sounding only while its gate is open
+pub struct ToneSource {
+	gate: Gate,
+	waveform: Waveform,
+	step: f32, // how much of a period passes with each sample
+	volume: f32,
+	phase: f32 // how far into the current period the wave is, from 0 to 1
+}
+
+impl ToneSource {
+	pub fn new(config: &AudioConfig, gate: Gate) -> ToneSource {
+		ToneSource {
+			gate,
+			waveform: config.waveform,
+			step: config.frequency / SAMPLE_RATE as f32,
+			volume: config.volume,
+			phase: 0.0
+		}
+	}
+}
+
+impl Iterator for ToneSource {
+	type Item = f32;
+
+	fn next(&mut self) -> Option<f32> {
+		if !self.gate.is_open() {
+			self.phase = 0.0; // start at the beginning of a period when the tone comes back
+			return Some(0.0);
+		}
+
+		let sample = match self.waveform {
+			Waveform::Square => if self.phase < 0.5 { 1.0 } else { -1.0 },
+			Waveform::Sine => (self.phase * 2.0 * PI).sin()
+		};
+		self.phase = (self.phase + self.step) % 1.0;
+
+		Some(sample * self.volume)
+	}
+}
+
+impl Source for ToneSource {
+	fn current_frame_len(&self) -> Option<usize> { None }
+	fn channels(&self) -> u16 { 1 }
+	fn sample_rate(&self) -> u32 { SAMPLE_RATE }
+	fn total_duration(&self) -> Option<Duration> { None }
+}
+
+// A sound file decoded up front and looped for as long as its gate is open. Every time the gate
+// opens the sound starts over
+pub struct SampleSource {
+	gate: Gate,
+	samples: Vec<f32>, // mixed down to one channel
+	sample_rate: u32,
+	position: usize
+}
+
+impl SampleSource {
+	pub fn load(path: &Path, volume: f32, gate: Gate) -> Result<SampleSource, Box<dyn Error>> {
+		let decoder = Decoder::new(BufReader::new(File::open(path)?))?;
+		let channels = decoder.channels() as usize;
+		let sample_rate = decoder.sample_rate();
+
+		let interleaved: Vec<f32> = decoder.convert_samples().collect();
+		let samples: Vec<f32> = interleaved.chunks(channels)
+							.map(|frame| frame.iter().sum::<f32>() / channels as f32 * volume)
+							.collect();
+
+		if samples.is_empty() {
+			return Err(format!("{} has no audio in it", path.display()).into());
+		}
+
+		Ok(SampleSource { gate, samples, sample_rate, position: 0 })
+	}
+}
+
+impl Iterator for SampleSource {
+	type Item = f32;
+
+	fn next(&mut self) -> Option<f32> {
+		if !self.gate.is_open() {
+			self.position = 0;
+			return Some(0.0);
+		}
+
+		let sample = self.samples[self.position];
+		self.position = (self.position + 1) % self.samples.len();
+
+		Some(sample)
+	}
+}
+
+impl Source for SampleSource {
+	fn current_frame_len(&self) -> Option<usize> { None }
+	fn channels(&self) -> u16 { 1 }
+	fn sample_rate(&self) -> u32 { self.sample_rate }
+	fn total_duration(&self) -> Option<Duration> { None }
+}
+
+// What the audio thread needs to know about the machine to play its sound
+#[derive(Clone, Copy)]
+struct PatternState {
+	playing: bool,
+	pattern: [u8; 16],
+	rate: f32 // bits of the pattern played per second
+}
+
+// Kept by the emulator to tell a PatternSource what to play
+pub struct PatternPlayer {
+	state: Arc<Mutex<PatternState>>
+}
+
+impl PatternPlayer {
+	// Creates a player and the never ending source it controls, which should be appended to a sink
+	pub fn new(volume: f32) -> (PatternPlayer, PatternSource) {
+		let state = Arc::new(Mutex::new(PatternState { playing: false, pattern: [0; 16], rate: 4000.0 }));
+		let source = PatternSource { state: state.clone(), volume, position: 0.0 };
+
+		(PatternPlayer { state }, source)
+	}
+
+	pub fn update(&self, playing: bool, pattern: [u8; 16], rate: f32) {
+		*self.state.lock().unwrap() = PatternState { playing, pattern, rate };
+	}
+}
+
+// Loops over XO-CHIP's 128 bit audio pattern, playing set bits as a high sample and unset bits as a
+// low one. Outputs silence while the sound timer isn't running
+pub struct PatternSource {
+	state: Arc<Mutex<PatternState>>,
+	volume: f32,
+	position: f32 // which bit of the pattern is being played, with the fraction of it already played
+}
+
+impl Iterator for PatternSource {
+	type Item = f32;
+
+	fn next(&mut self) -> Option<f32> {
+		let state = *self.state.lock().unwrap();
+		if !state.playing {
+			self.position = 0.0; // start from the beginning of the pattern next time
+			return Some(0.0);
+		}
+
+		let bit = self.position as usize;
+		let set = state.pattern[bit / 8] & (0x80 >> (bit % 8)) != 0;
+		self.position = (self.position + state.rate / SAMPLE_RATE as f32) % 128.0;
+
+		Some(if set { self.volume } else { -self.volume })
+	}
+}
+
+impl Source for PatternSource {
+	fn current_frame_len(&self) -> Option<usize> { None }
+	fn channels(&self) -> u16 { 1 }
+	fn sample_rate(&self) -> u32 { SAMPLE_RATE }
+	fn total_duration(&self) -> Option<Duration> { None }
+}
@@ -0,0 +1,42 @@
+use std::time::{ Duration, Instant };
+
+pub const FRAME_RATE: u32 = 60;
+
+// If the emulator falls further behind than this (the window was dragged, the machine was asleep...)
+// the missed frames are dropped instead of being run all at once
+const MAX_CATCH_UP_FRAMES: u32 = 5;
+
+// Keeps the emulated machine's frames in step with wall-clock time
+pub struct FrameClock {
+	frame_duration: Duration,
+	next_frame: Instant // when the next frame is due
+}
+
+impl FrameClock {
+	pub fn new(now: Instant) -> FrameClock {
+		FrameClock {
+			frame_duration: Duration::from_secs(1) / FRAME_RATE,
+			next_frame: now
+		}
+	}
+
+	// How many frames should be run to catch up with the current time
+	pub fn frames_due(&mut self, now: Instant) -> u32 {
+		let mut frames = 0;
+		while self.next_frame <= now {
+			frames += 1;
+			self.next_frame += self.frame_duration;
+
+			if frames == MAX_CATCH_UP_FRAMES {
+				self.next_frame = now + self.frame_duration;
+				break;
+			}
+		}
+
+		frames
+	}
+
+	pub fn next_frame(&self) -> Instant {
+		self.next_frame
+	}
+}
@@ -0,0 +1,243 @@
+use crate::{ CHIP8, ExecError, Instruction, disasm::{ self, Syntax } };
+
+use std::{
+	collections::VecDeque,
+	fs::{ self, File },
+	io::{ self, BufWriter, Write },
+	path::{ Path, PathBuf }
+};
+
+#[cfg(test)]
+mod tests;
+
+// How many instructions are kept for the dump written when one fails
+pub const DEFAULT_HISTORY_LEN: usize = 1000;
+
+// Logs every instruction the machine executes: the cycle it ran on, its address, opcode and disassembly,
+// and the registers it changed. The last instructions are kept whether they're logged or not, and written
+// out when an instruction fails so there's a record of how the program got there. That's done by
+// run_frame, which knows whether the error halts the machine
+pub struct Tracer {
+	output: Option<Box<dyn Write + Send>>, // where every instruction is logged, if anywhere
+	ranges: Vec<(u16, u16)>, // only instructions at these addresses are logged, all of them when empty
+	history: VecDeque<String>, // the last instructions, logged or not. newest last
+	history_len: usize,
+	dump_path: Option<PathBuf>, // where the history goes when an instruction fails, stderr if nowhere
+	dumped: bool, // the history was written for an error the machine carried on from
+	cycle: u64 // how many instructions the machine has run since tracing started
+}
+
+impl Tracer {
+	// A tracer that only keeps the last instructions, for the dump
+	pub fn new() -> Tracer {
+		Tracer {
+			output: None,
+			ranges: Vec::new(),
+			history: VecDeque::new(),
+			history_len: DEFAULT_HISTORY_LEN,
+			dump_path: None,
+			dumped: false,
+			cycle: 0
+		}
+	}
+
+	// Logs every instruction to a new file at path
+	pub fn to_file<P: AsRef<Path>>(path: P) -> io::Result<Tracer> {
+		let file = File::create(path)?;
+		Ok(Tracer::with_output(Box::new(BufWriter::new(file))))
+	}
+
+	pub fn with_output(output: Box<dyn Write + Send>) -> Tracer {
+		Tracer { output: Some(output), ..Tracer::new() }
+	}
+
+	// Only logs the instructions from start to end, both included. Can be called again to log more ranges
+	pub fn add_range(&mut self, start: u16, end: u16) {
+		self.ranges.push((start.min(end), start.max(end)));
+	}
+
+	pub fn set_history_len(&mut self, len: usize) {
+		self.history_len = len;
+		while self.history.len() > len {
+			self.history.pop_front();
+		}
+	}
+
+	pub fn set_dump_path<P: Into<PathBuf>>(&mut self, path: Option<P>) {
+		self.dump_path = path.map(Into::into);
+	}
+
+	// The last instructions run, oldest first
+	pub fn history(&self) -> impl Iterator<Item = &str> {
+		self.history.iter().map(String::as_str)
+	}
+
+	pub fn cycle(&self) -> u64 {
+		self.cycle
+	}
+
+	// Records an instruction the machine just ran
+	fn executed(&mut self, before: &Snapshot, after: &Snapshot, instruction: Instruction, opcode: u16, long: u16) {
+		let mut line = format_line(self.cycle, before.pc, Some((instruction, opcode, long)));
+		let changes = before.changes(after);
+		if !changes.is_empty() {
+			line.push_str("  ");
+			line.push_str(&changes);
+		}
+		self.record(before.pc, line);
+	}
+
+	// Records an instruction that failed. It's None if it couldn't be fetched
+	fn failed(&mut self, pc: u16, fetched: Option<(Instruction, u16, u16)>, error: &ExecError) {
+		let line = format!("{}  error: {}", format_line(self.cycle, pc, fetched), error);
+		self.record(pc, line);
+	}
+
+	fn record(&mut self, pc: u16, mut line: String) {
+		self.cycle += 1;
+		line.truncate(line.trim_end().len()); // the padding is only there to line up what comes after
+
+		let logged = self.ranges.is_empty() || self.ranges.iter().any(|&(start, end)| (start ..= end).contains(&pc));
+		if let (true, Some(output)) = (logged, self.output.as_mut()) {
+			if let Err(e) = writeln!(output, "{}", line) {
+				eprintln!("Stopped tracing, the trace couldn't be written: {}", e);
+				self.output = None;
+			}
+		}
+
+		if self.history_len > 0 {
+			if self.history.len() == self.history_len {
+				self.history.pop_front();
+			}
+			self.history.push_back(line);
+		}
+	}
+
+	// Writes the history to the dump file, or stderr without one
+	fn dump(&mut self, error: &ExecError) -> io::Result<()> {
+		if let Some(output) = self.output.as_mut() {
+			output.flush()?; // the program might not get to finish the log normally
+		}
+
+		let mut text = format!("The last {} instructions before {}\n", self.history.len(), error);
+		for line in self.history.iter() {
+			text.push_str(line);
+			text.push('\n');
+		}
+
+		self.dumped = true;
+		match self.dump_path.as_ref() {
+			Some(path) => {
+				fs::write(path, text)?;
+				eprintln!("Wrote the last instructions to {}", path.display());
+				Ok(())
+			},
+			None => io::stderr().write_all(text.as_bytes())
+		}
+	}
+}
+
+impl Default for Tracer {
+	fn default() -> Tracer {
+		Tracer::new()
+	}
+}
+
+// The cycle, address, opcode and disassembly of an instruction, padded so whatever follows lines up.
+// long is the word after it, for F000 NNNN
+fn format_line(cycle: u64, pc: u16, fetched: Option<(Instruction, u16, u16)>) -> String {
+	let (opcode, text) = match fetched {
+		Some((instruction, opcode, long)) => (format!("{:04X}", opcode), disasm::format_instruction(instruction, long, Syntax::Octo)),
+		None => ("----".to_string(), String::new())
+	};
+	format!("{:>10}  {:03X}  {}  {:<24}", cycle, pc, opcode, text)
+}
+
+// The registers an instruction can change, to find the ones it did
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) struct Snapshot {
+	pc: u16,
+	v: [u8; 16],
+	i: u16,
+	sp: u16,
+	delay_timer: u8,
+	sound_timer: u8
+}
+
+impl Snapshot {
+	pub(crate) fn of(machine: &CHIP8) -> Snapshot {
+		Snapshot {
+			pc: machine.pc,
+			v: machine.V,
+			i: machine.I,
+			sp: machine.sp,
+			delay_timer: machine.delay_timer,
+			sound_timer: machine.sound_timer
+		}
+	}
+
+	// The registers that differ, like v3 00->05, i 200->300. pc isn't included, the next line shows where it went
+	fn changes(&self, after: &Snapshot) -> String {
+		let mut changes = Vec::new();
+		for (n, (a, b)) in self.v.iter().zip(after.v.iter()).enumerate() {
+			if a != b {
+				changes.push(format!("v{:x} {:02X}->{:02X}", n, a, b));
+			}
+		}
+		if self.i != after.i {
+			changes.push(format!("i {:03X}->{:03X}", self.i, after.i));
+		}
+		if self.sp != after.sp {
+			changes.push(format!("sp {}->{}", self.sp, after.sp));
+		}
+		if self.delay_timer != after.delay_timer {
+			changes.push(format!("delay {:02X}->{:02X}", self.delay_timer, after.delay_timer));
+		}
+		if self.sound_timer != after.sound_timer {
+			changes.push(format!("sound {:02X}->{:02X}", self.sound_timer, after.sound_timer));
+		}
+		changes.join(", ")
+	}
+}
+
+// Tracing
+impl CHIP8 {
+	// Starts logging the instructions the machine runs, or stops with None
+	pub fn set_tracer(&mut self, tracer: Option<Tracer>) {
+		self.tracer = tracer;
+	}
+
+	pub fn tracer(&self) -> Option<&Tracer> {
+		self.tracer.as_ref()
+	}
+
+	pub fn take_tracer(&mut self) -> Option<Tracer> {
+		self.tracer.take()
+	}
+
+	// Writes out the last instructions after one failed. Errors the machine carries on from only do it the
+	// first time, so a program skipping over data doesn't write it for every instruction
+	pub(crate) fn dump_trace(&mut self, error: &ExecError, halted: bool) {
+		if let Some(tracer) = self.tracer.as_mut().filter(|t| halted || !t.dumped) {
+			if let Err(e) = tracer.dump(error) {
+				eprintln!("Couldn't write the last instructions: {}", e);
+			}
+		}
+	}
+
+	// Logs the instruction that was just run from before, or failed
+	pub(crate) fn trace(&mut self, before: &Snapshot, instruction: Option<Instruction>, error: Option<&ExecError>) {
+		let after = Snapshot::of(self);
+		let long = self.memory.get(before.pc as usize + 2 .. before.pc as usize + 4)
+							.map_or(0, |w| (w[0] as u16) << 8 | w[1] as u16);
+		let opcode = self.opcode;
+
+		if let Some(tracer) = self.tracer.as_mut() {
+			match (instruction, error) {
+				(_, Some(e)) => tracer.failed(before.pc, instruction.map(|i| (i, opcode, long)), e),
+				(Some(i), None) => tracer.executed(before, &after, i, opcode, long),
+				(None, None) => ()
+			}
+		}
+	}
+}
@@ -0,0 +1,135 @@
+use super::Tracer;
+use crate::{ CHIP8, ErrorPolicy, ErrorAction, ExecError, asm::assemble };
+
+use std::{
+	fs,
+	io::{ self, Write },
+	sync::{ Arc, Mutex }
+};
+
+// A trace output the test can read back
+#[derive(Clone, Default)]
+struct Log(Arc<Mutex<Vec<u8>>>);
+
+impl Write for Log {
+	fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+		self.0.lock().unwrap().extend_from_slice(buf);
+		Ok(buf.len())
+	}
+
+	fn flush(&mut self) -> io::Result<()> {
+		Ok(())
+	}
+}
+
+impl Log {
+	fn lines(&self) -> Vec<String> {
+		String::from_utf8(self.0.lock().unwrap().clone()).unwrap().lines().map(str::to_string).collect()
+	}
+}
+
+fn machine_with(source: &str) -> CHIP8 {
+	let rom = assemble(source).unwrap().rom;
+	let mut machine = CHIP8::new(&[]).unwrap();
+	machine.memory[0x200 .. 0x200 + rom.len()].copy_from_slice(&rom);
+	machine
+}
+
+#[test]
+fn logs_instructions_with_the_registers_they_change() {
+	let mut machine = machine_with("
+	: main
+		v3 := 5
+		i := 0x300
+		delay := v3
+	: stop
+		jump stop
+	");
+	let log = Log::default();
+	machine.set_tracer(Some(Tracer::with_output(Box::new(log.clone()))));
+
+	machine.run_frame(&ErrorPolicy::default()).unwrap();
+
+	let lines = log.lines();
+	assert_eq!(lines.len(), 10);
+	assert_eq!(lines[0], "         0  200  6305  v3 := 0x05                v3 00->05");
+	assert_eq!(lines[1], "         1  202  A300  i := 0x300                i 000->300");
+	assert_eq!(lines[2], "         2  204  F315  delay := v3               delay 00->05");
+	assert_eq!(lines[3], "         3  206  1206  jump 0x206");
+	assert_eq!(machine.tracer().unwrap().cycle(), 10);
+}
+
+#[test]
+fn only_logs_the_given_ranges_but_remembers_everything() {
+	let mut machine = machine_with("
+	: main
+		v0 := 1
+		v1 := 2
+		v2 := 3
+	: stop
+		jump stop
+	");
+	let log = Log::default();
+	let mut tracer = Tracer::with_output(Box::new(log.clone()));
+	tracer.add_range(0x204, 0x202);
+	tracer.set_history_len(3);
+	machine.set_tracer(Some(tracer));
+
+	for _ in 0 .. 4 {
+		machine.emulate_cycle().unwrap();
+	}
+
+	let lines = log.lines();
+	assert_eq!(lines.len(), 2);
+	assert!(lines[0].contains("202  6102"));
+	assert!(lines[1].contains("204  6203"));
+
+	let tracer = machine.take_tracer().unwrap();
+	let history: Vec<&str> = tracer.history().collect();
+	assert_eq!(history.len(), 3);
+	assert!(history[0].contains("202  6102"));
+	assert!(history[2].contains("206  1206"));
+}
+
+#[test]
+fn failures_dump_the_last_instructions() {
+	let mut machine = machine_with("
+	: main
+		v0 := 1
+		0xFF 0xFF
+	");
+	let dump_path = std::env::temp_dir().join(format!("chip8-trace-dump-{}.txt", std::process::id()));
+	let log = Log::default();
+	let mut tracer = Tracer::with_output(Box::new(log.clone()));
+	tracer.set_dump_path(Some(&dump_path));
+	machine.set_tracer(Some(tracer));
+
+	let error = ExecError::UnknownOpcode { pc: 0x202, opcode: 0xFFFF };
+	let policy = ErrorPolicy { unknown_opcode: ErrorAction::Halt, ..ErrorPolicy::default() };
+	assert_eq!(machine.run_frame(&policy), Err(error));
+
+	let dump = fs::read_to_string(&dump_path).unwrap();
+	fs::remove_file(&dump_path).unwrap();
+	let lines: Vec<&str> = dump.lines().collect();
+	assert_eq!(lines[0], "The last 2 instructions before unknown opcode FFFF at 202");
+	assert!(lines[1].contains("200  6001"));
+	assert!(lines[2].contains("202  FFFF") && lines[2].ends_with("error: unknown opcode FFFF at 202"), "{}", lines[2]);
+	assert_eq!(log.lines().last().map(String::as_str), Some(lines[2]));
+}
+
+#[test]
+fn skipped_errors_only_dump_once() {
+	let mut machine = machine_with(": main 0xFF 0xFF 0xFF 0xFF");
+	let dump_path = std::env::temp_dir().join(format!("chip8-trace-skipped-{}.txt", std::process::id()));
+	let mut tracer = Tracer::new();
+	tracer.set_dump_path(Some(&dump_path));
+	machine.set_tracer(Some(tracer));
+
+	let policy = ErrorPolicy { unknown_opcode: ErrorAction::Ignore, ..ErrorPolicy::default() };
+	machine.run_frame(&policy).unwrap();
+
+	let dump = fs::read_to_string(&dump_path).unwrap();
+	fs::remove_file(&dump_path).unwrap();
+	assert!(dump.starts_with("The last 1 instructions before unknown opcode FFFF at 200\n"), "{}", dump);
+	assert_eq!(machine.tracer().unwrap().history().count(), 10);
+}